@@ -0,0 +1,49 @@
+//! cargo +nightly fuzz run curve_invariants
+//!
+//! Checks the invariants of tests/curve_properties.rs on fuzzer generated pools and trades.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/curve_invariants/mod.rs"]
+mod curve_invariants;
+
+use curve_invariants::*;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    curve_type: u8,
+    reserve_ratio: u32,
+    curve_parameters: [u64; 2],
+    buys: Vec<u64>,
+    fee: u16,
+    provider_fee_bps: u16,
+    a: u64,
+    b: u64,
+    exact_in: bool,
+}
+
+fuzz_target!(|input: Input| {
+    let params = CurveParams {
+        curve_type: input.curve_type % 4,
+        reserve_ratio: input.reserve_ratio,
+        curve_parameters: input.curve_parameters,
+    };
+    let calculator = match params.calculator() {
+        Some(calculator) => calculator,
+        None => return,
+    };
+    let (supply, reserve) = pool_after_buys(&*calculator, &input.buys);
+    let circulating = supply - VIRTUAL_SUPPLY;
+
+    check_buy_monotonic(&*calculator, supply, reserve, input.a, input.b);
+    check_sell_monotonic(&*calculator, supply, reserve, input.a % (circulating + 1), input.b % (circulating + 1));
+    check_buy_sell_round_trip(&*calculator, supply, reserve, input.a);
+    check_exact_out_round_trip(&*calculator, supply, reserve, input.b);
+    check_sell_exact_out(&*calculator, supply, reserve, input.a);
+    check_solvency(&*calculator, supply, reserve);
+    check_buy_fees(&params, input.fee % 50_001, input.provider_fee_bps % 1_001, supply, reserve, input.a, input.exact_in);
+    check_sell_fees(&params, input.provider_fee_bps % 1_001, supply, reserve, input.b, input.exact_in);
+});
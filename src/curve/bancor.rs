@@ -0,0 +1,202 @@
+//! Bancor bonding curve: the reserve is a constant fraction of the market cap
+
+use crate::{
+    curve::{
+        calculator::{CurveCalculator, PRICE_SCALE, TOKEN_PRECISION},
+        math::{ceil_div, power, power_ceil, scaled_log2, exp2, one, to_u64, to_u128, U256, FRACTIONAL_BITS, MAX_EXP2_INTEGER},
+    },
+    error::VisionError,
+};
+
+/// Reserve ratio (connector weight) is expressed in ppm, like Bancor's MAX_WEIGHT.
+pub const MAX_RESERVE_RATIO: u32 = 1_000_000;
+
+/// Smallest reserve ratio a pool can be initialized with (10%)
+pub const MIN_RESERVE_RATIO: u32 = 100_000;
+
+/// Original connector weight of page tokens: 0.60976
+pub const DEFAULT_RESERVE_RATIO: u32 = 609_760;
+
+/// Reserve ratios a pool can be initialized with
+pub fn is_valid_reserve_ratio(reserve_ratio: u32) -> bool {
+    (MIN_RESERVE_RATIO..=MAX_RESERVE_RATIO).contains(&reserve_ratio)
+}
+
+pub struct BancorCurve {
+    /// Connector weight in ppm
+    pub reserve_ratio: u32,
+}
+
+impl CurveCalculator for BancorCurve {
+    fn buy_out_for_in(&self, supply: u64, reserve_balance: u64, amount_in: u64) -> Option<u64> {
+        purchase_target_amount(supply, reserve_balance, self.reserve_ratio, amount_in)
+    }
+
+    fn sell_out_for_in(&self, supply: u64, reserve_balance: u64, amount_in: u64) -> Option<u64> {
+        sale_target_amount(supply, reserve_balance, self.reserve_ratio, amount_in)
+    }
+
+    fn in_for_exact_out(&self, supply: u64, reserve_balance: u64, amount_out: u64) -> Option<u64> {
+        purchase_cost(supply, reserve_balance, self.reserve_ratio, amount_out)
+    }
+
+    fn sell_in_for_exact_out(&self, supply: u64, reserve_balance: u64, amount_out: u64) -> Option<u64> {
+        sale_cost(supply, reserve_balance, self.reserve_ratio, amount_out)
+    }
+
+    /// reserve_balance / (supply * reserve_ratio)
+    fn spot_price(&self, supply: u64, reserve_balance: u64) -> Option<u128> {
+        if supply == 0 || self.reserve_ratio == 0 {
+            return None;
+        }
+        let numerator = U256::from(reserve_balance) * U256::from(MAX_RESERVE_RATIO) * U256::from(TOKEN_PRECISION) * U256::from(PRICE_SCALE);
+        to_u128(numerator / (U256::from(supply) * U256::from(self.reserve_ratio)))
+    }
+
+    fn validate(&self) -> Result<(), VisionError> {
+        if is_valid_reserve_ratio(self.reserve_ratio) {
+            Ok(())
+        } else {
+            Err(VisionError::InvalidReserveRatio)
+        }
+    }
+}
+
+/// Bancor "purchaseTargetAmount": tokens minted for depositing `amount` into the reserve.
+/// supply * ((1 + amount / reserve_balance) ^ (reserve_ratio / MAX_RESERVE_RATIO) - 1)
+///
+/// Every step rounds down, so the result never exceeds the exact value (pool's favor).
+/// Returns None on a zero reserve, an invalid ratio or if the result does not fit in a u64.
+pub fn purchase_target_amount(
+    supply: u64,
+    reserve_balance: u64,
+    reserve_ratio: u32,
+    amount: u64,
+) -> Option<u64> {
+    if reserve_balance == 0 || reserve_ratio == 0 || reserve_ratio > MAX_RESERVE_RATIO {
+        return None;
+    }
+    if amount == 0 {
+        return Some(0);
+    }
+
+    let supply = U256::from(supply);
+    let reserve_balance = U256::from(reserve_balance);
+    let amount = U256::from(amount);
+
+    // Linear curve, no need for the power function
+    if reserve_ratio == MAX_RESERVE_RATIO {
+        return to_u64(supply * amount / reserve_balance);
+    }
+
+    let result = power(amount + reserve_balance, reserve_balance, reserve_ratio as u64, MAX_RESERVE_RATIO as u64)?;
+    let new_supply = (supply * result) >> FRACTIONAL_BITS;
+    to_u64(new_supply.checked_sub(supply)?)
+}
+
+/// Bancor "saleTargetAmount": reserve released for burning `amount` tokens.
+/// reserve_balance * (1 - (1 - amount / supply) ^ (MAX_RESERVE_RATIO / reserve_ratio))
+///
+/// The remaining reserve is rounded up, so the result never exceeds the exact value (pool's favor).
+/// Returns None on an invalid ratio or if `amount` exceeds `supply`.
+pub fn sale_target_amount(
+    supply: u64,
+    reserve_balance: u64,
+    reserve_ratio: u32,
+    amount: u64,
+) -> Option<u64> {
+    if reserve_ratio == 0 || reserve_ratio > MAX_RESERVE_RATIO || amount > supply {
+        return None;
+    }
+    if amount == 0 {
+        return Some(0);
+    }
+    // Selling the entire supply returns the entire reserve
+    if amount == supply {
+        return Some(reserve_balance);
+    }
+
+    // Linear curve, no need for the power function
+    if reserve_ratio == MAX_RESERVE_RATIO {
+        return to_u64(U256::from(reserve_balance) * U256::from(amount) / U256::from(supply));
+    }
+
+    // result = (supply / (supply - amount)) ^ (MAX_RESERVE_RATIO / reserve_ratio)
+    // sale = reserve_balance - reserve_balance / result
+    let exponent = scaled_log2(U256::from(supply), U256::from(supply - amount), MAX_RESERVE_RATIO as u64, reserve_ratio as u64)?;
+    if (exponent >> FRACTIONAL_BITS) >= U256::from(MAX_EXP2_INTEGER) {
+        // reserve_balance / result < 1, only the rounding lamport stays in the pool
+        return Some(reserve_balance.saturating_sub(1));
+    }
+    let result = exp2(exponent)?;
+
+    let remaining = ceil_div(U256::from(reserve_balance) << FRACTIONAL_BITS, result)?;
+    reserve_balance.checked_sub(to_u64(remaining)?)
+}
+
+/// Inverse of `purchase_target_amount`: reserve needed to mint exactly `amount` tokens.
+/// reserve_balance * ((1 + amount / supply) ^ (MAX_RESERVE_RATIO / reserve_ratio) - 1)
+///
+/// Rounded up, so the result is never below the exact value (pool's favor).
+pub fn purchase_cost(
+    supply: u64,
+    reserve_balance: u64,
+    reserve_ratio: u32,
+    amount: u64,
+) -> Option<u64> {
+    if supply == 0 || reserve_ratio == 0 || reserve_ratio > MAX_RESERVE_RATIO {
+        return None;
+    }
+    if amount == 0 {
+        return Some(0);
+    }
+
+    let supply = U256::from(supply);
+    let reserve_balance = U256::from(reserve_balance);
+    let amount = U256::from(amount);
+
+    // Linear curve, no need for the power function
+    if reserve_ratio == MAX_RESERVE_RATIO {
+        return to_u64(ceil_div(reserve_balance * amount, supply)?);
+    }
+
+    let result = power_ceil(supply + amount, supply, MAX_RESERVE_RATIO as u64, reserve_ratio as u64)?;
+    let cost = ceil_div(reserve_balance.checked_mul(result - one())?, one())?;
+    to_u64(cost)
+}
+
+/// Inverse of `sale_target_amount`: tokens to burn to release exactly `amount` from the reserve.
+/// supply * (1 - (1 - amount / reserve_balance) ^ (reserve_ratio / MAX_RESERVE_RATIO))
+///
+/// Rounded up, so the result is never below the exact value (pool's favor).
+pub fn sale_cost(
+    supply: u64,
+    reserve_balance: u64,
+    reserve_ratio: u32,
+    amount: u64,
+) -> Option<u64> {
+    if reserve_ratio == 0 || reserve_ratio > MAX_RESERVE_RATIO || amount > reserve_balance {
+        return None;
+    }
+    if amount == 0 {
+        return Some(0);
+    }
+    // Emptying the reserve burns the entire supply
+    if amount == reserve_balance {
+        return Some(supply);
+    }
+
+    let supply = U256::from(supply);
+    let reserve_balance = U256::from(reserve_balance);
+    let amount = U256::from(amount);
+
+    // Linear curve, no need for the power function
+    if reserve_ratio == MAX_RESERVE_RATIO {
+        return to_u64(ceil_div(supply * amount, reserve_balance)?);
+    }
+
+    // remaining supply = supply / (reserve_balance / (reserve_balance - amount)) ^ (reserve_ratio / MAX_RESERVE_RATIO)
+    let result = power_ceil(reserve_balance, reserve_balance - amount, reserve_ratio as u64, MAX_RESERVE_RATIO as u64)?;
+    let remaining = (supply << FRACTIONAL_BITS) / result;
+    to_u64(supply - remaining)
+}
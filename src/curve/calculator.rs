@@ -0,0 +1,33 @@
+//! Trait implemented by every bonding curve
+
+use crate::error::VisionError;
+
+/// Page tokens are minted with 9 decimals
+pub const TOKEN_PRECISION: u128 = 1_000_000_000;
+
+/// Spot prices are lamports per whole token, scaled by PRICE_SCALE (nanolamports per token)
+pub const PRICE_SCALE: u128 = 1_000_000_000;
+
+/// Bonding curve between the token supply and the lamports held in the reserve.
+///
+/// `supply` is the token supply the curve is evaluated at, `reserve_balance` the lamports
+/// backing it. Every amount is rounded in the pool's favor: outputs down, inputs up.
+pub trait CurveCalculator {
+    /// Tokens minted for depositing `amount_in` lamports into the reserve
+    fn buy_out_for_in(&self, supply: u64, reserve_balance: u64, amount_in: u64) -> Option<u64>;
+
+    /// Lamports released from the reserve for burning `amount_in` tokens
+    fn sell_out_for_in(&self, supply: u64, reserve_balance: u64, amount_in: u64) -> Option<u64>;
+
+    /// Lamports that have to be deposited to mint exactly `amount_out` tokens
+    fn in_for_exact_out(&self, supply: u64, reserve_balance: u64, amount_out: u64) -> Option<u64>;
+
+    /// Tokens that have to be burned to release at least `amount_out` lamports from the reserve
+    fn sell_in_for_exact_out(&self, supply: u64, reserve_balance: u64, amount_out: u64) -> Option<u64>;
+
+    /// Price of one whole token in lamports, scaled by PRICE_SCALE
+    fn spot_price(&self, supply: u64, reserve_balance: u64) -> Option<u128>;
+
+    /// Checks the curve parameters before a pool is created with them
+    fn validate(&self) -> Result<(), VisionError>;
+}
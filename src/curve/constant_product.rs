@@ -0,0 +1,105 @@
+//! Constant product bonding curve on virtual reserves
+
+use crate::{
+    curve::{
+        calculator::{CurveCalculator, PRICE_SCALE, TOKEN_PRECISION},
+        math::{ceil_div, to_u64, to_u128, U256},
+    },
+    error::VisionError,
+};
+
+/// (virtual_sol_reserve + reserve_balance) * (virtual_token_reserve - supply) = k
+///
+/// Tokens can be minted until the supply reaches `virtual_token_reserve`.
+pub struct ConstantProductCurve {
+    /// Lamports added to the reserve balance, sets the starting price
+    pub virtual_sol_reserve: u64,
+    /// Maximum supply of the token, in base units
+    pub virtual_token_reserve: u64,
+}
+
+impl ConstantProductCurve {
+    /// Sol and token side of the pool
+    fn reserves(&self, supply: u64, reserve_balance: u64) -> Option<(U256, U256)> {
+        let sol = U256::from(self.virtual_sol_reserve) + U256::from(reserve_balance);
+        let token = self.virtual_token_reserve.checked_sub(supply)?;
+        Some((sol, U256::from(token)))
+    }
+}
+
+impl CurveCalculator for ConstantProductCurve {
+    /// token * amount_in / (sol + amount_in)
+    fn buy_out_for_in(&self, supply: u64, reserve_balance: u64, amount_in: u64) -> Option<u64> {
+        let (sol, token) = self.reserves(supply, reserve_balance)?;
+        let amount_in = U256::from(amount_in);
+        let denominator = sol + amount_in;
+        if denominator.is_zero() {
+            return None;
+        }
+        to_u64(token * amount_in / denominator)
+    }
+
+    /// sol * amount_in / (token + amount_in)
+    fn sell_out_for_in(&self, supply: u64, reserve_balance: u64, amount_in: u64) -> Option<u64> {
+        let (sol, token) = self.reserves(supply, reserve_balance)?;
+        // The token side is empty once the supply reaches virtual_token_reserve
+        if amount_in == 0 {
+            return Some(0);
+        }
+        let amount_in = U256::from(amount_in);
+        let amount_out = to_u64(sol * amount_in / (token + amount_in))?;
+        // Virtual lamports can't be paid out
+        if amount_out > reserve_balance {
+            return None;
+        }
+        Some(amount_out)
+    }
+
+    /// sol * amount_out / (token - amount_out)
+    fn in_for_exact_out(&self, supply: u64, reserve_balance: u64, amount_out: u64) -> Option<u64> {
+        let (sol, token) = self.reserves(supply, reserve_balance)?;
+        let amount_out = U256::from(amount_out);
+        if amount_out >= token {
+            return None;
+        }
+        to_u64(ceil_div(sol * amount_out, token - amount_out)?)
+    }
+
+    /// token * amount_out / (sol - amount_out)
+    fn sell_in_for_exact_out(&self, supply: u64, reserve_balance: u64, amount_out: u64) -> Option<u64> {
+        // Virtual lamports can't be paid out
+        if amount_out > reserve_balance {
+            return None;
+        }
+        let (sol, token) = self.reserves(supply, reserve_balance)?;
+        if amount_out == 0 {
+            return Some(0);
+        }
+        // Every token is worth the whole sol side once the token side is empty
+        if token.is_zero() {
+            return None;
+        }
+        let amount_out = U256::from(amount_out);
+        let amount_in = to_u64(ceil_div(token * amount_out, sol - amount_out)?)?;
+        if amount_in > supply {
+            return None;
+        }
+        Some(amount_in)
+    }
+
+    /// sol / token
+    fn spot_price(&self, supply: u64, reserve_balance: u64) -> Option<u128> {
+        let (sol, token) = self.reserves(supply, reserve_balance)?;
+        if token.is_zero() {
+            return None;
+        }
+        to_u128(sol * U256::from(TOKEN_PRECISION) * U256::from(PRICE_SCALE) / token)
+    }
+
+    fn validate(&self) -> Result<(), VisionError> {
+        if self.virtual_sol_reserve == 0 || self.virtual_token_reserve == 0 {
+            return Err(VisionError::InvalidCurve);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Exponential bonding curve: the price doubles every `doubling_supply` tokens minted
+
+use crate::{
+    curve::{
+        calculator::{CurveCalculator, PRICE_SCALE, TOKEN_PRECISION},
+        math::{ceil_div, exp2, exp2_ceil, scaled_log2, one, to_u64, to_u128, U256, FRACTIONAL_BITS, LN2, MAX_EXP2_INTEGER},
+    },
+    error::VisionError,
+};
+
+/// reserve(supply) = reserve_0 * 2 ^ ((supply - supply_0) / doubling_supply)
+///
+/// The price is reserve_balance * ln(2) / doubling_supply, so the reserve grows
+/// at the same rate as the price.
+pub struct ExponentialCurve {
+    /// Supply (in token base units) that has to be minted for the price to double
+    pub doubling_supply: u64,
+}
+
+impl ExponentialCurve {
+    /// amount / doubling_supply as a fixed-point number, rounded down
+    fn exponent(&self, amount: u64) -> U256 {
+        (U256::from(amount) << FRACTIONAL_BITS) / U256::from(self.doubling_supply)
+    }
+}
+
+impl CurveCalculator for ExponentialCurve {
+    /// doubling_supply * log2(1 + amount_in / reserve_balance)
+    fn buy_out_for_in(&self, _supply: u64, reserve_balance: u64, amount_in: u64) -> Option<u64> {
+        let reserve_balance = U256::from(reserve_balance);
+        let log = scaled_log2(reserve_balance + U256::from(amount_in), reserve_balance, self.doubling_supply, 1)?;
+        to_u64(log >> FRACTIONAL_BITS)
+    }
+
+    /// reserve_balance * (1 - 2 ^ (-amount_in / doubling_supply))
+    fn sell_out_for_in(&self, supply: u64, reserve_balance: u64, amount_in: u64) -> Option<u64> {
+        if amount_in > supply || self.doubling_supply == 0 {
+            return None;
+        }
+        let exponent = self.exponent(amount_in);
+        if (exponent >> FRACTIONAL_BITS) >= U256::from(MAX_EXP2_INTEGER) {
+            // The remaining reserve is below one lamport
+            return Some(reserve_balance.saturating_sub(1));
+        }
+        let remaining = ceil_div(U256::from(reserve_balance) << FRACTIONAL_BITS, exp2(exponent)?)?;
+        reserve_balance.checked_sub(to_u64(remaining)?)
+    }
+
+    /// reserve_balance * (2 ^ (amount_out / doubling_supply) - 1)
+    fn in_for_exact_out(&self, _supply: u64, reserve_balance: u64, amount_out: u64) -> Option<u64> {
+        if self.doubling_supply == 0 {
+            return None;
+        }
+        let exponent = ceil_div(U256::from(amount_out) << FRACTIONAL_BITS, U256::from(self.doubling_supply))?;
+        let growth = exp2_ceil(exponent)? - one();
+        to_u64(ceil_div(U256::from(reserve_balance).checked_mul(growth)?, one())?)
+    }
+
+    /// doubling_supply * log2(reserve_balance / (reserve_balance - amount_out))
+    fn sell_in_for_exact_out(&self, supply: u64, reserve_balance: u64, amount_out: u64) -> Option<u64> {
+        if amount_out >= reserve_balance {
+            return None;
+        }
+        if amount_out == 0 {
+            return Some(0);
+        }
+        let reserve_balance = U256::from(reserve_balance);
+        let log = scaled_log2(reserve_balance, reserve_balance - U256::from(amount_out), self.doubling_supply, 1)?;
+        // log2 is rounded down, one more token covers its truncation error
+        let amount_in = to_u64(log >> FRACTIONAL_BITS)?.checked_add(1)?;
+        if amount_in > supply {
+            return None;
+        }
+        Some(amount_in)
+    }
+
+    fn spot_price(&self, _supply: u64, reserve_balance: u64) -> Option<u128> {
+        if self.doubling_supply == 0 {
+            return None;
+        }
+        let numerator = U256::from(reserve_balance) * LN2 * U256::from(TOKEN_PRECISION) * U256::from(PRICE_SCALE);
+        to_u128((numerator >> FRACTIONAL_BITS) / U256::from(self.doubling_supply))
+    }
+
+    fn validate(&self) -> Result<(), VisionError> {
+        if self.doubling_supply == 0 {
+            return Err(VisionError::InvalidCurve);
+        }
+        Ok(())
+    }
+}
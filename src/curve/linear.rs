@@ -0,0 +1,103 @@
+//! Linear bonding curve: the price grows by a fixed slope with every token minted
+
+use crate::{
+    curve::{
+        calculator::{CurveCalculator, PRICE_SCALE, TOKEN_PRECISION},
+        math::{ceil_div, to_u64, to_u128, U256},
+    },
+    error::VisionError,
+};
+
+/// price(supply) = initial_price + slope * supply, in lamports per whole token.
+///
+/// The curve only depends on the supply, the reserve balance is not used to price trades.
+pub struct LinearCurve {
+    /// Price of a whole token at supply 0, in lamports
+    pub initial_price: u64,
+    /// Price increase per whole token minted, in lamports
+    pub slope: u64,
+}
+
+impl LinearCurve {
+    /// initial_price * 10^9 + slope * supply -> the price scaled by 10^9
+    fn scaled_price(&self, supply: u64) -> Option<U256> {
+        (U256::from(self.initial_price) * U256::from(TOKEN_PRECISION))
+            .checked_add(U256::from(self.slope) * U256::from(supply))
+    }
+
+    /// Lamports locked under the curve between `supply` and `supply + amount`, times 2 * 10^18:
+    /// 2 * scaled_price(supply) * amount + slope * amount^2
+    fn scaled_area(&self, supply: u64, amount: u64) -> Option<U256> {
+        let amount = U256::from(amount);
+        (self.scaled_price(supply)? * amount * U256::from(2))
+            .checked_add(U256::from(self.slope).checked_mul(amount * amount)?)
+    }
+
+    fn area_denominator() -> U256 {
+        U256::from(TOKEN_PRECISION) * U256::from(TOKEN_PRECISION) * U256::from(2)
+    }
+}
+
+impl CurveCalculator for LinearCurve {
+    /// Largest amount whose area is below amount_in:
+    /// (sqrt(price^2 + 2 * 10^18 * slope * amount_in) - price) / slope
+    fn buy_out_for_in(&self, supply: u64, _reserve_balance: u64, amount_in: u64) -> Option<u64> {
+        let price = self.scaled_price(supply)?;
+        // amount_in * 10^18, same scale as price * token base units
+        let scaled_amount_in = U256::from(amount_in) * U256::from(TOKEN_PRECISION) * U256::from(TOKEN_PRECISION);
+        if self.slope == 0 {
+            if price.is_zero() {
+                return None;
+            }
+            return to_u64(scaled_amount_in / price);
+        }
+        let discriminant = price
+            .checked_mul(price)?
+            .checked_add((scaled_amount_in * U256::from(2)).checked_mul(U256::from(self.slope))?)?;
+        to_u64((discriminant.integer_sqrt() - price) / U256::from(self.slope))
+    }
+
+    fn sell_out_for_in(&self, supply: u64, _reserve_balance: u64, amount_in: u64) -> Option<u64> {
+        let area = self.scaled_area(supply.checked_sub(amount_in)?, amount_in)?;
+        to_u64(area / Self::area_denominator())
+    }
+
+    fn in_for_exact_out(&self, supply: u64, _reserve_balance: u64, amount_out: u64) -> Option<u64> {
+        let area = self.scaled_area(supply, amount_out)?;
+        to_u64(ceil_div(area, Self::area_denominator())?)
+    }
+
+    /// Smallest amount whose area below supply covers amount_out:
+    /// (price - sqrt(price^2 - 2 * 10^18 * slope * amount_out)) / slope
+    fn sell_in_for_exact_out(&self, supply: u64, _reserve_balance: u64, amount_out: u64) -> Option<u64> {
+        let price = self.scaled_price(supply)?;
+        // amount_out * 10^18, same scale as price * token base units
+        let scaled_amount_out = U256::from(amount_out) * U256::from(TOKEN_PRECISION) * U256::from(TOKEN_PRECISION);
+        let amount_in = if self.slope == 0 {
+            ceil_div(scaled_amount_out, price)?
+        } else {
+            let discriminant = price
+                .checked_mul(price)?
+                .checked_sub((scaled_amount_out * U256::from(2)).checked_mul(U256::from(self.slope))?)?;
+            ceil_div(price - discriminant.integer_sqrt(), U256::from(self.slope))?
+        };
+        let amount_in = to_u64(amount_in)?;
+        if amount_in > supply {
+            return None;
+        }
+        Some(amount_in)
+    }
+
+    fn spot_price(&self, supply: u64, _reserve_balance: u64) -> Option<u128> {
+        // scaled_price is already scaled by 10^9 == PRICE_SCALE
+        to_u128(self.scaled_price(supply)? * U256::from(PRICE_SCALE) / U256::from(TOKEN_PRECISION))
+    }
+
+    fn validate(&self) -> Result<(), VisionError> {
+        // A curve that never costs anything would mint unlimited tokens
+        if self.initial_price == 0 && self.slope == 0 {
+            return Err(VisionError::InvalidCurve);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Deterministic fixed-point helpers shared by the curves
+
+// Code generated by construct_uint!
+#![allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+
+use uint::construct_uint;
+
+construct_uint! {
+    pub struct U256(4);
+}
+
+/// Number of fractional bits of the fixed-point numbers used by `power`.
+pub const FRACTIONAL_BITS: usize = 96;
+
+/// Largest integer part of a log2 that `exp2` accepts: 2^128 * 2^96 still fits in U256.
+pub const MAX_EXP2_INTEGER: u64 = 128;
+
+/// The relative truncation error of `power` and `exp2` is far below 2^-64,
+/// upper bounds are the rounded down result plus this margin.
+const ROUND_UP_MARGIN_BITS: usize = 64;
+
+/// ln(2) * 2^96, rounded down
+pub const LN2: U256 = U256([15118436252839555992, 2977044471, 0, 0]);
+
+/// 1 as a fixed-point number
+pub fn one() -> U256 {
+    U256::one() << FRACTIONAL_BITS
+}
+
+/// (base_n / base_d) ^ (exp_n / exp_d) as a fixed-point number, rounded down.
+/// base_n must be greater or equal to base_d.
+pub fn power(base_n: U256, base_d: U256, exp_n: u64, exp_d: u64) -> Option<U256> {
+    exp2(scaled_log2(base_n, base_d, exp_n, exp_d)?)
+}
+
+/// (base_n / base_d) ^ (exp_n / exp_d) as a fixed-point number, rounded up.
+pub fn power_ceil(base_n: U256, base_d: U256, exp_n: u64, exp_d: u64) -> Option<U256> {
+    round_up(power(base_n, base_d, exp_n, exp_d)?)
+}
+
+/// log2(base_n / base_d) * exp_n / exp_d as a fixed-point number, rounded down.
+pub fn scaled_log2(base_n: U256, base_d: U256, exp_n: u64, exp_d: u64) -> Option<U256> {
+    if base_d.is_zero() || base_n < base_d || exp_d == 0 {
+        return None;
+    }
+    let base = (base_n << FRACTIONAL_BITS) / base_d;
+    log2(base).checked_mul(U256::from(exp_n)).map(|log| log / U256::from(exp_d))
+}
+
+/// log2 of a fixed-point number >= 1, rounded down.
+/// The fractional part is computed one bit at a time by repeated squaring.
+pub fn log2(x: U256) -> U256 {
+    let one = one();
+    let two = one << 1;
+
+    // Integer part
+    let integer = x.bits() - 1 - FRACTIONAL_BITS;
+    let mut y = x >> integer;
+    let mut result = U256::from(integer) << FRACTIONAL_BITS;
+
+    // Fractional part, y is in [1, 2)
+    for bit in (0..FRACTIONAL_BITS).rev() {
+        y = (y * y) >> FRACTIONAL_BITS;
+        if y >= two {
+            y >>= 1;
+            result |= U256::one() << bit;
+        }
+    }
+    result
+}
+
+/// 2^x for a fixed-point number, rounded down.
+/// The fractional part is computed as e^(frac * ln2) with its Taylor series, every term truncated.
+pub fn exp2(x: U256) -> Option<U256> {
+    let one = one();
+    let integer = x >> FRACTIONAL_BITS;
+    if integer >= U256::from(MAX_EXP2_INTEGER) {
+        return None;
+    }
+    let z = ((x & (one - 1)) * LN2) >> FRACTIONAL_BITS;
+
+    let mut result = one;
+    let mut term = one;
+    let mut i = 1u64;
+    while !term.is_zero() {
+        term = (term * z) / (U256::from(i) << FRACTIONAL_BITS);
+        result += term;
+        i += 1;
+    }
+    Some(result << integer.as_usize())
+}
+
+/// 2^x for a fixed-point number, rounded up.
+pub fn exp2_ceil(x: U256) -> Option<U256> {
+    round_up(exp2(x)?)
+}
+
+fn round_up(value: U256) -> Option<U256> {
+    value.checked_add((value >> ROUND_UP_MARGIN_BITS) + 1)
+}
+
+pub fn ceil_div(numerator: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let quotient = numerator / denominator;
+    if (quotient * denominator) == numerator {
+        Some(quotient)
+    } else {
+        Some(quotient + 1)
+    }
+}
+
+pub fn to_u64(value: U256) -> Option<u64> {
+    if value > U256::from(u64::MAX) {
+        None
+    } else {
+        Some(value.as_u64())
+    }
+}
+
+pub fn to_u128(value: U256) -> Option<u128> {
+    if value > U256::from(u128::MAX) {
+        None
+    } else {
+        Some(value.as_u128())
+    }
+}
@@ -0,0 +1,70 @@
+//! Bonding curves in deterministic fixed-point
+
+pub mod bancor;
+pub mod calculator;
+pub mod constant_product;
+pub mod exponential;
+pub mod linear;
+pub mod math;
+
+use crate::{
+    curve::{
+        bancor::BancorCurve,
+        calculator::CurveCalculator,
+        constant_product::ConstantProductCurve,
+        exponential::ExponentialCurve,
+        linear::LinearCurve,
+    },
+    error::VisionError,
+};
+
+/// Curve discriminator stored in `PageTokenSwap::curve_type`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum CurveType {
+    /// Bancor formula, uses `reserve_ratio`
+    Bancor = 0,
+    /// curve_parameters: [initial_price, slope]
+    Linear = 1,
+    /// curve_parameters: [doubling_supply, -]
+    Exponential = 2,
+    /// curve_parameters: [virtual_sol_reserve, virtual_token_reserve]
+    ConstantProduct = 3,
+}
+
+impl CurveType {
+    pub fn from_u8(curve_type: u8) -> Option<Self> {
+        match curve_type {
+            0 => Some(CurveType::Bancor),
+            1 => Some(CurveType::Linear),
+            2 => Some(CurveType::Exponential),
+            3 => Some(CurveType::ConstantProduct),
+            _ => None,
+        }
+    }
+}
+
+/// Builds the calculator of a pool from the curve fields saved in `PageTokenSwap`
+pub fn curve_calculator(
+    curve_type: u8,
+    reserve_ratio: u32,
+    curve_parameters: &[u64; 2],
+) -> Result<Box<dyn CurveCalculator>, VisionError> {
+    let [parameter_a, parameter_b] = *curve_parameters;
+    Ok(match CurveType::from_u8(curve_type).ok_or(VisionError::InvalidCurve)? {
+        CurveType::Bancor => Box::new(BancorCurve {
+            reserve_ratio,
+        }),
+        CurveType::Linear => Box::new(LinearCurve {
+            initial_price: parameter_a,
+            slope: parameter_b,
+        }),
+        CurveType::Exponential => Box::new(ExponentialCurve {
+            doubling_supply: parameter_a,
+        }),
+        CurveType::ConstantProduct => Box::new(ConstantProductCurve {
+            virtual_sol_reserve: parameter_a,
+            virtual_token_reserve: parameter_b,
+        }),
+    })
+}
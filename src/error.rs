@@ -5,47 +5,101 @@ use solana_program::{decode_error::DecodeError, program_error::ProgramError};
 use thiserror::Error;
 
 /// Errors that may be returned by the Token program.
+/// Discriminants are the `ProgramError::Custom` codes seen by clients, never change or reuse them.
 #[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
 pub enum VisionError {
     // Submitted Transaction is missing a signature
     #[error("Signature missing")]
-    SignatureRequired,
+    SignatureRequired = 0,
 
     /// The account cannot be initialized because it is already being used.
     #[error("Keypair already in use")]
-    AlreadyInUse,
+    AlreadyInUse = 1,
 
     /// Invalid Account Info Provided
     #[error("Invalid Account Address Provided")]
-    InvalidAccountAddress,
+    InvalidAccountAddress = 2,
 
     /// Invalid Program id -> e.g. System Program != Systemprogram::ID, Tokenprogram,...
     #[error("Invalid program id")]
-    InvalidProgramAddress,
+    InvalidProgramAddress = 3,
 
     /// Invalid Account Owner - Program ID
-    #[error("Invalid program id")]
-    InvalidAccountOnwerProgram,
+    #[error("Invalid account owner program")]
+    InvalidAccountOnwerProgram = 4,
 
     /// Swap instruction exceeds desired slippage limit
     #[error("Swap instruction exceeds desired slippage limit")]
-    ExceededSlippage,
+    ExceededSlippage = 5,
 
-    /// Swap instruction exceeds desired slippage limit
+    /// Mint is not initialized or not owned by the pool
     #[error("Invalid Mint")]
-    InvalidMint,
+    InvalidMint = 6,
 
     /// Invalid User Input
     #[error("Invalid Input")]
-    InvalidInput,
+    InvalidInput = 7,
 
-    /// Invalid User Input
+    /// Sell would take the reserve below its minimum collateral
     #[error("Reserve Error")]
-    ReserveError,
+    ReserveError = 8,
 
     /// Balance too Small
     #[error("Balance too small")]
-    BalanceTooSmall,
+    BalanceTooSmall = 9,
+
+    /// Reserve ratio outside of the allowed bounds
+    #[error("Invalid reserve ratio")]
+    InvalidReserveRatio = 10,
+
+    /// Unknown curve type or invalid curve parameters
+    #[error("Invalid curve")]
+    InvalidCurve = 11,
+
+    /// Instruction data could not be unpacked
+    #[error("Invalid instruction")]
+    InvalidInstruction = 12,
+
+    /// Arithmetic overflow or underflow, also returned when the curve can't price a trade
+    #[error("Overflow")]
+    Overflow = 13,
+
+    /// Page fee outside of 0 - 50000 (0-50%)
+    #[error("Invalid fee")]
+    InvalidFee = 14,
+
+    /// Deprecated, no longer returned: fee changes are signed by the pool authority, see `InvalidAuthority`.
+    /// Kept so the codes of the following variants stay the same.
+    #[error("Invalid fee account")]
+    InvalidFeeAccount = 15,
+
+    /// Config values out of bounds or config account not initialized
+    #[error("Invalid config")]
+    InvalidConfig = 16,
+
+    /// Trading is paused
+    #[error("Trading paused")]
+    Paused = 17,
+
+    /// Signer is not the admin of the program config
+    #[error("Invalid admin")]
+    InvalidAdmin = 18,
+
+    /// Trade submitted after its deadline
+    #[error("Deadline exceeded")]
+    DeadlineExceeded = 19,
+
+    /// Signer is not the pool authority, or not its pending authority when accepting
+    #[error("Invalid authority")]
+    InvalidAuthority = 20,
+
+    /// ChangeFee moves the fee by more than `PageTokenSwap::MAX_FEE_STEP`
+    #[error("Fee step exceeded")]
+    FeeStepExceeded = 21,
+
+    /// The pool has the baseline layout that can't store the change, MigratePool upgrades it
+    #[error("Outdated pool layout")]
+    OutdatedPoolLayout = 22,
 }
 impl From<VisionError> for ProgramError {
     fn from(e: VisionError) -> Self {
@@ -57,3 +111,44 @@ impl<T> DecodeError<T> for VisionError {
         "VisionError"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn error_codes_are_stable() {
+        let table = [
+            (VisionError::SignatureRequired, 0),
+            (VisionError::AlreadyInUse, 1),
+            (VisionError::InvalidAccountAddress, 2),
+            (VisionError::InvalidProgramAddress, 3),
+            (VisionError::InvalidAccountOnwerProgram, 4),
+            (VisionError::ExceededSlippage, 5),
+            (VisionError::InvalidMint, 6),
+            (VisionError::InvalidInput, 7),
+            (VisionError::ReserveError, 8),
+            (VisionError::BalanceTooSmall, 9),
+            (VisionError::InvalidReserveRatio, 10),
+            (VisionError::InvalidCurve, 11),
+            (VisionError::InvalidInstruction, 12),
+            (VisionError::Overflow, 13),
+            (VisionError::InvalidFee, 14),
+            (VisionError::InvalidFeeAccount, 15),
+            (VisionError::InvalidConfig, 16),
+            (VisionError::Paused, 17),
+            (VisionError::InvalidAdmin, 18),
+            (VisionError::DeadlineExceeded, 19),
+            (VisionError::InvalidAuthority, 20),
+            (VisionError::FeeStepExceeded, 21),
+            (VisionError::OutdatedPoolLayout, 22),
+        ];
+        for (error, code) in table.iter() {
+            assert_eq!(ProgramError::from(error.clone()), ProgramError::Custom(*code));
+            assert_eq!(VisionError::from_u32(*code), Some(error.clone()));
+        }
+        // Every variant is in the table
+        assert_eq!(VisionError::from_u32(table.len() as u32), None);
+    }
+}
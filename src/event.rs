@@ -0,0 +1,325 @@
+//! Events logged by the program with sol_log_data, one per successful instruction
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use crate::state::PauseState;
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+/// Prefix of sol_log_data lines in the transaction logs
+pub const LOG_PREFIX: &str = "Program data: ";
+
+/// Logged data: EVENT_VERSION followed by the Borsh encoded VisionEvent.
+/// Bumped whenever an existing event changes, new variants are only ever appended. The layouts of
+/// earlier versions stay in `legacy` so historical logs keep decoding.
+pub const EVENT_VERSION: u8 = 3;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct InitializeEvent {
+    pub mint: Pubkey,
+    pub payer: Pubkey,
+    pub fee_collector: Pubkey,
+    /// Page fee (x/100000)
+    pub fee: u16,
+    pub curve_type: u8,
+    pub reserve_ratio: u32,
+    pub curve_parameters: [u64; 2],
+    /// Lamports backing the virtual supply, rent excluded
+    pub reserve_balance: u64,
+    /// curve::calculator::CurveCalculator::spot_price, 0 if the curve can't price the pool
+    pub spot_price: u128,
+    /// PageTokenSwap::reserve_mint, Pubkey::default() for native SOL
+    pub reserve_mint: Pubkey,
+}
+
+/// Buy: amount_in in lamports, amount_out in tokens. Sell: amount_in in tokens, amount_out in lamports.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct TradeEvent {
+    pub mint: Pubkey,
+    pub trader: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub page_fee: u64,
+    pub provider_fee: u64,
+    /// Mint supply after the trade, without the virtual supply
+    pub supply: u64,
+    /// Reserve balance after the trade, rent excluded
+    pub reserve_balance: u64,
+    /// Spot price after the trade, 0 if the curve can't price the pool
+    pub spot_price: u128,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ChangeFeeEvent {
+    pub mint: Pubkey,
+    pub fee_collector: Pubkey,
+    /// Page fee (x/100000) in effect
+    pub fee: u16,
+    /// Queued page fee, applies from pending_fee_slot on
+    pub pending_fee: u16,
+    /// 0 if no change is queued
+    pub pending_fee_slot: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct PauseStateEvent {
+    pub mint: Pubkey,
+    pub pause_state: PauseState,
+}
+
+/// Logged on both steps, the transfer is done once pending_authority is Pubkey::default()
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct AuthorityEvent {
+    pub mint: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum VisionEvent {
+    Initialize(InitializeEvent),
+    Buy(TradeEvent),
+    Sell(TradeEvent),
+    ChangeFee(ChangeFeeEvent),
+    SetPauseState(PauseStateEvent),
+    SetAuthority(AuthorityEvent),
+}
+
+impl VisionEvent {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![EVENT_VERSION];
+        data.extend(self.try_to_vec().expect("serializing into a Vec can't fail"));
+        data
+    }
+
+    /// Events of earlier versions are converted, fields they lack get their default.
+    /// None if the data is not an event of a known version.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        match data.split_first()? {
+            (1, event) => legacy::VisionEventV1::try_from_slice(event).ok().map(Self::from),
+            (2, event) => legacy::VisionEventV2::try_from_slice(event).ok().map(Self::from),
+            (&EVENT_VERSION, event) => Self::try_from_slice(event).ok(),
+            _ => None,
+        }
+    }
+
+    /// Parses a "Program data: <base64>" line of the transaction logs
+    pub fn from_log(log: &str) -> Option<Self> {
+        let data = base64::decode(log.strip_prefix(LOG_PREFIX)?).ok()?;
+        Self::decode(&data)
+    }
+
+    pub fn emit(&self) {
+        sol_log_data(&[&self.encode()]);
+    }
+}
+
+/// Layouts of earlier event versions, only ever decoded
+pub mod legacy {
+    use super::*;
+
+    /// InitializeEvent before reserve_mint, versions 1 and 2
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+    pub struct InitializeEventV1 {
+        pub mint: Pubkey,
+        pub payer: Pubkey,
+        pub fee_collector: Pubkey,
+        pub fee: u16,
+        pub curve_type: u8,
+        pub reserve_ratio: u32,
+        pub curve_parameters: [u64; 2],
+        pub reserve_balance: u64,
+        pub spot_price: u128,
+    }
+
+    /// ChangeFeeEvent before fee changes were queued, version 1
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+    pub struct ChangeFeeEventV1 {
+        pub mint: Pubkey,
+        pub fee_collector: Pubkey,
+        pub fee: u16,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+    pub enum VisionEventV1 {
+        Initialize(InitializeEventV1),
+        Buy(TradeEvent),
+        Sell(TradeEvent),
+        ChangeFee(ChangeFeeEventV1),
+        SetPauseState(PauseStateEvent),
+        SetAuthority(AuthorityEvent),
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+    pub enum VisionEventV2 {
+        Initialize(InitializeEventV1),
+        Buy(TradeEvent),
+        Sell(TradeEvent),
+        ChangeFee(ChangeFeeEvent),
+        SetPauseState(PauseStateEvent),
+        SetAuthority(AuthorityEvent),
+    }
+
+    impl From<InitializeEventV1> for InitializeEvent {
+        fn from(event: InitializeEventV1) -> Self {
+            let InitializeEventV1 { mint, payer, fee_collector, fee, curve_type, reserve_ratio, curve_parameters, reserve_balance, spot_price } = event;
+            // Every pool had a native SOL reserve
+            InitializeEvent { mint, payer, fee_collector, fee, curve_type, reserve_ratio, curve_parameters, reserve_balance, spot_price, reserve_mint: Pubkey::default() }
+        }
+    }
+
+    impl From<ChangeFeeEventV1> for ChangeFeeEvent {
+        fn from(event: ChangeFeeEventV1) -> Self {
+            // The fee applied at once, nothing was queued
+            ChangeFeeEvent { mint: event.mint, fee_collector: event.fee_collector, fee: event.fee, pending_fee: event.fee, pending_fee_slot: 0 }
+        }
+    }
+
+    impl From<VisionEventV1> for VisionEventV2 {
+        fn from(event: VisionEventV1) -> Self {
+            match event {
+                VisionEventV1::Initialize(event) => VisionEventV2::Initialize(event),
+                VisionEventV1::Buy(event) => VisionEventV2::Buy(event),
+                VisionEventV1::Sell(event) => VisionEventV2::Sell(event),
+                VisionEventV1::ChangeFee(event) => VisionEventV2::ChangeFee(event.into()),
+                VisionEventV1::SetPauseState(event) => VisionEventV2::SetPauseState(event),
+                VisionEventV1::SetAuthority(event) => VisionEventV2::SetAuthority(event),
+            }
+        }
+    }
+
+    impl From<VisionEventV1> for VisionEvent {
+        fn from(event: VisionEventV1) -> Self {
+            VisionEventV2::from(event).into()
+        }
+    }
+
+    impl From<VisionEventV2> for VisionEvent {
+        fn from(event: VisionEventV2) -> Self {
+            match event {
+                VisionEventV2::Initialize(event) => VisionEvent::Initialize(event.into()),
+                VisionEventV2::Buy(event) => VisionEvent::Buy(event),
+                VisionEventV2::Sell(event) => VisionEvent::Sell(event),
+                VisionEventV2::ChangeFee(event) => VisionEvent::ChangeFee(event),
+                VisionEventV2::SetPauseState(event) => VisionEvent::SetPauseState(event),
+                VisionEventV2::SetAuthority(event) => VisionEvent::SetAuthority(event),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade() -> TradeEvent {
+        TradeEvent {
+            mint: Pubkey::new_unique(),
+            trader: Pubkey::new_unique(),
+            amount_in: 1_000_000_000,
+            amount_out: 42,
+            page_fee: 25_000_000,
+            provider_fee: 10_000_000,
+            supply: 42,
+            reserve_balance: 965_000_036,
+            spot_price: u128::MAX,
+        }
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let events = vec![
+            VisionEvent::Initialize(InitializeEvent {
+                mint: Pubkey::new_unique(),
+                payer: Pubkey::new_unique(),
+                fee_collector: Pubkey::new_unique(),
+                fee: 2500,
+                curve_type: 3,
+                reserve_ratio: 609_760,
+                curve_parameters: [1, 2],
+                reserve_balance: 36,
+                spot_price: 7,
+                reserve_mint: Pubkey::new_unique(),
+            }),
+            VisionEvent::Buy(trade()),
+            VisionEvent::Sell(trade()),
+            VisionEvent::ChangeFee(ChangeFeeEvent { mint: Pubkey::new_unique(), fee_collector: Pubkey::new_unique(), fee: 0, pending_fee: 1, pending_fee_slot: 2 }),
+            VisionEvent::SetPauseState(PauseStateEvent { mint: Pubkey::new_unique(), pause_state: PauseState::WithdrawOnly }),
+            VisionEvent::SetAuthority(AuthorityEvent { mint: Pubkey::new_unique(), authority: Pubkey::new_unique(), pending_authority: Pubkey::default() }),
+        ];
+        for (tag, event) in events.into_iter().enumerate() {
+            let data = event.encode();
+            assert_eq!(data[0], EVENT_VERSION);
+            assert_eq!(data[1], tag as u8);
+            assert_eq!(VisionEvent::decode(&data), Some(event));
+        }
+    }
+
+    #[test]
+    fn from_log() {
+        let event = VisionEvent::Buy(trade());
+        let log = format!("{}{}", LOG_PREFIX, base64::encode(event.encode()));
+        assert_eq!(VisionEvent::from_log(&log), Some(event));
+
+        assert_eq!(VisionEvent::from_log("Program log: Checking insturctions"), None);
+        assert_eq!(VisionEvent::from_log("Program data: not base64!"), None);
+    }
+
+    #[test]
+    fn decode_rejects_other_versions() {
+        let mut data = VisionEvent::Buy(trade()).encode();
+        data[0] = EVENT_VERSION + 1;
+        assert_eq!(VisionEvent::decode(&data), None);
+        data[0] = 0;
+        assert_eq!(VisionEvent::decode(&data), None);
+        assert_eq!(VisionEvent::decode(&[]), None);
+    }
+
+    #[test]
+    fn decodes_earlier_versions() {
+        let mint = Pubkey::new_unique();
+        let fee_collector = Pubkey::new_unique();
+        let initialize = legacy::InitializeEventV1 {
+            mint,
+            payer: Pubkey::new_unique(),
+            fee_collector,
+            fee: 2500,
+            curve_type: 0,
+            reserve_ratio: 609_760,
+            curve_parameters: [0, 0],
+            reserve_balance: 36,
+            spot_price: 7,
+        };
+
+        let mut data = vec![1];
+        data.extend(legacy::VisionEventV1::Initialize(initialize.clone()).try_to_vec().unwrap());
+        match VisionEvent::decode(&data) {
+            Some(VisionEvent::Initialize(event)) => {
+                assert_eq!((event.mint, event.fee, event.spot_price), (mint, 2500, 7));
+                assert_eq!(event.reserve_mint, Pubkey::default());
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+
+        let mut data = vec![1];
+        data.extend(legacy::VisionEventV1::ChangeFee(legacy::ChangeFeeEventV1 { mint, fee_collector, fee: 100 }).try_to_vec().unwrap());
+        assert_eq!(
+            VisionEvent::decode(&data),
+            Some(VisionEvent::ChangeFee(ChangeFeeEvent { mint, fee_collector, fee: 100, pending_fee: 100, pending_fee_slot: 0 }))
+        );
+
+        // Version 2 queued fee changes, Initialize had no reserve_mint yet
+        let change_fee = ChangeFeeEvent { mint, fee_collector, fee: 100, pending_fee: 200, pending_fee_slot: 300 };
+        let mut data = vec![2];
+        data.extend(legacy::VisionEventV2::ChangeFee(change_fee.clone()).try_to_vec().unwrap());
+        assert_eq!(VisionEvent::decode(&data), Some(VisionEvent::ChangeFee(change_fee)));
+        let mut data = vec![2];
+        data.extend(legacy::VisionEventV2::Initialize(initialize.clone()).try_to_vec().unwrap());
+        assert_eq!(VisionEvent::decode(&data), Some(VisionEvent::Initialize(initialize.into())));
+
+        // Trades never changed
+        let mut data = VisionEvent::Sell(trade()).encode();
+        for version in 1..EVENT_VERSION {
+            data[0] = version;
+            assert!(matches!(VisionEvent::decode(&data), Some(VisionEvent::Sell(_))));
+        }
+    }
+}
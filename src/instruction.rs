@@ -1,11 +1,21 @@
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
+    bpf_loader_upgradeable,
+    instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
     pubkey::Pubkey,
+    system_program,
+    sysvar,
     msg
 };
-use std::convert::TryInto;
-use crate::error::VisionError;
+use crate::{
+    error::VisionError,
+    metadata,
+    state::{ObservationBuffer, PauseState, ProgramConfig},
+    token,
+};
 
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub struct Amount {
     /// SOURCE amount to transfer, output to DESTINATION is based on the exchange rate
     pub amount_in: u64,
@@ -13,110 +23,1195 @@ pub struct Amount {
     pub minimum_amount_out: u64,
 }
 
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ExactTokenOut {
+    /// Exact amount of tokens to receive
+    pub token_amount_out: u64,
+    /// Maximum amount of lamports to spend, fees included. Prevents excessive slippage
+    pub maximum_amount_in: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ExactLamportsOut {
+    /// Exact amount of lamports to receive, after the provider fee
+    pub lamports_out: u64,
+    /// Maximum amount of tokens to burn. Prevents excessive slippage
+    pub maximum_tokens_in: u64,
+}
+
+/// Last slot or unix timestamp (Clock sysvar) a trade can execute at
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Deadline {
+    Slot(u64),
+    UnixTimestamp(i64),
+}
+
+/// Two-step transfer of the pool authority
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AuthorityStep {
+    /// Signed by the authority, new_authority_info becomes the pending authority
+    Propose,
+    /// Signed by the pending authority, which becomes the authority
+    Accept,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct QuoteRequest {
+    /// 0 -> Buy, 1 -> Sell
+    pub side: TradeSide,
+    /// Lamports in (buy) / tokens in (sell) if exact_in, tokens out (buy) / lamports out (sell) otherwise
+    pub amount: u64,
+    /// Quote Buy/Sell if true, BuyExactOut/SellExactOut otherwise
+    pub exact_in: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ViewRequest {
+    /// Side of the hypothetical trade priced in state::PoolView::price_after
+    pub side: TradeSide,
+    /// Lamports deposited into the reserve (buy) / tokens burned (sell), fees excluded
+    pub amount: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ObservationCapacity {
+    /// Samples the buffer has room for, up to state::ObservationBuffer::MAX_CAPACITY
+    pub capacity: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Provider fee in basis points, up to 1000 (10%)
+    pub provider_fee_bps: u16,
+    /// Bounds of the page fee (x/100000), max_page_fee up to 50000 (50%)
+    pub min_page_fee: u16,
+    pub max_page_fee: u16,
+    /// Trading state of every pool, encoded like the former `paused: bool` for Active and Paused
+    pub pause_state: PauseState,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub struct Fee {
     /// Fee from 0 - 50000.(0-50%). Will be divided by 100000 to create a precison of 0.001%
     pub fee: u16,
 }
 
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Curve {
+    /// Bancor connector weight in ppm. From curve::bancor::MIN_RESERVE_RATIO (10%) to curve::bancor::MAX_RESERVE_RATIO (100%)
+    pub reserve_ratio: u32,
+    /// curve::CurveType discriminator
+    pub curve_type: u8,
+    /// Parameters of non Bancor curves, see curve::CurveType
+    pub curve_parameters: [u64; 2],
+}
+
+/// Metaplex metadata of the page token. At most metadata::MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH and
+/// MAX_URI_LENGTH bytes.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+/// Token-2022 transfer fee of a page token, withheld fees are withdrawn to the fee collector by WithdrawWithheldFees
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct TransferFee {
+    /// At most 10000
+    pub basis_points: u16,
+    pub maximum_fee: u64,
+}
+
+/// Token-2022 extensions of a page token mint, neither can be changed after Initialize
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct MintExtensions {
+    /// Account holding the token metadata
+    pub metadata_pointer: Option<Pubkey>,
+    pub transfer_fee: Option<TransferFee>,
+}
+
+/// Optional parts of a page token created by Initialize, any of them can be combined
+#[derive(BorshSerialize, Clone, Debug, Default, PartialEq)]
+pub struct InitializeOptions {
+    /// Creates the Metaplex metadata of the mint with the pda as update authority
+    pub metadata: Option<TokenMetadata>,
+    /// Creates a Token-2022 mint with these extensions
+    pub extensions: Option<MintExtensions>,
+    /// Mint of a reserve of tokens instead of lamports
+    pub reserve_mint: Option<Pubkey>,
+}
+
+/// Initialize of clients from before the options ends after the curve, it decodes without options
+impl BorshDeserialize for InitializeOptions {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        if buf.is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(Self {
+            metadata: BorshDeserialize::deserialize(buf)?,
+            extensions: BorshDeserialize::deserialize(buf)?,
+            reserve_mint: BorshDeserialize::deserialize(buf)?,
+        })
+    }
+}
+
+/// Token accounts of a pool with a reserve mint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReserveAccounts {
+    pub reserve_mint: Pubkey,
+    /// Token program of the reserve mint, independent of the page token's
+    pub reserve_token_program: Pubkey,
+    /// Token account of the payer or seller. Pays the collateral of Initialize and buys, receives sells.
+    pub trader_reserve: Pubkey,
+}
+
+/// Accounts of a trade, `Quote` or `View` that depend on the pool, and the deadline of a trade. The default
+/// fits a pool of an spl-token mint with a SOL reserve and no ObservationBuffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TradeOptions {
+    /// Token program of the page token, the trader's associated token account is the one of this program
+    pub token_program: Pubkey,
+    /// Pools with a reserve mint only
+    pub reserve: Option<ReserveAccounts>,
+    /// The pool's current ObservationBuffer, required once InitializeObservations created it
+    pub observations: Option<Pubkey>,
+    /// Makes the trade its variant with a deadline, not supported by Quote
+    pub deadline: Option<Deadline>,
+}
+
+impl Default for TradeOptions {
+    fn default() -> Self {
+        Self { token_program: spl_token::id(), reserve: None, observations: None, deadline: None }
+    }
+}
+
+impl TradeOptions {
+    fn check(&self) -> Result<(), ProgramError> {
+        let reserve_token_program = self.reserve.map_or(spl_token::id(), |reserve| reserve.reserve_token_program);
+        if !token::is_token_program(&self.token_program) || !token::is_token_program(&reserve_token_program) {
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+        Ok(())
+    }
+
+    /// pda_associated_sol_info of the pool, its reserve token account with a reserve mint
+    fn reserve_address(&self, program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+        match &self.reserve {
+            Some(reserve) => find_reserve_address(program_id, mint, &reserve.reserve_mint, &reserve.reserve_token_program),
+            None => find_pool_sol_address(program_id, &find_pool_address(program_id, mint).0).0,
+        }
+    }
+
+    /// Accounts after the ones every trade has, `fee_collectors` receive their fees in reserve tokens
+    fn trailing_accounts(&self, fee_collectors: &[&Pubkey]) -> Vec<AccountMeta> {
+        let mut accounts = Vec::new();
+        if let Some(reserve) = &self.reserve {
+            accounts.push(AccountMeta::new(reserve.trader_reserve, false));
+            for fee_collector in fee_collectors {
+                let fee_collector_reserve = token::get_associated_token_address(fee_collector, &reserve.reserve_mint, &reserve.reserve_token_program);
+                accounts.push(AccountMeta::new(fee_collector_reserve, false));
+            }
+            accounts.push(AccountMeta::new_readonly(reserve.reserve_mint, false));
+            accounts.push(AccountMeta::new_readonly(reserve.reserve_token_program, false));
+        }
+        if let Some(observations) = self.observations {
+            accounts.push(AccountMeta::new(observations, false));
+        }
+        accounts
+    }
+}
+
+/// Encoded with Borsh, the tag is the index of the variant.
+/// Tags are part of the wire format: new variants are only ever appended at the end, existing variants
+/// are never reordered or removed. Their payload only grows by optional trailing fields whose empty
+/// encoding is the old payload, like `InitializeOptions` (see its `BorshDeserialize`).
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub enum VisionInstruction {
-    /// [signer, writable] payer -> Funding token creation.
-    /// [signer, writable] mint -> Keypair of Mint
-    /// [writable] pda -> Program Derived Address(with mint pubkey) for AMM. Holding AMM infos. [is_initialized, bump_seed of pda, bump seed pda_sol, fee (x/100000), fee_collector_pubkey]
+    /// Creates the pool of a new page token. Data: the Curve, followed by the InitializeOptions. Data
+    /// of clients from before the options ends after the Curve and creates a pool without options.
+    /// [signer, writable] payer -> Funding token creation, pays the collateral: 36 lamports and the rent of pda_sol, or 36 reserve tokens
+    /// [signer, writable] mint -> Keypair of Mint, created with 9 decimals and pda as mint authority
+    /// [writable] pda -> Program Derived Address(with mint pubkey) for AMM, created holding the PageTokenSwap.
+    ///     Sets [is_initialized, version, bump_seed of pda, bump seed pda_sol, fee (default page fee within the
+    ///     ProgramConfig bounds), fee_collector_pubkey, authority, reserve_ratio, curve_type, curve_parameters,
+    ///     reserve_mint] and starts the price oracle
     /// [writable] pda_sol -> Program derived address(with pda) holding sol/collateral.
+    /// [x] fee_collector_info -> Fee collector and pool authority saved in pda
     /// [x] system_program_info
-    /// [x] associated_token_program_info
-    /// [x] token_program_info
+    /// [x] token_program_info -> spl-token or Token-2022, owner of the mint
     /// [x] rent_sysvar_info
-    /// [x] fee_collector_info -> Pub key of Fee collector saved in pda
-    Initialize(),
+    /// [x] config_info -> ProgramConfig PDA, holds the page fee bounds
+    /// options.metadata only:
+    /// [writable] metadata_info -> metadata::find_metadata_address of the mint
+    /// [x] metadata_program_info
+    /// options.reserve_mint only, pda_sol stays empty and the collateral is paid in reserve tokens:
+    /// [x] reserve_mint_info -> Without a transfer fee
+    /// [writable] reserve_info -> Associated token account of pda for the reserve mint, created
+    /// [writable] payer_reserve_info -> Token account of the payer paying the collateral
+    /// [x] associated_token_program_info
+    /// [x] reserve_token_program_info -> Token program of the reserve mint, independent of token_program_info
+    /// token_program_info has to be Token-2022 with options.extensions.
+    Initialize(Curve, InitializeOptions),
     /// [signer, writable] payer -> Buyer spending sol and getting token.
     /// [writable] payer_associated_token_address_info
+    /// [writable] pda_info -> Trade statistics are updated
+    /// [writable] pda_associated_sol_info -> The reserve token account if the pool has a reserve mint
+    /// [writable] mint_info
     /// [writable] page_fee_collector_info
     /// [writable] provider_fee_collector_info
-    /// [writable] pda_info
-    /// [writable] pda_associated_sol_info
-    /// [writable] mint_info
     /// [x] system_program_info
-    /// [x] associated_token_program_info
     /// [x] token_program_info
+    /// [x] config_info -> ProgramConfig PDA, holds the provider fee and provider fee collector
+    /// [x] associated_token_program_info -> Creates payer_associated_token_address_info if empty
     /// [x] rent_sysvar_info
+    /// Reserve mint only, see TradeOptions::reserve:
+    /// [writable] payer_reserve_info -> Token account of the payer paying amount_in
+    /// [writable] page_fee_collector_reserve_info -> Associated token account of the page fee collector
+    /// [writable] provider_fee_collector_reserve_info -> Associated token account of the provider fee collector
+    /// [x] reserve_mint_info
+    /// [x] reserve_token_program_info -> Token program of the reserve mint
+    /// [writable] observations_info -> The pool's ObservationBuffer records a sample. Optional until
+    ///     InitializeObservations created it, see `PageTokenSwap::has_observations`
     Buy(Amount),
     /// [signer, writable] seller_info -> Seller, spending token and getting sol.
     /// [writable] seller_associated_token_address_info
-    /// [writable] provider_fee_collector_info
-    /// [writable] pda_info
-    /// [writable] pda_associated_sol_info
+    /// [writable] pda_info -> Trade statistics are updated
+    /// [writable] pda_associated_sol_info -> The reserve token account if the pool has a reserve mint
     /// [writable] mint_info
+    /// [writable] provider_fee_collector_info
     /// [x] system_program_info
     /// [x] token_program_info
+    /// [x] config_info -> ProgramConfig PDA, holds the provider fee and provider fee collector
+    /// Reserve mint only, see TradeOptions::reserve:
+    /// [writable] seller_reserve_info -> Token account receiving the amount out
+    /// [writable] provider_fee_collector_reserve_info -> Associated token account of the provider fee collector
+    /// [x] reserve_mint_info
+    /// [x] reserve_token_program_info -> Token program of the reserve mint
+    /// [writable] observations_info -> The pool's ObservationBuffer records a sample. Optional until
+    ///     InitializeObservations created it, see `PageTokenSwap::has_observations`
     Sell(Amount),
-    /// [signer, writable] fee_collector_info -> Current Fee collector saved in pda.
+    /// Sets the fee collector and queues the fee, see state::PageTokenSwap::queue_fee
+    /// [signer, writable] authority_info -> Pool authority saved in pda, funds a new fee collector
     /// [writable] new_fee_collector_info
     /// [writable] pda_info
     /// [x] mint_info
     /// [x] system_program_info
-    ChangeFee(Fee)
+    /// [x] config_info -> ProgramConfig PDA, holds the page fee bounds
+    ChangeFee(Fee),
+    /// Same accounts as Buy.
+    /// Mints exactly token_amount_out, the lamports needed are grossed up by the page and provider fee.
+    BuyExactOut(ExactTokenOut),
+    /// Same accounts as Sell.
+    /// Pays out exactly lamports_out, the tokens burned cover lamports_out plus the provider fee.
+    SellExactOut(ExactLamportsOut),
+    /// Same accounts as Buy or Sell, depending on side. Nothing is minted, burned or transferred.
+    /// Returns a packed state::Quote via return data.
+    Quote(QuoteRequest),
+    /// Creates the ProgramConfig, the payer becomes its admin. Has to be called once right after deployment
+    /// by the upgrade authority of the program.
+    /// [signer, writable] payer -> Admin of the config, the program's upgrade authority
+    /// [writable] config_info -> PDA(["config"])
+    /// [x] provider_fee_collector_info
+    /// [x] system_program_info
+    /// [x] program_data_info -> ProgramData account of the program, see `find_program_data_address`
+    InitializeConfig(Config),
+    /// [signer] admin_info -> Current admin saved in the config
+    /// [writable] config_info
+    /// [x] new_admin_info
+    /// [x] new_provider_fee_collector_info
+    UpdateConfig(Config),
+    /// Read-only. Returns a packed state::PoolView via return data.
+    /// [x] pda_info
+    /// [x] pda_associated_sol_info -> The reserve token account if the pool has a reserve mint
+    /// [x] mint_info
+    View(ViewRequest),
+    /// Creates the ObservationBuffer of a pool. Buy and Sell then have to pass it as their last account
+    /// and write a sample into it.
+    /// [signer, writable] payer -> Pays the rent of the buffer
+    /// [writable] pda_info
+    /// [writable] observations_info -> PDA(["observations", pda, [generation]])
+    /// [x] mint_info
+    /// [x] system_program_info
+    /// [signer] authority_info -> Pool authority saved in pda
+    InitializeObservations(ObservationCapacity),
+    /// Moves the samples into a larger buffer at the PDA of the next generation and closes the current one.
+    /// [signer, writable] payer -> Pays the rent of the new buffer
+    /// [writable] pda_info
+    /// [writable] observations_info -> Current buffer
+    /// [writable] new_observations_info -> PDA(["observations", pda, [generation + 1]]), the generation wraps around
+    /// [x] mint_info
+    /// [x] system_program_info
+    /// [signer, writable] authority_info -> Pool authority saved in pda, receives the rent of the current buffer
+    GrowObservations(ObservationCapacity),
+    /// Buy, rejected after the deadline. Same accounts as Buy.
+    BuyWithDeadline(Amount, Deadline),
+    /// Sell, rejected after the deadline. Same accounts as Sell.
+    SellWithDeadline(Amount, Deadline),
+    /// BuyExactOut, rejected after the deadline. Same accounts as Buy.
+    BuyExactOutWithDeadline(ExactTokenOut, Deadline),
+    /// SellExactOut, rejected after the deadline. Same accounts as Sell.
+    SellExactOutWithDeadline(ExactLamportsOut, Deadline),
+    /// Pauses a pool or allows only sells, see state::PauseState
+    /// [signer] authority_info -> Pool authority saved in pda
+    /// [writable] pda_info
+    /// [x] mint_info
+    SetPauseState(PauseState),
+    /// Proposes a new pool authority or accepts the proposal
+    /// [signer] signer_info -> Authority to propose, pending authority to accept
+    /// [writable] pda_info
+    /// [x] mint_info
+    /// [x] new_authority_info -> Propose only, Pubkey::default() cancels a proposal
+    SetAuthority(AuthorityStep),
+    /// Replaces the name, symbol and uri of the mint's Metaplex metadata
+    /// [signer] authority_info -> Pool authority saved in pda
+    /// [x] pda_info -> Update authority of the metadata
+    /// [x] mint_info
+    /// [writable] metadata_info
+    /// [x] metadata_program_info
+    UpdateMetadata(TokenMetadata),
+    /// Withdraws the Token-2022 transfer fees withheld in the mint and in the source token accounts to
+    /// the current fee collector. The pda is the withdraw withheld authority of the mint, anyone can call it.
+    /// [x] pda_info
+    /// [writable] mint_info -> Token-2022 mint with a transfer fee
+    /// [writable] fee_collector_token_info -> Associated token account of the fee collector saved in pda, has to exist
+    /// [x] token_program_info -> Token-2022
+    /// [writable] source_infos -> Any number of token accounts of the mint with withheld fees
+    WithdrawWithheldFees,
+    /// Grows a pool of the baseline layout to `PageTokenSwap::LEN` and writes it in the current layout.
+    /// Baseline pools trade as before but can't store ChangeFee, pause, authority or observation changes
+    /// until migrated. Anyone can call it, the fields keep their values.
+    /// [signer, writable] payer -> Pays the rent of the larger account
+    /// [writable] pda_info
+    /// [x] mint_info
+    /// [x] system_program_info
+    MigratePool,
 }
 
 impl VisionInstruction {
+    /// Borsh encoding: the variant index as a u8 tag followed by the fields in little endian.
+    /// Trailing bytes are rejected.
     pub fn unpack(instruction_data: &[u8]) -> Result<Self, ProgramError>{
-        let (&tag, rest) = instruction_data.split_first().ok_or(VisionError::InvalidInstruction)?;
         msg!("Checking insturctions");
-        Ok(match tag {
-            0 => {
-                Self::Initialize()
-            }
-            1 => {
-                let (amount_in, rest) = Self::unpack_u64(rest)?;
-                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
-                Self::Buy(Amount { 
-                    amount_in,
-                    minimum_amount_out 
-                })
-            }
-            2 => {
-                let (amount_in, rest) = Self::unpack_u64(rest)?;
-                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
-                Self::Sell(Amount { 
-                    amount_in,
-                    minimum_amount_out 
-                })
-            }
-            3 => {
-                let (fee, _rest) = Self::unpack_u16(rest)?;
-                Self::ChangeFee(Fee { 
-                    fee
-                })
-            }
-            _ => return Err(VisionError::InvalidInstruction.into()),
-        })
+        Self::try_from_slice(instruction_data).map_err(|_| VisionError::InvalidInstruction.into())
     }
 
-    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
-        if input.len() >= 8 {
-            let (amount, rest) = input.split_at(8);
-            let amount = amount
-                .get(..8)
-                .and_then(|slice| slice.try_into().ok())
-                .map(u64::from_le_bytes)
-                .ok_or(VisionError::InvalidInstruction)?;
-            Ok((amount, rest))
-        } else {
-            Err(VisionError::InvalidInstruction.into())
+    pub fn pack(&self) -> Vec<u8> {
+        self.try_to_vec().expect("serializing into a Vec can't fail")
+    }
+}
+
+/// Pool PDA of a mint, holding the PageTokenSwap state
+pub fn find_pool_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[&mint.to_bytes()], program_id)
+}
+
+/// PDA of a pool holding its sol reserve
+pub fn find_pool_sol_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[&pool.to_bytes()], program_id)
+}
+
+/// PDA of a pool's ObservationBuffer, `generation` is PageTokenSwap::observations_generation
+pub fn find_observations_address(program_id: &Pubkey, pool: &Pubkey, generation: u8) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ObservationBuffer::SEED, &pool.to_bytes(), &[generation]], program_id)
+}
+
+/// Singleton ProgramConfig PDA
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ProgramConfig::SEED], program_id)
+}
+
+/// Creates an `Initialize` instruction of an spl-token page token without options. `mint` is a new
+/// keypair that has to sign.
+pub fn initialize(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    fee_collector: &Pubkey,
+    reserve_ratio: u32,
+    curve_type: u8,
+    curve_parameters: [u64; 2],
+) -> Instruction {
+    let curve = Curve { reserve_ratio, curve_type, curve_parameters };
+    initialize_instruction(program_id, payer, mint, fee_collector, curve, &spl_token::id(), InitializeOptions::default(), None)
+}
+
+/// Creates an `Initialize` instruction with any combination of options, `reserve` pays the collateral.
+/// Extensions need Token-2022 as `token_program`.
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_with_options(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    fee_collector: &Pubkey,
+    curve: Curve,
+    token_program: &Pubkey,
+    metadata: Option<TokenMetadata>,
+    extensions: Option<MintExtensions>,
+    reserve: Option<ReserveAccounts>,
+) -> Result<Instruction, ProgramError> {
+    if !token::is_token_program(token_program) || (extensions.is_some() && *token_program != token::token_2022::id()) {
+        return Err(VisionError::InvalidProgramAddress.into());
+    }
+    let options = InitializeOptions { metadata, extensions, reserve_mint: reserve.map(|reserve| reserve.reserve_mint) };
+    Ok(initialize_instruction(program_id, payer, mint, fee_collector, curve, token_program, options, reserve))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn initialize_instruction(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    fee_collector: &Pubkey,
+    curve: Curve,
+    token_program: &Pubkey,
+    options: InitializeOptions,
+    reserve: Option<ReserveAccounts>,
+) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    let (pool_sol, _) = find_pool_sol_address(program_id, &pool);
+    let (config, _) = find_config_address(program_id);
+    let mut accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*mint, true),
+        AccountMeta::new(pool, false),
+        AccountMeta::new(pool_sol, false),
+        AccountMeta::new_readonly(*fee_collector, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(config, false),
+    ];
+    if options.metadata.is_some() {
+        accounts.push(AccountMeta::new(metadata::find_metadata_address(mint).0, false));
+        accounts.push(AccountMeta::new_readonly(metadata::id(), false));
+    }
+    if let Some(reserve) = &reserve {
+        accounts.push(AccountMeta::new_readonly(reserve.reserve_mint, false));
+        accounts.push(AccountMeta::new(find_reserve_address(program_id, mint, &reserve.reserve_mint, &reserve.reserve_token_program), false));
+        accounts.push(AccountMeta::new(reserve.trader_reserve, false));
+        accounts.push(AccountMeta::new_readonly(spl_associated_token_account::id(), false));
+        accounts.push(AccountMeta::new_readonly(reserve.reserve_token_program, false));
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: VisionInstruction::Initialize(curve, options).pack(),
+    }
+}
+
+fn buy_accounts(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    page_fee_collector: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    options: &TradeOptions,
+) -> Vec<AccountMeta> {
+    let (pool, _) = find_pool_address(program_id, mint);
+    let (config, _) = find_config_address(program_id);
+    let mut accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(token::get_associated_token_address(payer, mint, &options.token_program), false),
+        AccountMeta::new(pool, false),
+        AccountMeta::new(options.reserve_address(program_id, mint), false),
+        AccountMeta::new(*mint, false),
+        AccountMeta::new(*page_fee_collector, false),
+        AccountMeta::new(*provider_fee_collector, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(options.token_program, false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    accounts.extend(options.trailing_accounts(&[page_fee_collector, provider_fee_collector]));
+    accounts
+}
+
+fn sell_accounts(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    mint: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    options: &TradeOptions,
+) -> Vec<AccountMeta> {
+    let (pool, _) = find_pool_address(program_id, mint);
+    let (config, _) = find_config_address(program_id);
+    let mut accounts = vec![
+        AccountMeta::new(*seller, true),
+        AccountMeta::new(token::get_associated_token_address(seller, mint, &options.token_program), false),
+        AccountMeta::new(pool, false),
+        AccountMeta::new(options.reserve_address(program_id, mint), false),
+        AccountMeta::new(*mint, false),
+        AccountMeta::new(*provider_fee_collector, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(options.token_program, false),
+        AccountMeta::new_readonly(config, false),
+    ];
+    accounts.extend(options.trailing_accounts(&[provider_fee_collector]));
+    accounts
+}
+
+/// Creates a `Buy` instruction. The payer's associated token account is created on its first buy.
+/// `provider_fee_collector` is the one saved in the ProgramConfig.
+pub fn buy(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    page_fee_collector: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: buy_accounts(program_id, payer, mint, page_fee_collector, provider_fee_collector, &TradeOptions::default()),
+        data: VisionInstruction::Buy(Amount { amount_in, minimum_amount_out }).pack(),
+    }
+}
+
+/// Creates a `BuyExactOut` instruction, same accounts as `buy`
+pub fn buy_exact_out(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    page_fee_collector: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    token_amount_out: u64,
+    maximum_amount_in: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: buy_accounts(program_id, payer, mint, page_fee_collector, provider_fee_collector, &TradeOptions::default()),
+        data: VisionInstruction::BuyExactOut(ExactTokenOut { token_amount_out, maximum_amount_in }).pack(),
+    }
+}
+
+/// Creates a `Sell` instruction.
+/// `provider_fee_collector` is the one saved in the ProgramConfig.
+pub fn sell(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    mint: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: sell_accounts(program_id, seller, mint, provider_fee_collector, &TradeOptions::default()),
+        data: VisionInstruction::Sell(Amount { amount_in, minimum_amount_out }).pack(),
+    }
+}
+
+/// Creates a `SellExactOut` instruction, same accounts as `sell`
+pub fn sell_exact_out(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    mint: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    lamports_out: u64,
+    maximum_tokens_in: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: sell_accounts(program_id, seller, mint, provider_fee_collector, &TradeOptions::default()),
+        data: VisionInstruction::SellExactOut(ExactLamportsOut { lamports_out, maximum_tokens_in }).pack(),
+    }
+}
+
+/// Creates a `Quote` instruction. `page_fee_collector` is ignored for sell quotes.
+#[allow(clippy::too_many_arguments)]
+pub fn quote(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    page_fee_collector: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    side: TradeSide,
+    amount: u64,
+    exact_in: bool,
+) -> Instruction {
+    let options = TradeOptions::default();
+    let accounts = match side {
+        TradeSide::Buy => buy_accounts(program_id, payer, mint, page_fee_collector, provider_fee_collector, &options),
+        TradeSide::Sell => sell_accounts(program_id, payer, mint, provider_fee_collector, &options),
+    };
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: VisionInstruction::Quote(QuoteRequest { side, amount, exact_in }).pack(),
+    }
+}
+
+/// Creates a `Buy` instruction, or `BuyWithDeadline` with a deadline
+#[allow(clippy::too_many_arguments)]
+pub fn buy_with_options(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    page_fee_collector: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    options: &TradeOptions,
+) -> Result<Instruction, ProgramError> {
+    options.check()?;
+    let amount = Amount { amount_in, minimum_amount_out };
+    let data = match options.deadline {
+        Some(deadline) => VisionInstruction::BuyWithDeadline(amount, deadline),
+        None => VisionInstruction::Buy(amount),
+    };
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: buy_accounts(program_id, payer, mint, page_fee_collector, provider_fee_collector, options),
+        data: data.pack(),
+    })
+}
+
+/// Creates a `BuyExactOut` instruction, or `BuyExactOutWithDeadline` with a deadline
+#[allow(clippy::too_many_arguments)]
+pub fn buy_exact_out_with_options(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    page_fee_collector: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    token_amount_out: u64,
+    maximum_amount_in: u64,
+    options: &TradeOptions,
+) -> Result<Instruction, ProgramError> {
+    options.check()?;
+    let amount = ExactTokenOut { token_amount_out, maximum_amount_in };
+    let data = match options.deadline {
+        Some(deadline) => VisionInstruction::BuyExactOutWithDeadline(amount, deadline),
+        None => VisionInstruction::BuyExactOut(amount),
+    };
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: buy_accounts(program_id, payer, mint, page_fee_collector, provider_fee_collector, options),
+        data: data.pack(),
+    })
+}
+
+/// Creates a `Sell` instruction, or `SellWithDeadline` with a deadline
+pub fn sell_with_options(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    mint: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    options: &TradeOptions,
+) -> Result<Instruction, ProgramError> {
+    options.check()?;
+    let amount = Amount { amount_in, minimum_amount_out };
+    let data = match options.deadline {
+        Some(deadline) => VisionInstruction::SellWithDeadline(amount, deadline),
+        None => VisionInstruction::Sell(amount),
+    };
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: sell_accounts(program_id, seller, mint, provider_fee_collector, options),
+        data: data.pack(),
+    })
+}
+
+/// Creates a `SellExactOut` instruction, or `SellExactOutWithDeadline` with a deadline
+pub fn sell_exact_out_with_options(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    mint: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    lamports_out: u64,
+    maximum_tokens_in: u64,
+    options: &TradeOptions,
+) -> Result<Instruction, ProgramError> {
+    options.check()?;
+    let amount = ExactLamportsOut { lamports_out, maximum_tokens_in };
+    let data = match options.deadline {
+        Some(deadline) => VisionInstruction::SellExactOutWithDeadline(amount, deadline),
+        None => VisionInstruction::SellExactOut(amount),
+    };
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: sell_accounts(program_id, seller, mint, provider_fee_collector, options),
+        data: data.pack(),
+    })
+}
+
+/// Creates a `Quote` instruction, `page_fee_collector` is ignored for sell quotes. Quotes have no deadline.
+#[allow(clippy::too_many_arguments)]
+pub fn quote_with_options(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    page_fee_collector: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    side: TradeSide,
+    amount: u64,
+    exact_in: bool,
+    options: &TradeOptions,
+) -> Result<Instruction, ProgramError> {
+    options.check()?;
+    if options.deadline.is_some() {
+        return Err(VisionError::InvalidInstruction.into());
+    }
+    let accounts = match side {
+        TradeSide::Buy => buy_accounts(program_id, payer, mint, page_fee_collector, provider_fee_collector, options),
+        TradeSide::Sell => sell_accounts(program_id, payer, mint, provider_fee_collector, options),
+    };
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: VisionInstruction::Quote(QuoteRequest { side, amount, exact_in }).pack(),
+    })
+}
+
+/// Creates a `ChangeFee` instruction. Pass the current fee collector as `new_fee_collector` to keep it.
+pub fn change_fee(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    new_fee_collector: &Pubkey,
+    mint: &Pubkey,
+    fee: u16,
+) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    let (config, _) = find_config_address(program_id);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*new_fee_collector, false),
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(config, false),
+        ],
+        data: VisionInstruction::ChangeFee(Fee { fee }).pack(),
+    }
+}
+
+/// Creates a `SetPauseState` instruction
+pub fn set_pause_state(program_id: &Pubkey, authority: &Pubkey, mint: &Pubkey, pause_state: PauseState) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(*mint, false),
+        ],
+        data: VisionInstruction::SetPauseState(pause_state).pack(),
+    }
+}
+
+/// Creates a `SetAuthority` instruction proposing `new_authority`
+pub fn propose_authority(program_id: &Pubkey, authority: &Pubkey, mint: &Pubkey, new_authority: &Pubkey) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*new_authority, false),
+        ],
+        data: VisionInstruction::SetAuthority(AuthorityStep::Propose).pack(),
+    }
+}
+
+/// Creates a `SetAuthority` instruction accepting the authority proposed to `pending_authority`
+pub fn accept_authority(program_id: &Pubkey, pending_authority: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*pending_authority, true),
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(*mint, false),
+        ],
+        data: VisionInstruction::SetAuthority(AuthorityStep::Accept).pack(),
+    }
+}
+
+/// Address of the ProgramData account of a program deployed with the upgradeable loader
+pub fn find_program_data_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[&program_id.to_bytes()], &bpf_loader_upgradeable::id())
+}
+
+/// Creates an `InitializeConfig` instruction, the payer becomes the admin. It has to be the
+/// upgrade authority of the program.
+pub fn initialize_config(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    provider_fee_collector: &Pubkey,
+    config: Config,
+) -> Instruction {
+    let (config_address, _) = find_config_address(program_id);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(config_address, false),
+            AccountMeta::new_readonly(*provider_fee_collector, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(find_program_data_address(program_id).0, false),
+        ],
+        data: VisionInstruction::InitializeConfig(config).pack(),
+    }
+}
+
+/// Creates an `UpdateConfig` instruction. Pass the current admin / collector to keep them.
+pub fn update_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    new_admin: &Pubkey,
+    new_provider_fee_collector: &Pubkey,
+    config: Config,
+) -> Instruction {
+    let (config_address, _) = find_config_address(program_id);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(config_address, false),
+            AccountMeta::new_readonly(*new_admin, false),
+            AccountMeta::new_readonly(*new_provider_fee_collector, false),
+        ],
+        data: VisionInstruction::UpdateConfig(config).pack(),
+    }
+}
+
+/// Creates a `View` instruction. An amount of 0 prices no trade: price_after is the spot price.
+pub fn view(program_id: &Pubkey, mint: &Pubkey, side: TradeSide, amount: u64) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: view_accounts(program_id, mint, &TradeOptions::default()),
+        data: VisionInstruction::View(ViewRequest { side, amount }).pack(),
+    }
+}
+
+/// Creates a `View` instruction of a pool with a reserve mint, only the reserve of the options is used.
+/// Views have no deadline.
+pub fn view_with_options(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    side: TradeSide,
+    amount: u64,
+    options: &TradeOptions,
+) -> Result<Instruction, ProgramError> {
+    options.check()?;
+    if options.deadline.is_some() {
+        return Err(VisionError::InvalidInstruction.into());
+    }
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: view_accounts(program_id, mint, options),
+        data: VisionInstruction::View(ViewRequest { side, amount }).pack(),
+    })
+}
+
+fn view_accounts(program_id: &Pubkey, mint: &Pubkey, options: &TradeOptions) -> Vec<AccountMeta> {
+    let (pool, _) = find_pool_address(program_id, mint);
+    vec![
+        AccountMeta::new_readonly(pool, false),
+        AccountMeta::new_readonly(options.reserve_address(program_id, mint), false),
+        AccountMeta::new_readonly(*mint, false),
+    ]
+}
+
+/// Associated token account of the pool holding the reserve of a pool with a reserve mint
+pub fn find_reserve_address(program_id: &Pubkey, mint: &Pubkey, reserve_mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    let (pool, _) = find_pool_address(program_id, mint);
+    token::get_associated_token_address(&pool, reserve_mint, token_program)
+}
+
+/// Creates an `UpdateMetadata` instruction
+pub fn update_metadata(program_id: &Pubkey, authority: &Pubkey, mint: &Pubkey, token_metadata: TokenMetadata) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    let (metadata, _) = metadata::find_metadata_address(mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(metadata, false),
+            AccountMeta::new_readonly(metadata::id(), false),
+        ],
+        data: VisionInstruction::UpdateMetadata(token_metadata).pack(),
+    }
+}
+
+/// Creates a `WithdrawWithheldFees` instruction, `fee_collector` is the pool's current fee collector
+pub fn withdraw_withheld_fees(program_id: &Pubkey, mint: &Pubkey, fee_collector: &Pubkey, sources: &[Pubkey]) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(pool, false),
+        AccountMeta::new(*mint, false),
+        AccountMeta::new(token::get_associated_token_address(fee_collector, mint, &token::token_2022::id()), false),
+        AccountMeta::new_readonly(token::token_2022::id(), false),
+    ];
+    accounts.extend(sources.iter().map(|source| AccountMeta::new(*source, false)));
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: VisionInstruction::WithdrawWithheldFees.pack(),
+    }
+}
+
+/// Creates a `MigratePool` instruction
+pub fn migrate_pool(program_id: &Pubkey, payer: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: VisionInstruction::MigratePool.pack(),
+    }
+}
+
+/// Creates an `InitializeObservations` instruction, `generation` is the pool's observations_generation
+/// (0 unless a buffer was grown before) and `authority` its authority
+pub fn initialize_observations(program_id: &Pubkey, payer: &Pubkey, mint: &Pubkey, authority: &Pubkey, generation: u8, capacity: u32) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    let (observations, _) = find_observations_address(program_id, &pool, generation);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(pool, false),
+            AccountMeta::new(observations, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data: VisionInstruction::InitializeObservations(ObservationCapacity { capacity }).pack(),
+    }
+}
+
+/// Creates a `GrowObservations` instruction, `generation` is the pool's current observations_generation
+/// and `authority` its authority
+pub fn grow_observations(program_id: &Pubkey, payer: &Pubkey, mint: &Pubkey, authority: &Pubkey, generation: u8, capacity: u32) -> Instruction {
+    let (pool, _) = find_pool_address(program_id, mint);
+    let (observations, _) = find_observations_address(program_id, &pool, generation);
+    let (new_observations, _) = find_observations_address(program_id, &pool, generation.wrapping_add(1));
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(pool, false),
+            AccountMeta::new(observations, false),
+            AccountMeta::new(new_observations, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(*authority, true),
+        ],
+        data: VisionInstruction::GrowObservations(ObservationCapacity { capacity }).pack(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_variants() -> Vec<(u8, VisionInstruction)> {
+        vec![
+            (0, VisionInstruction::Initialize(
+                Curve { reserve_ratio: 609_760, curve_type: 2, curve_parameters: [7, u64::MAX] },
+                InitializeOptions {
+                    metadata: Some(TokenMetadata { name: "Page".to_string(), symbol: "PAGE".to_string(), uri: String::new() }),
+                    extensions: Some(MintExtensions { metadata_pointer: Some(Pubkey::new_from_array([20; 32])), transfer_fee: Some(TransferFee { basis_points: 50, maximum_fee: u64::MAX }) }),
+                    reserve_mint: Some(Pubkey::new_from_array([21; 32])),
+                }
+            )),
+            (1, VisionInstruction::Buy(Amount { amount_in: 1_000_000_000, minimum_amount_out: 42 })),
+            (2, VisionInstruction::Sell(Amount { amount_in: 5, minimum_amount_out: u64::MAX })),
+            (3, VisionInstruction::ChangeFee(Fee { fee: 2500 })),
+            (4, VisionInstruction::BuyExactOut(ExactTokenOut { token_amount_out: 3, maximum_amount_in: 4 })),
+            (5, VisionInstruction::SellExactOut(ExactLamportsOut { lamports_out: 6, maximum_tokens_in: 7 })),
+            (6, VisionInstruction::Quote(QuoteRequest { side: TradeSide::Sell, amount: 8, exact_in: true })),
+            (7, VisionInstruction::InitializeConfig(Config { provider_fee_bps: 100, min_page_fee: 0, max_page_fee: 50000, pause_state: PauseState::Active })),
+            (8, VisionInstruction::UpdateConfig(Config { provider_fee_bps: 1000, min_page_fee: 1, max_page_fee: 2, pause_state: PauseState::Paused })),
+            (9, VisionInstruction::View(ViewRequest { side: TradeSide::Buy, amount: 9 })),
+            (10, VisionInstruction::InitializeObservations(ObservationCapacity { capacity: 10 })),
+            (11, VisionInstruction::GrowObservations(ObservationCapacity { capacity: 11 })),
+            (12, VisionInstruction::BuyWithDeadline(Amount { amount_in: 12, minimum_amount_out: 0 }, Deadline::Slot(1))),
+            (13, VisionInstruction::SellWithDeadline(Amount { amount_in: 13, minimum_amount_out: 0 }, Deadline::UnixTimestamp(-1))),
+            (14, VisionInstruction::BuyExactOutWithDeadline(ExactTokenOut { token_amount_out: 14, maximum_amount_in: 0 }, Deadline::Slot(u64::MAX))),
+            (15, VisionInstruction::SellExactOutWithDeadline(ExactLamportsOut { lamports_out: 15, maximum_tokens_in: 0 }, Deadline::UnixTimestamp(i64::MAX))),
+            (16, VisionInstruction::SetPauseState(PauseState::WithdrawOnly)),
+            (17, VisionInstruction::SetAuthority(AuthorityStep::Accept)),
+            (18, VisionInstruction::UpdateMetadata(TokenMetadata { name: String::new(), symbol: String::new(), uri: "https://".to_string() })),
+            (19, VisionInstruction::WithdrawWithheldFees),
+            (20, VisionInstruction::MigratePool),
+        ]
+    }
+
+    #[test]
+    fn pack_unpack_round_trip() {
+        for (_, instruction) in all_variants() {
+            assert_eq!(VisionInstruction::unpack(&instruction.pack()).unwrap(), instruction);
         }
     }
 
-    fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
-        if input.len() >= 2 {
-            let (amount, rest) = input.split_at(2);
-            let amount = amount
-                .get(..2)
-                .and_then(|slice| slice.try_into().ok())
-                .map(u16::from_le_bytes)
-                .ok_or(VisionError::InvalidInstruction)?;
-            Ok((amount, rest))
-        } else {
-            Err(VisionError::InvalidInstruction.into())
+    #[test]
+    fn tags_are_stable() {
+        for (tag, instruction) in all_variants() {
+            assert_eq!(instruction.pack()[0], tag);
         }
+        // Every variant is in the list
+        assert!(VisionInstruction::unpack(&[all_variants().len() as u8]).is_err());
+    }
+
+    #[test]
+    fn layout_matches_hand_packed_bytes() {
+        let mut data = vec![0u8];
+        data.extend_from_slice(&609_760u32.to_le_bytes());
+        data.push(1);
+        data.extend_from_slice(&10u64.to_le_bytes());
+        data.extend_from_slice(&20u64.to_le_bytes());
+        // Initialize of clients from before the options
+        let curve = Curve { reserve_ratio: 609_760, curve_type: 1, curve_parameters: [10, 20] };
+        assert_eq!(VisionInstruction::unpack(&data).unwrap(), VisionInstruction::Initialize(curve.clone(), InitializeOptions::default()));
+        data.extend_from_slice(&[0, 1, 0, 0, 0]);
+        assert_eq!(VisionInstruction::unpack(&data).unwrap(), VisionInstruction::Initialize(curve.clone(), InitializeOptions {
+            extensions: Some(MintExtensions::default()),
+            ..InitializeOptions::default()
+        }));
+        // Options are all or nothing
+        data.pop();
+        assert!(VisionInstruction::unpack(&data).is_err());
+
+        let mut data = vec![6u8, 0];
+        data.extend_from_slice(&99u64.to_le_bytes());
+        data.push(0);
+        assert_eq!(
+            VisionInstruction::unpack(&data).unwrap(),
+            VisionInstruction::Quote(QuoteRequest { side: TradeSide::Buy, amount: 99, exact_in: false })
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn buy_builder_derives_accounts() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let page_fee_collector = Pubkey::new_unique();
+        let provider_fee_collector = Pubkey::new_unique();
+        let instruction = buy(&program_id, &payer, &mint, &page_fee_collector, &provider_fee_collector, 10, 1);
+
+        let (pool, _) = find_pool_address(&program_id, &mint);
+        let (pool_sol, _) = find_pool_sol_address(&program_id, &pool);
+        let keys: Vec<Pubkey> = instruction.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys, vec![
+            payer,
+            spl_associated_token_account::get_associated_token_address(&payer, &mint),
+            pool,
+            pool_sol,
+            mint,
+            page_fee_collector,
+            provider_fee_collector,
+            system_program::id(),
+            spl_token::id(),
+            find_config_address(&program_id).0,
+            spl_associated_token_account::id(),
+            sysvar::rent::id(),
+        ]);
+        let signers: Vec<bool> = instruction.accounts.iter().map(|meta| meta.is_signer).collect();
+        assert_eq!(signers, vec![true, false, false, false, false, false, false, false, false, false, false, false]);
+        assert_eq!(
+            VisionInstruction::unpack(&instruction.data).unwrap(),
+            VisionInstruction::Buy(Amount { amount_in: 10, minimum_amount_out: 1 })
+        );
+    }
+
+    #[test]
+    fn sell_builder_appends_option_accounts() {
+        let program_id = Pubkey::new_unique();
+        let seller = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let provider_fee_collector = Pubkey::new_unique();
+        let observations = Pubkey::new_unique();
+        let reserve = ReserveAccounts {
+            reserve_mint: Pubkey::new_unique(),
+            reserve_token_program: spl_token::id(),
+            trader_reserve: Pubkey::new_unique(),
+        };
+        let options = TradeOptions {
+            token_program: token::token_2022::id(),
+            reserve: Some(reserve),
+            observations: Some(observations),
+            deadline: Some(Deadline::Slot(5)),
+        };
+        let instruction = sell_with_options(&program_id, &seller, &mint, &provider_fee_collector, 10, 1, &options).unwrap();
+
+        let (pool, _) = find_pool_address(&program_id, &mint);
+        let keys: Vec<Pubkey> = instruction.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys, vec![
+            seller,
+            token::get_associated_token_address(&seller, &mint, &token::token_2022::id()),
+            pool,
+            find_reserve_address(&program_id, &mint, &reserve.reserve_mint, &spl_token::id()),
+            mint,
+            provider_fee_collector,
+            system_program::id(),
+            token::token_2022::id(),
+            find_config_address(&program_id).0,
+            reserve.trader_reserve,
+            spl_associated_token_account::get_associated_token_address(&provider_fee_collector, &reserve.reserve_mint),
+            reserve.reserve_mint,
+            spl_token::id(),
+            observations,
+        ]);
+        assert_eq!(
+            VisionInstruction::unpack(&instruction.data).unwrap(),
+            VisionInstruction::SellWithDeadline(Amount { amount_in: 10, minimum_amount_out: 1 }, Deadline::Slot(5))
+        );
+
+        // Builders return errors instead of building instructions the program rejects
+        let not_a_token_program = TradeOptions { token_program: system_program::id(), ..TradeOptions::default() };
+        assert_eq!(
+            sell_with_options(&program_id, &seller, &mint, &provider_fee_collector, 10, 1, &not_a_token_program),
+            Err(VisionError::InvalidProgramAddress.into())
+        );
+        assert_eq!(
+            quote_with_options(&program_id, &seller, &mint, &mint, &provider_fee_collector, TradeSide::Sell, 10, true, &options),
+            Err(VisionError::InvalidInstruction.into())
+        );
+        assert_eq!(view_with_options(&program_id, &mint, TradeSide::Sell, 10, &options), Err(VisionError::InvalidInstruction.into()));
+
+        // Views read the pool's reserve token account
+        let options = TradeOptions { deadline: None, ..options };
+        let view = view_with_options(&program_id, &mint, TradeSide::Sell, 10, &options).unwrap();
+        assert_eq!(view.accounts[1].pubkey, keys[3]);
+    }
+
+    #[test]
+    fn unpack_rejects_malformed_data() {
+        assert!(VisionInstruction::unpack(&[]).is_err());
+        // Truncated
+        assert!(VisionInstruction::unpack(&[3, 0]).is_err());
+        // Trailing bytes
+        assert!(VisionInstruction::unpack(&[3, 0, 0, 0]).is_err());
+        // Invalid pause state
+        assert!(VisionInstruction::unpack(&[7, 0, 0, 0, 0, 0, 0, 3]).is_err());
+        assert!(VisionInstruction::unpack(&[16, 3]).is_err());
+        // Invalid side
+        let mut data = vec![6u8, 2];
+        data.extend_from_slice(&1u64.to_le_bytes());
+        data.push(1);
+        assert!(VisionInstruction::unpack(&data).is_err());
+    }
+}
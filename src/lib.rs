@@ -1,7 +1,12 @@
+pub mod curve;
 pub mod error;
+pub mod event;
 pub mod processor;
 pub mod instruction;
+pub mod metadata;
+pub mod oracle;
 pub mod state;
+pub mod token;
 
 #[cfg(not(feature = "no-entrypoint"))]
 mod entrypoint;
@@ -0,0 +1,201 @@
+//! Client of the Metaplex token metadata program. mpl-token-metadata doesn't build with this
+//! solana-program, the two instructions used by Initialize and UpdateMetadata are encoded here.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+
+solana_program::declare_id!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// First seed of metadata accounts
+pub const PREFIX: &[u8] = b"metadata";
+
+pub const MAX_NAME_LENGTH: usize = 32;
+pub const MAX_SYMBOL_LENGTH: usize = 10;
+pub const MAX_URI_LENGTH: usize = 200;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Creator {
+    pub address: Pubkey,
+    pub verified: bool,
+    /// Share of the royalties in percent
+    pub share: u8,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Collection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum UseMethod {
+    Burn,
+    Multiple,
+    Single,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Uses {
+    pub use_method: UseMethod,
+    pub remaining: u64,
+    pub total: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct DataV2 {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub creators: Option<Vec<Creator>>,
+    pub collection: Option<Collection>,
+    pub uses: Option<Uses>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum CollectionDetails {
+    V1 { size: u64 },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct CreateMetadataAccountArgsV3 {
+    pub data: DataV2,
+    pub is_mutable: bool,
+    pub collection_details: Option<CollectionDetails>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct UpdateMetadataAccountArgsV2 {
+    pub data: Option<DataV2>,
+    pub update_authority: Option<Pubkey>,
+    pub primary_sale_happened: Option<bool>,
+    pub is_mutable: Option<bool>,
+}
+
+/// The instructions of the metadata program used by the pool. The tag is the index of the variant
+/// in the metadata program's instruction enum, followed by the Borsh encoded arguments.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataInstruction {
+    CreateMetadataAccountV3(CreateMetadataAccountArgsV3),
+    UpdateMetadataAccountV2(UpdateMetadataAccountArgsV2),
+}
+
+impl MetadataInstruction {
+    pub const CREATE_METADATA_ACCOUNT_V3: u8 = 33;
+    pub const UPDATE_METADATA_ACCOUNT_V2: u8 = 15;
+
+    pub fn pack(&self) -> Vec<u8> {
+        let (tag, args) = match self {
+            Self::CreateMetadataAccountV3(args) => (Self::CREATE_METADATA_ACCOUNT_V3, args.try_to_vec()),
+            Self::UpdateMetadataAccountV2(args) => (Self::UPDATE_METADATA_ACCOUNT_V2, args.try_to_vec()),
+        };
+        let mut data = vec![tag];
+        data.extend_from_slice(&args.expect("serializing into a Vec can't fail"));
+        data
+    }
+
+    /// None for other instructions and trailing bytes
+    pub fn unpack(input: &[u8]) -> Option<Self> {
+        let (&tag, args) = input.split_first()?;
+        match tag {
+            Self::CREATE_METADATA_ACCOUNT_V3 => CreateMetadataAccountArgsV3::try_from_slice(args).ok().map(Self::CreateMetadataAccountV3),
+            Self::UPDATE_METADATA_ACCOUNT_V2 => UpdateMetadataAccountArgsV2::try_from_slice(args).ok().map(Self::UpdateMetadataAccountV2),
+            _ => None,
+        }
+    }
+}
+
+/// Metadata account of `mint`
+pub fn find_metadata_address(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PREFIX, &id().to_bytes(), &mint.to_bytes()], &id())
+}
+
+/// Creates a mutable metadata account, `mint_authority` and `update_authority` sign
+pub fn create_metadata_account_v3(
+    metadata: &Pubkey,
+    mint: &Pubkey,
+    mint_authority: &Pubkey,
+    payer: &Pubkey,
+    update_authority: &Pubkey,
+    data: DataV2,
+) -> Instruction {
+    Instruction {
+        program_id: id(),
+        accounts: vec![
+            AccountMeta::new(*metadata, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*mint_authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*update_authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: MetadataInstruction::CreateMetadataAccountV3(CreateMetadataAccountArgsV3 {
+            data,
+            is_mutable: true,
+            collection_details: None,
+        })
+        .pack(),
+    }
+}
+
+/// Replaces the data of a metadata account, keeps its update authority and mutability
+pub fn update_metadata_account_v2(metadata: &Pubkey, update_authority: &Pubkey, data: DataV2) -> Instruction {
+    Instruction {
+        program_id: id(),
+        accounts: vec![
+            AccountMeta::new(*metadata, false),
+            AccountMeta::new_readonly(*update_authority, true),
+        ],
+        data: MetadataInstruction::UpdateMetadataAccountV2(UpdateMetadataAccountArgsV2 {
+            data: Some(data),
+            update_authority: None,
+            primary_sale_happened: None,
+            is_mutable: None,
+        })
+        .pack(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_matches_metadata_program() {
+        let data = DataV2 {
+            name: "N".to_string(),
+            symbol: "S".to_string(),
+            uri: "U".to_string(),
+            seller_fee_basis_points: 0,
+            creators: None,
+            collection: None,
+            uses: None,
+        };
+        let mut expected = vec![33];
+        for field in [b"N", b"S", b"U"].iter() {
+            expected.extend_from_slice(&1u32.to_le_bytes());
+            expected.extend_from_slice(*field);
+        }
+        // seller_fee_basis_points, no creators, collection or uses, is_mutable, no collection_details
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 1, 0]);
+        let key = Pubkey::new_unique();
+        let instruction = create_metadata_account_v3(&key, &key, &key, &key, &key, data.clone());
+        assert_eq!(instruction.data, expected);
+        assert_eq!(instruction.accounts.len(), 6);
+        assert_eq!(
+            MetadataInstruction::unpack(&instruction.data),
+            Some(MetadataInstruction::CreateMetadataAccountV3(CreateMetadataAccountArgsV3 { data: data.clone(), is_mutable: true, collection_details: None }))
+        );
+
+        let instruction = update_metadata_account_v2(&key, &key, data);
+        assert_eq!(instruction.data[..2], [15, 1]);
+        // No new update authority, primary_sale_happened and is_mutable unchanged
+        assert_eq!(instruction.data[instruction.data.len() - 3..], [0, 0, 0]);
+        assert!(MetadataInstruction::unpack(&[0]).is_none());
+        assert!(MetadataInstruction::unpack(&[instruction.data.clone(), vec![0]].concat()).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+//! TWAP queries over the samples of an ObservationBuffer
+
+use crate::{
+    curve::math::{self, U256},
+    state::{Observation, PageTokenSwap, PriceSample},
+};
+
+/// Cumulative price of the pool at `slot`.
+///
+/// `samples` are `ObservationBuffer::samples`, oldest first. Between two samples the cumulative
+/// price is interpolated, which is exact unless trades in between were not sampled. From the
+/// pool's last update on it is extended with its last price. None before the oldest sample.
+pub fn cumulative_price_at(samples: &[PriceSample], swap_state: &PageTokenSwap, slot: u64) -> Option<u128> {
+    if slot >= swap_state.price_cumulative_slot {
+        return Some(swap_state.observe(slot).price_cumulative);
+    }
+    let pool = Observation { slot: swap_state.price_cumulative_slot, price_cumulative: swap_state.price_cumulative };
+    let mut points = samples
+        .iter()
+        .map(|sample| Observation { slot: sample.slot, price_cumulative: sample.price_cumulative })
+        .chain(Some(pool));
+
+    let mut before = points.next()?;
+    if slot < before.slot {
+        return None;
+    }
+    for after in points {
+        if slot <= after.slot {
+            return Some(interpolate(&before, &after, slot));
+        }
+        before = after;
+    }
+    None
+}
+
+/// Time weighted average spot price from `start_slot` to `end_slot`, scaled like
+/// `CurveCalculator::spot_price`. None if the window is empty or starts before the oldest sample.
+pub fn twap(samples: &[PriceSample], swap_state: &PageTokenSwap, start_slot: u64, end_slot: u64) -> Option<u128> {
+    let start = Observation { slot: start_slot, price_cumulative: cumulative_price_at(samples, swap_state, start_slot)? };
+    let end = Observation { slot: end_slot, price_cumulative: cumulative_price_at(samples, swap_state, end_slot)? };
+    start.twap(&end)
+}
+
+/// Cumulative price at `slot` between `before.slot` and `after.slot`, wrapping like the accumulator
+fn interpolate(before: &Observation, after: &Observation, slot: u64) -> u128 {
+    if after.slot == before.slot {
+        return after.price_cumulative;
+    }
+    let delta = after.price_cumulative.wrapping_sub(before.price_cumulative);
+    let elapsed = U256::from(delta) * U256::from(slot - before.slot) / U256::from(after.slot - before.slot);
+    // At most delta
+    before.price_cumulative.wrapping_add(math::to_u128(elapsed).unwrap_or(delta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(slot: u64, price_cumulative: u128) -> PriceSample {
+        PriceSample { slot, price_cumulative, supply: 0, reserve_balance: 0 }
+    }
+
+    /// Price 10 from slot 0, 20 from slot 10, 40 from slot 20 (the pool's last update)
+    fn pool() -> (Vec<PriceSample>, PageTokenSwap) {
+        let swap_state = PageTokenSwap {
+            is_initialized: true,
+            version: PageTokenSwap::VERSION,
+            trade_count: 2,
+            last_trade_slot: 20,
+            price_cumulative: 300,
+            price_cumulative_slot: 20,
+            last_price: 40,
+            ..PageTokenSwap::default()
+        };
+        (vec![sample(0, 0), sample(10, 100)], swap_state)
+    }
+
+    #[test]
+    fn cumulative_price_between_and_after_samples() {
+        let (samples, swap_state) = pool();
+        assert_eq!(cumulative_price_at(&samples, &swap_state, 0), Some(0));
+        assert_eq!(cumulative_price_at(&samples, &swap_state, 5), Some(50));
+        assert_eq!(cumulative_price_at(&samples, &swap_state, 10), Some(100));
+        // The trade at slot 20 was not sampled, the pool itself is the last point
+        assert_eq!(cumulative_price_at(&samples, &swap_state, 15), Some(200));
+        assert_eq!(cumulative_price_at(&samples, &swap_state, 30), Some(700));
+    }
+
+    #[test]
+    fn twap_over_windows() {
+        let (samples, swap_state) = pool();
+        assert_eq!(twap(&samples, &swap_state, 0, 10), Some(10));
+        assert_eq!(twap(&samples, &swap_state, 10, 20), Some(20));
+        assert_eq!(twap(&samples, &swap_state, 0, 30), Some(700 / 30));
+        assert_eq!(twap(&samples, &swap_state, 25, 35), Some(40));
+        assert_eq!(twap(&samples, &swap_state, 10, 10), None);
+
+        // Before the oldest sample
+        let samples = &samples[1..];
+        assert_eq!(twap(samples, &swap_state, 5, 15), None);
+        assert_eq!(twap(samples, &swap_state, 10, 15), Some(20));
+    }
+
+    #[test]
+    fn interpolation_survives_wrap_around() {
+        let before = Observation { slot: 0, price_cumulative: u128::MAX - 9 };
+        let after = Observation { slot: 10, price_cumulative: 10 };
+        assert_eq!(interpolate(&before, &after, 4), u128::MAX - 1);
+        assert_eq!(interpolate(&before, &after, 5), 0);
+        assert_eq!(interpolate(&before, &after, 10), 10);
+    }
+}
@@ -1,39 +1,77 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
     decode_error::DecodeError,
     entrypoint::ProgramResult,
     system_instruction,
     msg,
-    program::{invoke_signed, invoke},
+    program::{invoke_signed, invoke, set_return_data},
     program_error::{PrintProgramError, ProgramError},
     program_option::COption,
     program_pack::Pack,
-    pubkey,
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar},
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
     system_program
 };
 use num_traits::FromPrimitive;
 use crate::{
-    state::{PageTokenSwap, BuyAmt},
+    curve::{self, math::{self, U256}},
+    state::{PageTokenSwap, PauseState, ProgramConfig, BuyAmt, SellAmt, Quote, PoolView, ObservationBuffer, PriceSample},
     error::VisionError,
-    instruction::{VisionInstruction, Amount, Fee},
-};
-use spl_token::{
-    state::{Account, Mint}
+    event::{VisionEvent, InitializeEvent, TradeEvent, ChangeFeeEvent, PauseStateEvent, AuthorityEvent},
+    instruction::{VisionInstruction, Amount, Fee, Curve, Config, ExactTokenOut, ExactLamportsOut, QuoteRequest, TradeSide, ViewRequest, ObservationCapacity, Deadline, AuthorityStep, TokenMetadata, InitializeOptions},
+    metadata,
+    token,
 };
 use spl_associated_token_account;
-use std::convert::TryInto;
+use spl_token_2022::extension::ExtensionType;
+
+/// Page fee is stored as x/100000 -> precision of 0.001%
+const FEE_DENOMINATOR: u128 = 100000;
+
+/// Highest page fee: 50% (x/100000)
+const MAX_PAGE_FEE: u16 = 50000;
+
+/// Page fee of a new pool: 2.5% (x/100000), clamped to the bounds of the config
+const DEFAULT_PAGE_FEE: u16 = 2500;
+
+/// Provider fee is stored in basis points (x/10000)
+const BPS_DENOMINATOR: u128 = 10000;
+
+/// Highest provider fee: 10% (x/10000)
+const MAX_PROVIDER_FEE_BPS: u16 = 1000;
+
+/// Price impact of a quote is expressed in ppm
+const PRICE_IMPACT_PRECISION: u64 = 1_000_000;
+
+/// Side of a buy that is fixed by the buyer
+pub enum BuyLimit {
+    /// Spend exactly amount_in lamports, receive at least minimum_amount_out tokens
+    ExactIn { amount_in: u64, minimum_amount_out: u64 },
+    /// Receive exactly token_amount_out tokens, spend at most maximum_amount_in lamports
+    ExactOut { token_amount_out: u64, maximum_amount_in: u64 },
+}
 
-static PROVIDER_FEE_COLLECTOR_ID: Pubkey = pubkey!("CohZhJhnHkdutc7iktrrGVUX4oUM3VctSX7DybSzRN4f");
+/// Side of a sell that is fixed by the seller
+pub enum SellLimit {
+    /// Burn exactly amount_in tokens, receive at least minimum_amount_out lamports
+    ExactIn { amount_in: u64, minimum_amount_out: u64 },
+    /// Receive exactly lamports_out lamports, burn at most maximum_tokens_in tokens
+    ExactOut { lamports_out: u64, maximum_tokens_in: u64 },
+}
 
 pub struct Processor {}
 
 impl Processor {
     pub fn initialize_page_token(
         program_id: &Pubkey,
-        accounts: &[AccountInfo]
+        accounts: &[AccountInfo],
+        reserve_ratio: u32,
+        curve_type: u8,
+        curve_parameters: [u64; 2],
+        options: InitializeOptions
     ) -> Result<(), ProgramError>{
+        let InitializeOptions { metadata: token_metadata, extensions, reserve_mint } = options;
     
     // Accounts
         let account_info_iter = &mut accounts.iter();
@@ -46,16 +84,41 @@ impl Processor {
         let system_program_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
         let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let config_info = next_account_info(account_info_iter)?;
+        // options.metadata only
+        let metadata = match token_metadata {
+            Some(token_metadata) => {
+                let metadata_info = next_account_info(account_info_iter)?;
+                let metadata_program_info = next_account_info(account_info_iter)?;
+                Some((metadata_info, metadata_program_info, Self::metadata_data(token_metadata)?))
+            }
+            None => None,
+        };
+        // options.reserve_mint only
+        let reserve = if reserve_mint.is_some() {
+            let reserve_mint_info = next_account_info(account_info_iter)?;
+            let reserve_info = next_account_info(account_info_iter)?;
+            let payer_reserve_info = next_account_info(account_info_iter)?;
+            let associated_token_program_info = next_account_info(account_info_iter)?;
+            let reserve_token_program_info = next_account_info(account_info_iter)?;
+            Some((reserve_mint_info, reserve_info, payer_reserve_info, associated_token_program_info, reserve_token_program_info))
+        } else {
+            None
+        };
     // Variables
+        let config = Self::unpack_config(program_id, config_info)?;
         let (pda, bump_seed) = Pubkey::find_program_address(&[&new_mint_info.key.to_bytes()], program_id);
         let (pda_sol, bump_seed_sol) = Pubkey::find_program_address(&[&pda_info.key.to_bytes()], program_id);
 
         // Minimum Collateral(Sol) needed for AMM to Mint the first Token to the Associated Token Account
-        let collateral = 36 as u64;
-        let collateral_rent = collateral.checked_add((Rent::get()?).minimum_balance(0 as usize)).ok_or(VisionError::Overflow)?;
+        let collateral = 36u64;
+        let collateral_rent = collateral.checked_add((Rent::get()?).minimum_balance(0)).ok_or(VisionError::Overflow)?;
 
     // Checks
 
+        // Curve
+        curve::curve_calculator(curve_type, reserve_ratio, &curve_parameters)?.validate()?;
+
         // Payer Info
         if !payer_info.is_signer {
             return Err(VisionError::SignatureRequired.into()); 
@@ -98,10 +161,47 @@ impl Processor {
             return Err(VisionError::InvalidProgramAddress.into());
         }
 
-        // Token Program Id
-        if *token_program_info.key != spl_token::ID{
+        // Token Program Id, mint extensions need Token-2022
+        if !token::is_token_program(token_program_info.key) {
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+        if extensions.is_some() && *token_program_info.key != token::token_2022::id() {
             return Err(VisionError::InvalidProgramAddress.into());
         }
+
+        // Metaplex metadata
+        if let Some((metadata_info, metadata_program_info, _)) = &metadata {
+            Self::check_metadata_accounts(new_mint_info.key, metadata_info, metadata_program_info)?;
+        }
+
+        // Reserve mint, of either token program. The payer pays the collateral in reserve tokens.
+        // Transfer fees would take their cut of every transfer to and from the reserve, such mints are rejected.
+        if let Some((reserve_mint_info, reserve_info, payer_reserve_info, associated_token_program_info, reserve_token_program_info)) = &reserve {
+            if Some(*reserve_mint_info.key) != reserve_mint {
+                return Err(VisionError::InvalidAccountAddress.into());
+            }
+            if !token::is_token_program(reserve_token_program_info.key) {
+                return Err(VisionError::InvalidProgramAddress.into());
+            }
+            if *reserve_mint_info.owner != *reserve_token_program_info.key {
+                return Err(VisionError::InvalidAccountOnwerProgram.into());
+            }
+            if token::has_extension(&reserve_mint_info.data.borrow(), ExtensionType::TransferFeeConfig)? {
+                return Err(VisionError::InvalidMint.into());
+            }
+            if *reserve_info.key != token::get_associated_token_address(&pda, reserve_mint_info.key, reserve_token_program_info.key) {
+                return Err(VisionError::InvalidAccountAddress.into());
+            }
+            if *payer_reserve_info.owner != *reserve_token_program_info.key {
+                return Err(VisionError::InvalidAccountOnwerProgram.into());
+            }
+            if token::unpack_account(&payer_reserve_info.data.borrow())?.amount < collateral {
+                return Err(VisionError::BalanceTooSmall.into());
+            }
+            if *associated_token_program_info.key != spl_associated_token_account::id() {
+                return Err(VisionError::InvalidProgramAddress.into());
+            }
+        }
     // EXECUTION
 
         // AMM
@@ -128,35 +228,90 @@ impl Processor {
 
             // Save AMM Info
             swap_state.is_initialized = true;
+            swap_state.version = PageTokenSwap::VERSION;
             swap_state.bump_seed = bump_seed;
             swap_state.bump_seed_sol = bump_seed_sol;
-            swap_state.fee = 2500;
+            swap_state.fee = DEFAULT_PAGE_FEE.max(config.min_page_fee).min(config.max_page_fee);
             // ! Replace Fee collector with Program that distributes fee % towards multiple accounts
             swap_state.fee_collector_pubkey = *fee_collector_info.key;
+            swap_state.authority = *fee_collector_info.key;
+            swap_state.reserve_ratio = reserve_ratio;
+            swap_state.curve_type = curve_type;
+            swap_state.curve_parameters = curve_parameters;
+            swap_state.reserve_mint = reserve.map_or(Pubkey::default(), |(reserve_mint_info, ..)| *reserve_mint_info.key);
+            let reserve_mint = swap_state.reserve_mint;
+            let fee = swap_state.fee;
+            let spot_price = Self::spot_price(&swap_state, 1000000000u64, collateral)?;
+            // Start of the price oracle
+            swap_state.record_price(Clock::get()?.slot, spot_price);
             PageTokenSwap::pack(swap_state, &mut pda_info.data.borrow_mut())?;
 
             // Save collateral for one token in Account
-            invoke(
-                &system_instruction::transfer(
-                    payer_info.key,
-                    pda_associated_sol_info.key,
-                    collateral_rent,
-                ),
-                &[
-                    payer_info.clone(),
-                    pda_associated_sol_info.clone(),
-                    system_program_info.clone()
-                ]
-            )?;
+            match reserve {
+                Some((reserve_mint_info, reserve_info, payer_reserve_info, associated_token_program_info, reserve_token_program_info)) => {
+                    invoke(
+                        &token::create_associated_token_account(
+                            payer_info.key,
+                            pda_info.key,
+                            reserve_mint_info.key,
+                            reserve_token_program_info.key
+                        ),
+                        &[
+                            payer_info.clone(),
+                            reserve_info.clone(),
+                            pda_info.clone(),
+                            reserve_mint_info.clone(),
+                            system_program_info.clone(),
+                            reserve_token_program_info.clone(),
+                            rent_sysvar_info.clone(),
+                            associated_token_program_info.clone()
+                        ]
+                    )?;
+                    invoke(
+                        &token::transfer_checked(
+                            reserve_token_program_info.key,
+                            payer_reserve_info.key,
+                            reserve_mint_info.key,
+                            reserve_info.key,
+                            payer_info.key,
+                            collateral,
+                            token::unpack_mint(&reserve_mint_info.data.borrow())?.decimals
+                        )?,
+                        &[
+                            reserve_token_program_info.clone(),
+                            payer_reserve_info.clone(),
+                            reserve_mint_info.clone(),
+                            reserve_info.clone(),
+                            payer_info.clone()
+                        ]
+                    )?;
+                }
+                None => {
+                    invoke(
+                        &system_instruction::transfer(
+                            payer_info.key,
+                            pda_associated_sol_info.key,
+                            collateral_rent,
+                        ),
+                        &[
+                            payer_info.clone(),
+                            pda_associated_sol_info.clone(),
+                            system_program_info.clone()
+                        ]
+                    )?;
+                }
+            }
             // Calculation will start with 1(*10^9) token without actually ever minting and creating associated token account to prevent formula from hitting 0.
             
         // Mint Token
+        let extensions = extensions.unwrap_or_default();
+        let mint_space = token::mint_space(extensions.transfer_fee.is_some(), extensions.metadata_pointer.is_some())?;
         invoke(
             &system_instruction::create_account(
                 payer_info.key,
                 new_mint_info.key,
-                (Rent::get()?).minimum_balance(Mint::LEN),
-                Mint::LEN as u64,
+                (Rent::get()?).minimum_balance(mint_space),
+                mint_space as u64,
                 token_program_info.key,
             ),
             &[
@@ -165,12 +320,35 @@ impl Processor {
                 token_program_info.clone()
             ]
         )?;
+        // Extensions are initialized before the mint, the pda withdraws withheld transfer fees to the fee collector
+        if let Some(transfer_fee) = extensions.transfer_fee {
+            invoke(
+                &token::initialize_transfer_fee_config(
+                    new_mint_info.key,
+                    pda_info.key,
+                    transfer_fee.basis_points,
+                    transfer_fee.maximum_fee
+                )?,
+                &[
+                    new_mint_info.clone(),
+                    token_program_info.clone()
+                ]
+            )?;
+        }
+        if let Some(metadata_address) = extensions.metadata_pointer {
+            invoke(
+                &token::initialize_metadata_pointer(new_mint_info.key, &metadata_address)?,
+                &[
+                    new_mint_info.clone(),
+                    token_program_info.clone()
+                ]
+            )?;
+        }
         invoke(
-            &spl_token::instruction::initialize_mint(
+            &token::initialize_mint(
                 token_program_info.key,
                 new_mint_info.key,
                 pda_info.key,
-                None,
                 9
             )?,
             &[
@@ -181,6 +359,44 @@ impl Processor {
             ]
         )?;
 
+        // Metadata, the pda is mint and update authority
+        if let Some((metadata_info, metadata_program_info, data)) = metadata {
+            invoke_signed(
+                &metadata::create_metadata_account_v3(
+                    metadata_info.key,
+                    new_mint_info.key,
+                    pda_info.key,
+                    payer_info.key,
+                    pda_info.key,
+                    data
+                ),
+                &[
+                    metadata_info.clone(),
+                    new_mint_info.clone(),
+                    pda_info.clone(),
+                    payer_info.clone(),
+                    system_program_info.clone(),
+                    metadata_program_info.clone()
+                ],
+                &[&[
+                    &new_mint_info.key.to_bytes(),
+                    &[bump_seed]
+                ]]
+            )?;
+        }
+
+        VisionEvent::Initialize(InitializeEvent {
+            mint: *new_mint_info.key,
+            payer: *payer_info.key,
+            fee_collector: *fee_collector_info.key,
+            fee,
+            curve_type,
+            reserve_ratio,
+            curve_parameters,
+            reserve_balance: collateral,
+            spot_price,
+            reserve_mint,
+        }).emit();
 
         Ok(())
 
@@ -191,6 +407,137 @@ impl Processor {
         accounts: &[AccountInfo],
         amount_in: u64,
         minimum_amount_out: u64
+    ) -> Result<(), ProgramError> {
+        Self::process_buy(program_id, accounts, BuyLimit::ExactIn { amount_in, minimum_amount_out }, false)
+    }
+
+    pub fn buy_exact_out(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        token_amount_out: u64,
+        maximum_amount_in: u64
+    ) -> Result<(), ProgramError> {
+        Self::process_buy(program_id, accounts, BuyLimit::ExactOut { token_amount_out, maximum_amount_in }, false)
+    }
+
+    /// Lamports spent, fees and tokens minted for a buy, rounded in the pool's favor
+    pub fn buy_amounts(
+        swap_state: &PageTokenSwap,
+        provider_fee_bps: u16,
+        token_supply: u64,
+        reserve_balance: u64,
+        limit: &BuyLimit
+    ) -> Result<BuyAmt, ProgramError> {
+        let calculator = swap_state.curve_calculator()?;
+
+        let amount_in = match *limit {
+            BuyLimit::ExactIn { amount_in, .. } => amount_in,
+            BuyLimit::ExactOut { token_amount_out, .. } => {
+                // Lamports the reserve needs, grossed up so that amount_in - fees covers them
+                let reserve_amount_in = calculator.in_for_exact_out(token_supply, reserve_balance, token_amount_out).ok_or(VisionError::Overflow)?;
+                Self::gross_up(reserve_amount_in, (swap_state.fee as u128) + Self::provider_fee_rate(provider_fee_bps))?
+            }
+        };
+
+        let (fee_page, fee_provider) = Self::buy_fees(amount_in, swap_state.fee, provider_fee_bps);
+        let adjusted_amount_in = amount_in.checked_sub(fee_page).and_then(|amount| amount.checked_sub(fee_provider)).ok_or(VisionError::Overflow)?;
+
+        let token_amt = match *limit {
+            BuyLimit::ExactIn { .. } => calculator.buy_out_for_in(token_supply, reserve_balance, adjusted_amount_in).ok_or(VisionError::Overflow)?,
+            BuyLimit::ExactOut { token_amount_out, .. } => token_amount_out,
+        };
+
+        Ok(BuyAmt {
+            amount_in,
+            adjusted_amount_in,
+            token_amt,
+            fee_page,
+            fee_provider
+        })
+    }
+
+    /// Smallest amount that is still >= net_amount after fee (x/100000) is taken from it
+    fn gross_up(net_amount: u64, fee: u128) -> Result<u64, ProgramError> {
+        let fee_rate = FEE_DENOMINATOR.checked_sub(fee).filter(|rate| *rate > 0).ok_or(VisionError::InvalidFee)?;
+        let gross_amount = math::ceil_div(U256::from(net_amount) * U256::from(FEE_DENOMINATOR), U256::from(fee_rate)).ok_or(VisionError::Overflow)?;
+        Ok(math::to_u64(gross_amount).ok_or(VisionError::Overflow)?)
+    }
+
+    /// Provider fee (basis points) in the precision of the page fee (x/100000)
+    fn provider_fee_rate(provider_fee_bps: u16) -> u128 {
+        (provider_fee_bps as u128) * FEE_DENOMINATOR / BPS_DENOMINATOR
+    }
+
+    /// Page fee and provider fee charged on amount_in, rounded down
+    fn buy_fees(amount_in: u64, fee: u16, provider_fee_bps: u16) -> (u64, u64) {
+        let page_fee = ((amount_in as u128) * (fee as u128) / FEE_DENOMINATOR) as u64;
+        let provider_fee = ((amount_in as u128) * (provider_fee_bps as u128) / BPS_DENOMINATOR) as u64;
+        (page_fee, provider_fee)
+    }
+
+    /// Runs every check and calculation of a trade without executing it, the result is returned as a packed `Quote`.
+    /// Takes the accounts of Buy or Sell depending on side. Slippage limits are not enforced.
+    pub fn quote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        side: TradeSide,
+        amount: u64,
+        exact_in: bool
+    ) -> Result<(), ProgramError> {
+        match (side, exact_in) {
+            (TradeSide::Buy, true) => {
+                Self::process_buy(program_id, accounts, BuyLimit::ExactIn { amount_in: amount, minimum_amount_out: 0 }, true)
+            }
+            (TradeSide::Buy, false) => {
+                Self::process_buy(program_id, accounts, BuyLimit::ExactOut { token_amount_out: amount, maximum_amount_in: u64::MAX }, true)
+            }
+            (TradeSide::Sell, true) => {
+                Self::process_sell(program_id, accounts, SellLimit::ExactIn { amount_in: amount, minimum_amount_out: 0 }, true)
+            }
+            (TradeSide::Sell, false) => {
+                Self::process_sell(program_id, accounts, SellLimit::ExactOut { lamports_out: amount, maximum_tokens_in: u64::MAX }, true)
+            }
+        }
+    }
+
+    /// Spot price after a trade and its change from the spot price before it, in ppm
+    fn price_impact(
+        swap_state: &PageTokenSwap,
+        token_supply: u64,
+        reserve_balance: u64,
+        token_supply_after: u64,
+        reserve_balance_after: u64
+    ) -> Result<(u128, u64), ProgramError> {
+        let calculator = swap_state.curve_calculator()?;
+        let price_before = calculator.spot_price(token_supply, reserve_balance).ok_or(VisionError::Overflow)?;
+        let price_after = calculator.spot_price(token_supply_after, reserve_balance_after).ok_or(VisionError::Overflow)?;
+        let price_change = price_after.max(price_before) - price_after.min(price_before);
+        let price_impact = if price_before == 0 {
+            0
+        } else {
+            let impact = U256::from(price_change) * U256::from(PRICE_IMPACT_PRECISION) / U256::from(price_before);
+            math::to_u64(impact).ok_or(VisionError::Overflow)?
+        };
+        Ok((price_after, price_impact))
+    }
+
+    /// Spot price logged with the events, 0 if the curve can't price the pool so logging never fails a trade
+    fn spot_price(swap_state: &PageTokenSwap, token_supply: u64, reserve_balance: u64) -> Result<u128, ProgramError> {
+        Ok(swap_state.curve_calculator()?.spot_price(token_supply, reserve_balance).unwrap_or(0))
+    }
+
+    fn return_quote(quote: Quote) -> Result<(), ProgramError> {
+        let mut data = [0u8; Quote::LEN];
+        Quote::pack(quote, &mut data)?;
+        set_return_data(&data);
+        Ok(())
+    }
+
+    fn process_buy(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        limit: BuyLimit,
+        quote_only: bool
     ) -> Result<(), ProgramError> {
         let account_info_iter = &mut accounts.iter();
     // Accounts
@@ -203,34 +550,92 @@ impl Processor {
         let provider_fee_collector_info = next_account_info(account_info_iter)?;    
         let system_program_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
+        let config_info = next_account_info(account_info_iter)?;
+        let associated_token_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
     // Variables
 
-        let mint_state = spl_token::state::Mint::unpack(&mint_info.data.borrow())?;
+        // Program config, trading can be paused for every pool
+        let config = Self::unpack_config(program_id, config_info)?;
 
-        // AMM state
-        let swap_state = PageTokenSwap::unpack(&pda_info.data.borrow())?;
-        // FEES
-        let page_fee = ((amount_in as f64) * ((swap_state.fee as f64) / (100000f64))) as u64;
-        let provider_fee = ((amount_in as f64) * 0.01f64) as u64;
+        let mint_state = token::unpack_mint(&mint_info.data.borrow())?;
 
-        // Bancor formula "purchaseTargetAmount"
+        // AMM state, trading can also be paused by the page
+        let mut swap_state = PageTokenSwap::unpack_versioned(&pda_info.data.borrow())?;
+        if !config.pause_state.allows_buy() || !swap_state.pause_state.allows_buy() {
+            return Err(VisionError::Paused.into());
+        }
+        // Pools with a reserve mint are paid in reserve tokens
+        let reserve_accounts = if swap_state.has_token_reserve() {
+            let payer_reserve_info = next_account_info(account_info_iter)?;
+            let page_fee_collector_reserve_info = next_account_info(account_info_iter)?;
+            let provider_fee_collector_reserve_info = next_account_info(account_info_iter)?;
+            let reserve_mint_info = next_account_info(account_info_iter)?;
+            let reserve_token_program_info = next_account_info(account_info_iter)?;
+            Some((payer_reserve_info, page_fee_collector_reserve_info, provider_fee_collector_reserve_info, reserve_mint_info, reserve_token_program_info))
+        } else {
+            None
+        };
+        // Required once the pool has an ObservationBuffer, leaving it out would skip the sample
+        let observations_info = if swap_state.has_observations {
+            Some(next_account_info(account_info_iter)?)
+        } else {
+            account_info_iter.next()
+        };
+        // A queued fee change applies from its slot on
+        let slot = Clock::get()?.slot;
+        swap_state.activate_pending_fee(slot);
+
+        // Curve "buy_out_for_in" (Bancor "purchaseTargetAmount") or "in_for_exact_out"
             // Token supply in circulation + initial 1(*10^9) token.
-            let token_supply = (((spl_token::state::Mint::unpack(&mint_info.data.borrow())?).supply as u64).checked_add(1000000000u64).ok_or(VisionError::Overflow)?) as f64;
-            // Amount In - Fees
-            let adjusted_amount_in = (amount_in as f64) * (1f64 - 0.01f64 - ((swap_state.fee as f64) / (100000f64)));
+            let token_supply = mint_state.supply.checked_add(1000000000u64).ok_or(VisionError::Overflow)?;
             // Reserve Balance - Rent payed for Rent exemption
-            let reserve_balance = (pda_associated_sol_info.lamports().checked_sub((((Rent::get()?).minimum_balance(0 as usize)) as u64)).ok_or(VisionError::Overflow)?) as f64;
-            // Tokens received if input is amount_in
-            let token_amt_from_sol_input = (token_supply * (((1f64 + (adjusted_amount_in) / reserve_balance).powf(0.60976f64)) - 1f64)) as u64;
-            msg!("token_amt_from_sol_input {:?}",token_amt_from_sol_input);
+            let reserve_balance = Self::unpack_reserve(program_id, pda_info, &swap_state, pda_associated_sol_info)?;
+            // Amount In, fees and tokens received
+            let BuyAmt {
+                amount_in,
+                adjusted_amount_in,
+                token_amt: token_amt_from_sol_input,
+                fee_page: page_fee,
+                fee_provider: provider_fee
+            } = Self::buy_amounts(&swap_state, config.provider_fee_bps, token_supply, reserve_balance, &limit)?;
     // Checks
 
         // Check slippage
-            if amount_in > payer_info.lamports() {
+            // First buy of the payer, its associated token account is created before minting
+            let create_token_account = *payer_associated_token_address_info.owner == system_program::ID && payer_associated_token_address_info.data_is_empty();
+            let token_account_rent = if create_token_account {
+                let space = token::associated_account_space(token_program_info.key, &mint_info.data.borrow())?;
+                (Rent::get()?).minimum_balance(space).saturating_sub(payer_associated_token_address_info.lamports())
+            } else {
+                0
+            };
+            let lamports_needed = match reserve_accounts {
+                Some(_) => token_account_rent,
+                None => amount_in.checked_add(token_account_rent).ok_or(VisionError::Overflow)?,
+            };
+            if lamports_needed > payer_info.lamports() {
                 return Err(VisionError::BalanceTooSmall.into());
             }
-            if token_amt_from_sol_input < minimum_amount_out {
-                return Err(VisionError::ExceededSlippage.into());
+            if let Some((payer_reserve_info, _, _, _, reserve_token_program_info)) = reserve_accounts {
+                if *payer_reserve_info.owner != *reserve_token_program_info.key {
+                    return Err(VisionError::InvalidAccountOnwerProgram.into());
+                }
+                if amount_in > token::unpack_account(&payer_reserve_info.data.borrow())?.amount {
+                    return Err(VisionError::BalanceTooSmall.into());
+                }
+            }
+            match limit {
+                BuyLimit::ExactIn { minimum_amount_out, .. } => {
+                    if token_amt_from_sol_input < minimum_amount_out {
+                        return Err(VisionError::ExceededSlippage.into());
+                    }
+                }
+                BuyLimit::ExactOut { maximum_amount_in, .. } => {
+                    if amount_in > maximum_amount_in {
+                        return Err(VisionError::ExceededSlippage.into());
+                    }
+                }
             }
 
         // Accounts
@@ -240,13 +645,20 @@ impl Processor {
             if *payer_info.owner != system_program::ID {
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
             }
-            // Create Associated token account off-chain and check everything on-chain
-            if *payer_associated_token_address_info.owner != spl_token::ID {
+            // Token accounts and the mint are owned by the token program passed
+            if !token::is_token_program(token_program_info.key) {
+                return Err(VisionError::InvalidProgramAddress.into());
+            }
+            // Associated token account is created if empty, the rent sysvar is checked by its program
+            if !create_token_account && *payer_associated_token_address_info.owner != *token_program_info.key {
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
             }
-            if *payer_associated_token_address_info.key != spl_associated_token_account::get_associated_token_address(payer_info.key, mint_info.key){
+            if *payer_associated_token_address_info.key != token::get_associated_token_address(payer_info.key, mint_info.key, token_program_info.key){
                 return Err(VisionError::InvalidAccountAddress.into());
             }
+            if *associated_token_program_info.key != spl_associated_token_account::id() {
+                return Err(VisionError::InvalidProgramAddress.into());
+            }
 
             if *pda_info.owner != *program_id{
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
@@ -256,14 +668,21 @@ impl Processor {
             }
             
 
-            if *pda_associated_sol_info.key != (Pubkey::create_program_address(&[&pda_info.key.to_bytes(), &[swap_state.bump_seed_sol]], program_id)?) {
-                return Err(VisionError::InvalidAccountAddress.into());
-            }
-            if *pda_associated_sol_info.owner != system_program::ID{
-                return Err(VisionError::InvalidAccountOnwerProgram.into());
-            }
-
-            if *mint_info.owner != spl_token::ID {
+            // The reserve is checked by unpack_reserve, reserve tokens move with the reserve mint's token program
+            let reserve_decimals = match reserve_accounts {
+                Some((_, page_fee_collector_reserve_info, provider_fee_collector_reserve_info, reserve_mint_info, reserve_token_program_info)) => {
+                    let decimals = Self::unpack_reserve_mint(&swap_state, reserve_mint_info, reserve_token_program_info)?;
+                    if *pda_associated_sol_info.owner != *reserve_token_program_info.key {
+                        return Err(VisionError::InvalidAccountOnwerProgram.into());
+                    }
+                    Self::check_reserve_account(&swap_state, page_fee_collector_reserve_info, &swap_state.fee_collector_pubkey, reserve_token_program_info.key)?;
+                    Self::check_reserve_account(&swap_state, provider_fee_collector_reserve_info, &config.provider_fee_collector, reserve_token_program_info.key)?;
+                    decimals
+                }
+                None => 0,
+            };
+
+            if *mint_info.owner != *token_program_info.key {
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
             }
             if !mint_state.is_initialized {
@@ -283,7 +702,7 @@ impl Processor {
             if *provider_fee_collector_info.owner != system_program::ID{
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
             }
-            if *provider_fee_collector_info.key != PROVIDER_FEE_COLLECTOR_ID {
+            if *provider_fee_collector_info.key != config.provider_fee_collector {
                 return Err(VisionError::InvalidAccountAddress.into());
             }
 
@@ -291,19 +710,57 @@ impl Processor {
                 return Err(VisionError::InvalidProgramAddress.into());
             }
 
-            if *token_program_info.key != spl_token::ID{
-                return Err(VisionError::InvalidProgramAddress.into());
+            let observations = match observations_info {
+                Some(observations_info) => Some((observations_info, Self::unpack_observations(program_id, pda_info.key, &swap_state, observations_info)?)),
+                None => None,
+            };
+
+        // Quote
+            if quote_only {
+                let (price_after, price_impact) = Self::price_impact(
+                    &swap_state,
+                    token_supply,
+                    reserve_balance,
+                    token_supply.checked_add(token_amt_from_sol_input).ok_or(VisionError::Overflow)?,
+                    reserve_balance.checked_add(adjusted_amount_in).ok_or(VisionError::Overflow)?
+                )?;
+                return Self::return_quote(Quote {
+                    amount_in,
+                    amount_out: token_amt_from_sol_input,
+                    page_fee,
+                    provider_fee,
+                    price_after,
+                    price_impact
+                });
             }
     // EXECUTION
-        
+        if create_token_account {
+            invoke(
+                &token::create_associated_token_account(
+                    payer_info.key,
+                    payer_info.key,
+                    mint_info.key,
+                    token_program_info.key
+                ),
+                &[
+                    payer_info.clone(),
+                    payer_associated_token_address_info.clone(),
+                    mint_info.clone(),
+                    system_program_info.clone(),
+                    token_program_info.clone(),
+                    rent_sysvar_info.clone(),
+                    associated_token_program_info.clone()
+                ]
+            )?;
+        }
+
         invoke_signed(
-            &spl_token::instruction::mint_to(
+            &token::mint_to(
                 token_program_info.key,
                 mint_info.key,
                 payer_associated_token_address_info.key,
                 pda_info.key,
-                &[],
-                token_amt_from_sol_input as u64
+                token_amt_from_sol_input
             )?,
             &[
                 token_program_info.clone(),
@@ -317,46 +774,107 @@ impl Processor {
             ]]
         )?;
 
-        // Pay for token
-        invoke(
-            &system_instruction::transfer(
-                payer_info.key,
-                pda_associated_sol_info.key,
-                adjusted_amount_in as u64
-            ),
-            &[
-                system_program_info.clone(),
-                payer_info.clone(),
-                pda_associated_sol_info.clone()
-            ]
-        )?;
+        // Pay for token and fees, in reserve tokens if the pool has a reserve mint
+        match reserve_accounts {
+            Some((payer_reserve_info, page_fee_collector_reserve_info, provider_fee_collector_reserve_info, reserve_mint_info, reserve_token_program_info)) => {
+                for (destination_info, amount) in [
+                    (pda_associated_sol_info, adjusted_amount_in),
+                    (provider_fee_collector_reserve_info, provider_fee),
+                    (page_fee_collector_reserve_info, page_fee)
+                ].iter() {
+                    invoke(
+                        &token::transfer_checked(
+                            reserve_token_program_info.key,
+                            payer_reserve_info.key,
+                            reserve_mint_info.key,
+                            destination_info.key,
+                            payer_info.key,
+                            *amount,
+                            reserve_decimals
+                        )?,
+                        &[
+                            reserve_token_program_info.clone(),
+                            payer_reserve_info.clone(),
+                            reserve_mint_info.clone(),
+                            (*destination_info).clone(),
+                            payer_info.clone()
+                        ]
+                    )?;
+                }
+            }
+            None => {
+                // Pay for token
+                invoke(
+                    &system_instruction::transfer(
+                        payer_info.key,
+                        pda_associated_sol_info.key,
+                        adjusted_amount_in
+                    ),
+                    &[
+                        system_program_info.clone(),
+                        payer_info.clone(),
+                        pda_associated_sol_info.clone()
+                    ]
+                )?;
+
+                // Pay fee to provider
+                invoke(
+                    &system_instruction::transfer(
+                        payer_info.key,
+                        provider_fee_collector_info.key,
+                        provider_fee
+                    ),
+                    &[
+                        system_program_info.clone(),
+                        payer_info.clone(),
+                        provider_fee_collector_info.clone()
+                    ]
+                )?;
+                // Pay fee to page fee collector
+                invoke(
+                    &system_instruction::transfer(
+                        payer_info.key,
+                        page_fee_collector_info.key,
+                        page_fee
+                    ),
+                    &[
+                        system_program_info.clone(),
+                        payer_info.clone(),
+                        page_fee_collector_info.clone()
+                    ]
+                )?;
+            }
+        }
 
-        // Pay fee to provider
-        invoke(
-            &system_instruction::transfer(
-                payer_info.key,
-                provider_fee_collector_info.key,
-                provider_fee as u64
-            ),
-            &[
-                system_program_info.clone(),
-                payer_info.clone(),
-                provider_fee_collector_info.clone()
-            ]
-        )?;
-        // Pay fee to page fee collector
-        invoke(
-            &system_instruction::transfer(
-                payer_info.key,
-                page_fee_collector_info.key,
-                page_fee as u64
-            ),
-            &[
-                system_program_info.clone(),
-                payer_info.clone(),
-                page_fee_collector_info.clone()
-            ]
-        )?;
+        let token_supply_after = token_supply.checked_add(token_amt_from_sol_input).ok_or(VisionError::Overflow)?;
+        let reserve_balance_after = reserve_balance.checked_add(adjusted_amount_in).ok_or(VisionError::Overflow)?;
+        let spot_price = Self::spot_price(&swap_state, token_supply_after, reserve_balance_after)?;
+
+        // Price oracle and pool statistics
+        swap_state.record_price(slot, spot_price);
+        swap_state.record_buy(amount_in, page_fee, provider_fee, slot)?;
+        let supply_after = mint_state.supply.checked_add(token_amt_from_sol_input).ok_or(VisionError::Overflow)?;
+        if let Some((observations_info, observations)) = observations {
+            Self::write_observation(observations_info, observations, PriceSample {
+                slot,
+                price_cumulative: swap_state.price_cumulative,
+                supply: supply_after,
+                reserve_balance: reserve_balance_after
+            })?;
+        }
+        PageTokenSwap::pack_versioned(swap_state, &mut pda_info.data.borrow_mut())?;
+
+        VisionEvent::Buy(TradeEvent {
+            mint: *mint_info.key,
+            trader: *payer_info.key,
+            amount_in,
+            amount_out: token_amt_from_sol_input,
+            page_fee,
+            provider_fee,
+            supply: supply_after,
+            reserve_balance: reserve_balance_after,
+            spot_price,
+        }).emit();
 
         Ok(())
 
@@ -368,6 +886,66 @@ impl Processor {
         accounts: &[AccountInfo],
         amount_in: u64,
         minimum_amount_out: u64
+    ) -> Result<(), ProgramError> {
+        Self::process_sell(program_id, accounts, SellLimit::ExactIn { amount_in, minimum_amount_out }, false)
+    }
+
+    pub fn sell_exact_out(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        lamports_out: u64,
+        maximum_tokens_in: u64
+    ) -> Result<(), ProgramError> {
+        Self::process_sell(program_id, accounts, SellLimit::ExactOut { lamports_out, maximum_tokens_in }, false)
+    }
+
+    /// Tokens burned, fees and lamports paid out for a sell, rounded in the pool's favor
+    pub fn sell_amounts(
+        swap_state: &PageTokenSwap,
+        provider_fee_bps: u16,
+        token_supply: u64,
+        reserve_balance: u64,
+        limit: &SellLimit
+    ) -> Result<SellAmt, ProgramError> {
+        let calculator = swap_state.curve_calculator()?;
+
+        match *limit {
+            SellLimit::ExactIn { amount_in, .. } => {
+                let sol_amt = calculator.sell_out_for_in(token_supply, reserve_balance, amount_in).ok_or(VisionError::Overflow)?;
+                let fee_provider = Self::sell_fee(sol_amt, provider_fee_bps);
+                Ok(SellAmt {
+                    token_amt: amount_in,
+                    sol_amt,
+                    adjusted_sol_amt: sol_amt.checked_sub(fee_provider).ok_or(VisionError::Overflow)?,
+                    fee_provider
+                })
+            }
+            SellLimit::ExactOut { lamports_out, .. } => {
+                // Lamports released from the reserve, grossed up so that sol_amt - fee covers lamports_out
+                let sol_amt = Self::gross_up(lamports_out, Self::provider_fee_rate(provider_fee_bps))?;
+                let fee_provider = Self::sell_fee(sol_amt, provider_fee_bps);
+                let token_amt = calculator.sell_in_for_exact_out(token_supply, reserve_balance, sol_amt).ok_or(VisionError::Overflow)?;
+                // Rounding leftovers of the gross up stay in the reserve
+                Ok(SellAmt {
+                    token_amt,
+                    sol_amt: lamports_out.checked_add(fee_provider).ok_or(VisionError::Overflow)?,
+                    adjusted_sol_amt: lamports_out,
+                    fee_provider
+                })
+            }
+        }
+    }
+
+    /// Provider fee charged on the lamports released by a sell, rounded down
+    fn sell_fee(sol_amt: u64, provider_fee_bps: u16) -> u64 {
+        ((sol_amt as u128) * (provider_fee_bps as u128) / BPS_DENOMINATOR) as u64
+    }
+
+    fn process_sell(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        limit: SellLimit,
+        quote_only: bool
     ) -> Result<(), ProgramError> {
         let account_info_iter = &mut accounts.iter();
     // Accounts
@@ -379,39 +957,72 @@ impl Processor {
         let provider_fee_collector_info = next_account_info(account_info_iter)?;    
         let system_program_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
+        let config_info = next_account_info(account_info_iter)?;
     // Variables
 
-        let mint_state = spl_token::state::Mint::unpack(&mint_info.data.borrow())?;
+        // Program config, trading can be paused for every pool
+        let config = Self::unpack_config(program_id, config_info)?;
 
-        // AMM state
-        let swap_state = PageTokenSwap::unpack(&pda_info.data.borrow())?;
+        let mint_state = token::unpack_mint(&mint_info.data.borrow())?;
 
-        // Bancor formula "saleTargetAmount"
+        // AMM state, trading can also be paused by the page
+        let mut swap_state = PageTokenSwap::unpack_versioned(&pda_info.data.borrow())?;
+        if !config.pause_state.allows_sell() || !swap_state.pause_state.allows_sell() {
+            return Err(VisionError::Paused.into());
+        }
+        // Pools with a reserve mint pay out reserve tokens
+        let reserve_accounts = if swap_state.has_token_reserve() {
+            let seller_reserve_info = next_account_info(account_info_iter)?;
+            let provider_fee_collector_reserve_info = next_account_info(account_info_iter)?;
+            let reserve_mint_info = next_account_info(account_info_iter)?;
+            let reserve_token_program_info = next_account_info(account_info_iter)?;
+            Some((seller_reserve_info, provider_fee_collector_reserve_info, reserve_mint_info, reserve_token_program_info))
+        } else {
+            None
+        };
+        // Required once the pool has an ObservationBuffer, leaving it out would skip the sample
+        let observations_info = if swap_state.has_observations {
+            Some(next_account_info(account_info_iter)?)
+        } else {
+            account_info_iter.next()
+        };
+        // A queued fee change applies from its slot on
+        let slot = Clock::get()?.slot;
+        swap_state.activate_pending_fee(slot);
+
+        // Curve "sell_out_for_in" (Bancor "saleTargetAmount") or "sell_in_for_exact_out"
             // Token supply in circulation + initial 1(*10^9) token.
-            let token_supply = (((spl_token::state::Mint::unpack(&mint_info.data.borrow())?).supply as u64).checked_add(1000000000u64).ok_or(VisionError::Overflow)?) as f64;
+            let token_supply = mint_state.supply.checked_add(1000000000u64).ok_or(VisionError::Overflow)?;
             // Reserve Balance - Rent payed for Rent exemption
-            let reserve_balance = (pda_associated_sol_info.lamports().checked_sub((((Rent::get()?).minimum_balance(0 as usize)) as u64)).ok_or(VisionError::Overflow)?) as f64;
-            // sol received if input is amount_in
-            let sol_amt_from_token_input = (reserve_balance * (1f64 - (1f64 - (amount_in as f64) / token_supply).powf(1f64 / 0.60976f64)));
-            msg!("sol_amt_from_token_input {:?}",sol_amt_from_token_input);
-        // FEES
-            let provider_fee = ((sol_amt_from_token_input as f64) * 0.01f64);
-
-        let adjusted_sol_amt_from_token_input = (sol_amt_from_token_input - provider_fee) as u64;
-        msg!("adjusted_sol_amt_from_token_input {:?}",adjusted_sol_amt_from_token_input);
-    
+            let reserve_balance = Self::unpack_reserve(program_id, pda_info, &swap_state, pda_associated_sol_info)?;
+            // Tokens burned, sol released and fees
+            let SellAmt {
+                token_amt: amount_in,
+                sol_amt: sol_amt_from_token_input,
+                adjusted_sol_amt: adjusted_sol_amt_from_token_input,
+                fee_provider: provider_fee
+            } = Self::sell_amounts(&swap_state, config.provider_fee_bps, token_supply, reserve_balance, &limit)?;
     
     // Checks
 
         // Check output
-            if amount_in > (spl_token::state::Account::unpack(&payer_associated_token_address_info.data.borrow())?).amount {
+            if amount_in > (token::unpack_account(&payer_associated_token_address_info.data.borrow())?).amount {
                 return Err(VisionError::BalanceTooSmall.into());
             }
-            if (sol_amt_from_token_input as u64) > ((reserve_balance as u64).checked_sub(36u64).ok_or(VisionError::Overflow)?){
+            if sol_amt_from_token_input > reserve_balance.checked_sub(36u64).ok_or(VisionError::Overflow)?{
                 return Err(VisionError::ReserveError.into());
             }
-            if adjusted_sol_amt_from_token_input < minimum_amount_out {
-                return Err(VisionError::ExceededSlippage.into());
+            match limit {
+                SellLimit::ExactIn { minimum_amount_out, .. } => {
+                    if adjusted_sol_amt_from_token_input < minimum_amount_out {
+                        return Err(VisionError::ExceededSlippage.into());
+                    }
+                }
+                SellLimit::ExactOut { maximum_tokens_in, .. } => {
+                    if amount_in > maximum_tokens_in {
+                        return Err(VisionError::ExceededSlippage.into());
+                    }
+                }
             }
         // Accounts
             if !payer_info.is_signer {
@@ -420,11 +1031,14 @@ impl Processor {
             if *payer_info.owner != system_program::ID {
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
             }
-            
-            if *payer_associated_token_address_info.owner != spl_token::ID {
+            // Token accounts and the mint are owned by the token program passed
+            if !token::is_token_program(token_program_info.key) {
+                return Err(VisionError::InvalidProgramAddress.into());
+            }
+            if *payer_associated_token_address_info.owner != *token_program_info.key {
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
             }
-            if *payer_associated_token_address_info.key != spl_associated_token_account::get_associated_token_address(payer_info.key, mint_info.key){
+            if *payer_associated_token_address_info.key != token::get_associated_token_address(payer_info.key, mint_info.key, token_program_info.key){
                 return Err(VisionError::InvalidAccountAddress.into());
             }
 
@@ -436,14 +1050,20 @@ impl Processor {
             }
             
 
-            if *pda_associated_sol_info.key != (Pubkey::create_program_address(&[&pda_info.key.to_bytes(), &[swap_state.bump_seed_sol]], program_id)?) {
-                return Err(VisionError::InvalidAccountAddress.into());
-            }
-            if *pda_associated_sol_info.owner != system_program::ID{
-                return Err(VisionError::InvalidAccountOnwerProgram.into());
-            }
-
-            if *mint_info.owner != spl_token::ID {
+            // The reserve is checked by unpack_reserve, reserve tokens move with the reserve mint's token program
+            let reserve_decimals = match reserve_accounts {
+                Some((seller_reserve_info, provider_fee_collector_reserve_info, reserve_mint_info, reserve_token_program_info)) => {
+                    let decimals = Self::unpack_reserve_mint(&swap_state, reserve_mint_info, reserve_token_program_info)?;
+                    if *pda_associated_sol_info.owner != *reserve_token_program_info.key || *seller_reserve_info.owner != *reserve_token_program_info.key {
+                        return Err(VisionError::InvalidAccountOnwerProgram.into());
+                    }
+                    Self::check_reserve_account(&swap_state, provider_fee_collector_reserve_info, &config.provider_fee_collector, reserve_token_program_info.key)?;
+                    decimals
+                }
+                None => 0,
+            };
+
+            if *mint_info.owner != *token_program_info.key {
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
             }
             if !mint_state.is_initialized {
@@ -456,7 +1076,7 @@ impl Processor {
             if *provider_fee_collector_info.owner != system_program::ID{
                 return Err(VisionError::InvalidAccountOnwerProgram.into());
             }
-            if *provider_fee_collector_info.key != PROVIDER_FEE_COLLECTOR_ID {
+            if *provider_fee_collector_info.key != config.provider_fee_collector {
                 return Err(VisionError::InvalidAccountAddress.into());
             }
 
@@ -464,54 +1084,107 @@ impl Processor {
                 return Err(VisionError::InvalidProgramAddress.into());
             }
 
-            if *token_program_info.key != spl_token::ID{
-                return Err(VisionError::InvalidProgramAddress.into());
+            let observations = match observations_info {
+                Some(observations_info) => Some((observations_info, Self::unpack_observations(program_id, pda_info.key, &swap_state, observations_info)?)),
+                None => None,
+            };
+
+        // Quote
+            if quote_only {
+                let (price_after, price_impact) = Self::price_impact(
+                    &swap_state,
+                    token_supply,
+                    reserve_balance,
+                    token_supply.checked_sub(amount_in).ok_or(VisionError::Overflow)?,
+                    reserve_balance.checked_sub(sol_amt_from_token_input).ok_or(VisionError::Overflow)?
+                )?;
+                return Self::return_quote(Quote {
+                    amount_in,
+                    amount_out: adjusted_sol_amt_from_token_input,
+                    page_fee: 0,
+                    provider_fee,
+                    price_after,
+                    price_impact
+                });
             }
 
     // EXECUTION
 
-            invoke_signed(
-                &system_instruction::transfer(
-                    pda_associated_sol_info.key,
-                    provider_fee_collector_info.key,
-                    provider_fee as u64
-                ),
-                &[
-                    system_program_info.clone(),
-                    pda_associated_sol_info.clone(),
-                    provider_fee_collector_info.clone()
-                ],
-                &[&[
-                    &pda_info.key.to_bytes(),
-                    &[swap_state.bump_seed_sol]
-                ]]
-            )?;
-    
-            invoke_signed(
-                &system_instruction::transfer(
-                    pda_associated_sol_info.key,
-                    payer_info.key,
-                    adjusted_sol_amt_from_token_input as u64
-                ),
-                &[
-                    system_program_info.clone(),
-                    pda_associated_sol_info.clone(),
-                    payer_info.clone()
-                ],
-                &[&[
-                    &pda_info.key.to_bytes(),
-                    &[swap_state.bump_seed_sol]
-                ]]
-            )?;
+            match reserve_accounts {
+                // The pool owns its reserve token account
+                Some((seller_reserve_info, provider_fee_collector_reserve_info, reserve_mint_info, reserve_token_program_info)) => {
+                    for (destination_info, amount) in [
+                        (provider_fee_collector_reserve_info, provider_fee),
+                        (seller_reserve_info, adjusted_sol_amt_from_token_input)
+                    ].iter() {
+                        invoke_signed(
+                            &token::transfer_checked(
+                                reserve_token_program_info.key,
+                                pda_associated_sol_info.key,
+                                reserve_mint_info.key,
+                                destination_info.key,
+                                pda_info.key,
+                                *amount,
+                                reserve_decimals
+                            )?,
+                            &[
+                                reserve_token_program_info.clone(),
+                                pda_associated_sol_info.clone(),
+                                reserve_mint_info.clone(),
+                                (*destination_info).clone(),
+                                pda_info.clone()
+                            ],
+                            &[&[
+                                &mint_info.key.to_bytes(),
+                                &[swap_state.bump_seed]
+                            ]]
+                        )?;
+                    }
+                }
+                None => {
+                    invoke_signed(
+                        &system_instruction::transfer(
+                            pda_associated_sol_info.key,
+                            provider_fee_collector_info.key,
+                            provider_fee
+                        ),
+                        &[
+                            system_program_info.clone(),
+                            pda_associated_sol_info.clone(),
+                            provider_fee_collector_info.clone()
+                        ],
+                        &[&[
+                            &pda_info.key.to_bytes(),
+                            &[swap_state.bump_seed_sol]
+                        ]]
+                    )?;
+
+                    invoke_signed(
+                        &system_instruction::transfer(
+                            pda_associated_sol_info.key,
+                            payer_info.key,
+                            adjusted_sol_amt_from_token_input
+                        ),
+                        &[
+                            system_program_info.clone(),
+                            pda_associated_sol_info.clone(),
+                            payer_info.clone()
+                        ],
+                        &[&[
+                            &pda_info.key.to_bytes(),
+                            &[swap_state.bump_seed_sol]
+                        ]]
+                    )?;
+                }
+            }
     
             invoke(
-                &spl_token::instruction::burn(
+                &token::burn(
                     token_program_info.key,
                     payer_associated_token_address_info.key,
                     mint_info.key,
                     payer_info.key,
-                    &[],
-                    amount_in as u64
+                    amount_in
                 )?,
                 &[
                     token_program_info.clone(),
@@ -520,6 +1193,37 @@ impl Processor {
                     payer_info.clone()
                 ]
             )?;
+
+        let token_supply_after = token_supply.checked_sub(amount_in).ok_or(VisionError::Overflow)?;
+        let reserve_balance_after = reserve_balance.checked_sub(sol_amt_from_token_input).ok_or(VisionError::Overflow)?;
+        let spot_price = Self::spot_price(&swap_state, token_supply_after, reserve_balance_after)?;
+
+        // Price oracle and pool statistics
+        swap_state.record_price(slot, spot_price);
+        swap_state.record_sell(adjusted_sol_amt_from_token_input, provider_fee, slot)?;
+        let supply_after = mint_state.supply.checked_sub(amount_in).ok_or(VisionError::Overflow)?;
+        if let Some((observations_info, observations)) = observations {
+            Self::write_observation(observations_info, observations, PriceSample {
+                slot,
+                price_cumulative: swap_state.price_cumulative,
+                supply: supply_after,
+                reserve_balance: reserve_balance_after
+            })?;
+        }
+        PageTokenSwap::pack_versioned(swap_state, &mut pda_info.data.borrow_mut())?;
+
+        VisionEvent::Sell(TradeEvent {
+            mint: *mint_info.key,
+            trader: *payer_info.key,
+            amount_in,
+            amount_out: adjusted_sol_amt_from_token_input,
+            page_fee: 0,
+            provider_fee,
+            supply: supply_after,
+            reserve_balance: reserve_balance_after,
+            spot_price,
+        }).emit();
+
         Ok(())
     }
 
@@ -532,25 +1236,24 @@ impl Processor {
 
     // Accounts
 
-        let fee_collector_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
         let new_fee_collector_info = next_account_info(account_info_iter)?;
         let pda_info = next_account_info(account_info_iter)?;
         let mint_info = next_account_info(account_info_iter)?;
         let system_program_info = next_account_info(account_info_iter)?;
+        let config_info = next_account_info(account_info_iter)?;
 
     // Variables
-        let mut swap_state = PageTokenSwap::unpack(&pda_info.data.borrow())?;
-        let mint_state = spl_token::state::Mint::unpack(&mint_info.data.borrow())?;
+        let config = Self::unpack_config(program_id, config_info)?;
+        let mut swap_state = PageTokenSwap::unpack_versioned(&pda_info.data.borrow())?;
+        let mint_state = token::unpack_mint(&mint_info.data.borrow())?;
     // Checks
-        if !fee_collector_info.is_signer {
-            return Err(VisionError::InvalidFeeAccount.into());
+        if !authority_info.is_signer || *authority_info.key != swap_state.authority {
+            return Err(VisionError::InvalidAuthority.into());
         }
-        if *fee_collector_info.owner != system_program::ID{
+        if *authority_info.owner != system_program::ID{
             return Err(VisionError::InvalidAccountOnwerProgram.into());
         }
-        if *fee_collector_info.key != swap_state.fee_collector_pubkey{
-            return Err(VisionError::InvalidAccountAddress.into());
-        }
 
         if (new_fee_collector_info.lamports() > 0) && (*new_fee_collector_info.owner != system_program::ID){
             return Err(VisionError::InvalidAccountOnwerProgram.into());
@@ -563,7 +1266,7 @@ impl Processor {
             return Err(VisionError::InvalidAccountAddress.into());
         }
 
-        if *mint_info.owner != spl_token::ID {
+        if !token::is_token_program(mint_info.owner) {
             return Err(VisionError::InvalidAccountOnwerProgram.into());
         }
         if !mint_state.is_initialized {
@@ -580,12 +1283,12 @@ impl Processor {
         if new_fee_collector_info.lamports() == 0 {
             invoke(
                 &system_instruction::transfer(
-                    fee_collector_info.key,
+                    authority_info.key,
                     new_fee_collector_info.key,
-                    (Rent::get()?).minimum_balance(0 as usize),
+                    (Rent::get()?).minimum_balance(0),
                 ),
                 &[
-                    fee_collector_info.clone(),
+                    authority_info.clone(),
                     new_fee_collector_info.clone(),
                     system_program_info.clone()
                 ]
@@ -593,45 +1296,797 @@ impl Processor {
         }
 
     // EXECUTION
-        if (fee_collector_info.key != new_fee_collector_info.key){
-            swap_state.fee_collector_pubkey = *new_fee_collector_info.key;
-        }
+        swap_state.fee_collector_pubkey = *new_fee_collector_info.key;
 
-        if (fee != swap_state.fee){
-            if (fee > 50000) || (fee < 0){
-                return Err(VisionError::InvalidFee.into());
-            }else{
-                swap_state.fee = fee;
-            }
+        // Fee changes are queued, a change that already applies is moved to fee first
+        let slot = Clock::get()?.slot;
+        swap_state.activate_pending_fee(slot);
+        if fee != swap_state.fee && (fee < config.min_page_fee || fee > config.max_page_fee) {
+            return Err(VisionError::InvalidFee.into());
         }
-
-        PageTokenSwap::pack(swap_state, &mut pda_info.data.borrow_mut())?;
+        swap_state.queue_fee(fee, slot)?;
+
+        let event = ChangeFeeEvent {
+            mint: *mint_info.key,
+            fee_collector: swap_state.fee_collector_pubkey,
+            fee: swap_state.fee,
+            pending_fee: swap_state.pending_fee,
+            pending_fee_slot: swap_state.pending_fee_slot,
+        };
+        PageTokenSwap::pack_versioned(swap_state, &mut pda_info.data.borrow_mut())?;
+
+        VisionEvent::ChangeFee(event).emit();
     
         Ok(())
     }
 
-    
+    pub fn set_pause_state(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pause_state: PauseState,
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
 
-    pub fn process(
+    // Accounts
+        let authority_info = next_account_info(account_info_iter)?;
+        let pda_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+
+    // Variables
+        let mut swap_state = Self::unpack_pool(program_id, pda_info, mint_info)?;
+
+    // Checks
+        if !authority_info.is_signer || *authority_info.key != swap_state.authority {
+            return Err(VisionError::InvalidAuthority.into());
+        }
+
+    // EXECUTION
+        swap_state.pause_state = pause_state;
+        PageTokenSwap::pack_versioned(swap_state, &mut pda_info.data.borrow_mut())?;
+
+        VisionEvent::SetPauseState(PauseStateEvent { mint: *mint_info.key, pause_state }).emit();
+
+        Ok(())
+    }
+
+    pub fn withdraw_withheld_fees(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        instruction_data: &[u8]
-    ) -> ProgramResult {
-        let instruction = VisionInstruction::unpack(instruction_data)?;
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
 
-        match instruction {
-            VisionInstruction::Initialize() => {
-                Self::initialize_page_token(program_id, accounts)
-            }
-            VisionInstruction::Buy(Amount { amount_in, minimum_amount_out }) => {
-                Self::buy(program_id, accounts, amount_in, minimum_amount_out)
-            }
-            VisionInstruction::Sell(Amount { amount_in, minimum_amount_out }) => {
-                Self::sell(program_id, accounts, amount_in, minimum_amount_out)
-            }
+    // Accounts
+        let pda_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+        let fee_collector_token_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let source_infos = account_info_iter.as_slice();
+
+    // Variables
+        let swap_state = Self::unpack_pool(program_id, pda_info, mint_info)?;
+
+    // Checks
+        if *token_program_info.key != token::token_2022::id() {
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+        if *mint_info.owner != token::token_2022::id() {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        if !token::has_extension(&mint_info.data.borrow(), ExtensionType::TransferFeeConfig)? {
+            return Err(VisionError::InvalidMint.into());
+        }
+        // Follows ChangeFee, fees withheld before a change go to the new fee collector
+        if *fee_collector_token_info.key != token::get_associated_token_address(&swap_state.fee_collector_pubkey, mint_info.key, token_program_info.key) {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+
+    // EXECUTION
+        let seeds: &[&[u8]] = &[&mint_info.key.to_bytes(), &[swap_state.bump_seed]];
+        invoke_signed(
+            &token::withdraw_withheld_tokens_from_mint(mint_info.key, fee_collector_token_info.key, pda_info.key)?,
+            &[
+                token_program_info.clone(),
+                mint_info.clone(),
+                fee_collector_token_info.clone(),
+                pda_info.clone()
+            ],
+            &[seeds]
+        )?;
+        if !source_infos.is_empty() {
+            let sources: Vec<&Pubkey> = source_infos.iter().map(|source_info| source_info.key).collect();
+            let mut infos = vec![
+                token_program_info.clone(),
+                mint_info.clone(),
+                fee_collector_token_info.clone(),
+                pda_info.clone()
+            ];
+            infos.extend(source_infos.iter().cloned());
+            invoke_signed(
+                &token::withdraw_withheld_tokens_from_accounts(mint_info.key, fee_collector_token_info.key, pda_info.key, &sources)?,
+                &infos,
+                &[seeds]
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn migrate_pool(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+
+    // Accounts
+        let payer_info = next_account_info(account_info_iter)?;
+        let pda_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+
+    // Checks
+        Self::check_payer(payer_info)?;
+        let mut swap_state = Self::unpack_pool(program_id, pda_info, mint_info)?;
+        if pda_info.data_len() != PageTokenSwap::BASELINE_LEN {
+            return Err(VisionError::AlreadyInUse.into());
+        }
+        if *system_program_info.key != system_program::ID{
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+
+    // EXECUTION
+        let lamports = (Rent::get()?).minimum_balance(PageTokenSwap::LEN).saturating_sub(pda_info.lamports());
+        if lamports > 0 {
+            invoke(
+                &system_instruction::transfer(payer_info.key, pda_info.key, lamports),
+                &[
+                    payer_info.clone(),
+                    pda_info.clone(),
+                    system_program_info.clone()
+                ]
+            )?;
+        }
+        pda_info.realloc(PageTokenSwap::LEN, true)?;
+
+        swap_state.version = PageTokenSwap::VERSION;
+        PageTokenSwap::pack_versioned(swap_state, &mut pda_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    pub fn set_authority(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        step: AuthorityStep,
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+
+    // Accounts
+        let signer_info = next_account_info(account_info_iter)?;
+        let pda_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+
+    // Variables
+        let mut swap_state = Self::unpack_pool(program_id, pda_info, mint_info)?;
+
+    // Checks and EXECUTION
+        if !signer_info.is_signer {
+            return Err(VisionError::InvalidAuthority.into());
+        }
+        match step {
+            AuthorityStep::Propose => {
+                let new_authority_info = next_account_info(account_info_iter)?;
+                if *signer_info.key != swap_state.authority {
+                    return Err(VisionError::InvalidAuthority.into());
+                }
+                swap_state.pending_authority = *new_authority_info.key;
+            }
+            AuthorityStep::Accept => {
+                // Pubkey::default() can't sign, no transfer is pending
+                if *signer_info.key != swap_state.pending_authority {
+                    return Err(VisionError::InvalidAuthority.into());
+                }
+                swap_state.authority = swap_state.pending_authority;
+                swap_state.pending_authority = Pubkey::default();
+            }
+        }
+
+        let event = AuthorityEvent {
+            mint: *mint_info.key,
+            authority: swap_state.authority,
+            pending_authority: swap_state.pending_authority,
+        };
+        PageTokenSwap::pack_versioned(swap_state, &mut pda_info.data.borrow_mut())?;
+
+        VisionEvent::SetAuthority(event).emit();
+
+        Ok(())
+    }
+
+    pub fn update_metadata(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        token_metadata: TokenMetadata,
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+
+    // Accounts
+        let authority_info = next_account_info(account_info_iter)?;
+        let pda_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+        let metadata_info = next_account_info(account_info_iter)?;
+        let metadata_program_info = next_account_info(account_info_iter)?;
+
+    // Variables
+        let swap_state = Self::unpack_pool(program_id, pda_info, mint_info)?;
+        let data = Self::metadata_data(token_metadata)?;
+
+    // Checks
+        if !authority_info.is_signer || *authority_info.key != swap_state.authority {
+            return Err(VisionError::InvalidAuthority.into());
+        }
+        Self::check_metadata_accounts(mint_info.key, metadata_info, metadata_program_info)?;
+
+    // EXECUTION
+        invoke_signed(
+            &metadata::update_metadata_account_v2(metadata_info.key, pda_info.key, data),
+            &[
+                metadata_info.clone(),
+                pda_info.clone(),
+                metadata_program_info.clone()
+            ],
+            &[&[
+                &mint_info.key.to_bytes(),
+                &[swap_state.bump_seed]
+            ]]
+        )?;
+
+        Ok(())
+    }
+
+    pub fn initialize_config(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        provider_fee_bps: u16,
+        min_page_fee: u16,
+        max_page_fee: u16,
+        pause_state: PauseState
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+
+    // Accounts
+        let payer_info = next_account_info(account_info_iter)?;
+        let config_info = next_account_info(account_info_iter)?;
+        let provider_fee_collector_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let program_data_info = next_account_info(account_info_iter)?;
+
+    // Variables
+        let (config_pda, bump_seed) = Pubkey::find_program_address(&[ProgramConfig::SEED], program_id);
+        let (program_data, _) = Pubkey::find_program_address(&[&program_id.to_bytes()], &bpf_loader_upgradeable::id());
+
+    // Checks
+        Self::check_config_values(provider_fee_bps, min_page_fee, max_page_fee)?;
+
+        if !payer_info.is_signer {
+            return Err(VisionError::SignatureRequired.into());
+        }
+        if *payer_info.owner != system_program::ID {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        // Only the upgrade authority can claim the config, anyone else could front-run it after deployment
+        if *program_data_info.key != program_data {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        if *program_data_info.owner != bpf_loader_upgradeable::id() {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        match bincode::deserialize(&program_data_info.data.borrow()) {
+            Ok(UpgradeableLoaderState::ProgramData { upgrade_authority_address: Some(authority), .. }) if authority == *payer_info.key => {}
+            _ => return Err(VisionError::InvalidAdmin.into()),
+        }
+
+        if config_info.lamports() > 0 {
+            return Err(VisionError::AlreadyInUse.into());
+        }
+        if *config_info.key != config_pda {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+
+        if (provider_fee_collector_info.lamports() > 0) && (*provider_fee_collector_info.owner != system_program::ID){
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+
+        if *system_program_info.key != system_program::ID{
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+
+    // EXECUTION
+        invoke_signed(
+            &system_instruction::create_account(
+                payer_info.key,
+                config_info.key,
+                (Rent::get()?).minimum_balance(ProgramConfig::LEN),
+                ProgramConfig::LEN as u64,
+                program_id,
+            ),
+            &[
+                payer_info.clone(),
+                config_info.clone(),
+                system_program_info.clone()
+            ],
+            &[&[
+                ProgramConfig::SEED,
+                &[bump_seed]
+            ]]
+        )?;
+
+        let config = ProgramConfig {
+            is_initialized: true,
+            version: ProgramConfig::VERSION,
+            bump_seed,
+            admin: *payer_info.key,
+            provider_fee_collector: *provider_fee_collector_info.key,
+            provider_fee_bps,
+            min_page_fee,
+            max_page_fee,
+            pause_state
+        };
+        ProgramConfig::pack(config, &mut config_info.data.borrow_mut())?;
+
+        Ok(())
+    }
+
+    pub fn update_config(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        provider_fee_bps: u16,
+        min_page_fee: u16,
+        max_page_fee: u16,
+        pause_state: PauseState
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+
+    // Accounts
+        let admin_info = next_account_info(account_info_iter)?;
+        let config_info = next_account_info(account_info_iter)?;
+        let new_admin_info = next_account_info(account_info_iter)?;
+        let new_provider_fee_collector_info = next_account_info(account_info_iter)?;
+
+    // Variables
+        let mut config = Self::unpack_config(program_id, config_info)?;
+
+    // Checks
+        Self::check_config_values(provider_fee_bps, min_page_fee, max_page_fee)?;
+
+        if !admin_info.is_signer {
+            return Err(VisionError::InvalidAdmin.into());
+        }
+        if *admin_info.key != config.admin {
+            return Err(VisionError::InvalidAdmin.into());
+        }
+
+        if (new_provider_fee_collector_info.lamports() > 0) && (*new_provider_fee_collector_info.owner != system_program::ID){
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+
+    // EXECUTION
+        config.admin = *new_admin_info.key;
+        config.provider_fee_collector = *new_provider_fee_collector_info.key;
+        config.provider_fee_bps = provider_fee_bps;
+        config.min_page_fee = min_page_fee;
+        config.max_page_fee = max_page_fee;
+        config.pause_state = pause_state;
+        ProgramConfig::pack(config, &mut config_info.data.borrow_mut())?;
+
+        Ok(())
+    }
+
+    /// Provider fee up to MAX_PROVIDER_FEE_BPS, min_page_fee <= max_page_fee <= MAX_PAGE_FEE
+    fn check_config_values(provider_fee_bps: u16, min_page_fee: u16, max_page_fee: u16) -> Result<(), ProgramError> {
+        if provider_fee_bps > MAX_PROVIDER_FEE_BPS || min_page_fee > max_page_fee || max_page_fee > MAX_PAGE_FEE {
+            return Err(VisionError::InvalidConfig.into());
+        }
+        Ok(())
+    }
+
+    /// Unpacks the config after checking its owner and address
+    fn unpack_config(program_id: &Pubkey, config_info: &AccountInfo) -> Result<ProgramConfig, ProgramError> {
+        if *config_info.owner != *program_id {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        let config = ProgramConfig::unpack(&config_info.data.borrow()).map_err(|_| VisionError::InvalidConfig)?;
+        if *config_info.key != (Pubkey::create_program_address(&[ProgramConfig::SEED, &[config.bump_seed]], program_id)?) {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        Ok(config)
+    }
+
+    pub fn view(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        side: TradeSide,
+        amount: u64
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+    // Accounts
+        let pda_info = next_account_info(account_info_iter)?;
+        let pda_associated_sol_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+    // Checks
+        if *pda_info.owner != *program_id{
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        let swap_state = PageTokenSwap::unpack_versioned(&pda_info.data.borrow())?;
+        if *pda_info.key != (Pubkey::create_program_address(&[&mint_info.key.to_bytes(), &[swap_state.bump_seed]], program_id)?) {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        let reserve_balance = Self::unpack_reserve(program_id, pda_info, &swap_state, pda_associated_sol_info)?;
+        if !token::is_token_program(mint_info.owner) {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        let mint_state = token::unpack_mint(&mint_info.data.borrow())?;
+    // Variables
+        let supply = mint_state.supply;
+        let price_after = match side {
+            TradeSide::Buy => swap_state.price_after_buy(supply, reserve_balance, amount)?,
+            TradeSide::Sell => swap_state.price_after_sell(supply, reserve_balance, amount)?,
+        };
+        let view = PoolView {
+            supply,
+            reserve_balance,
+            spot_price: swap_state.spot_price(supply, reserve_balance)?,
+            market_cap: swap_state.market_cap(supply, reserve_balance)?,
+            fully_diluted_value: swap_state.fully_diluted_value(supply, reserve_balance)?,
+            price_after
+        };
+
+        let mut data = [0u8; PoolView::LEN];
+        PoolView::pack(view, &mut data)?;
+        set_return_data(&data);
+        Ok(())
+    }
+
+    
+
+    pub fn initialize_observations(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        capacity: u32
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+    // Accounts
+        let payer_info = next_account_info(account_info_iter)?;
+        let pda_info = next_account_info(account_info_iter)?;
+        let observations_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+    // Checks
+        if capacity == 0 || capacity > ObservationBuffer::MAX_CAPACITY {
+            return Err(VisionError::InvalidInput.into());
+        }
+        Self::check_payer(payer_info)?;
+        let mut swap_state = Self::unpack_pool(program_id, pda_info, mint_info)?;
+        // Trades have to pass the buffer once it exists, only the pool authority opts the pool in
+        if !authority_info.is_signer || *authority_info.key != swap_state.authority {
+            return Err(VisionError::InvalidAuthority.into());
+        }
+        if *system_program_info.key != system_program::ID{
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+    // EXECUTION
+        let buffer = Self::create_observations(program_id, payer_info, pda_info.key, observations_info, system_program_info, swap_state.observations_generation, capacity)?;
+        ObservationBuffer::pack(buffer, &mut observations_info.data.borrow_mut()[..ObservationBuffer::LEN])?;
+
+        swap_state.has_observations = true;
+        PageTokenSwap::pack_versioned(swap_state, &mut pda_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    pub fn grow_observations(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        capacity: u32
+    ) -> Result<(), ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+    // Accounts
+        let payer_info = next_account_info(account_info_iter)?;
+        let pda_info = next_account_info(account_info_iter)?;
+        let observations_info = next_account_info(account_info_iter)?;
+        let new_observations_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+    // Checks
+        Self::check_payer(payer_info)?;
+        let mut swap_state = Self::unpack_pool(program_id, pda_info, mint_info)?;
+        let observations = Self::unpack_observations(program_id, pda_info.key, &swap_state, observations_info)?;
+        if capacity <= observations.capacity || capacity > ObservationBuffer::MAX_CAPACITY {
+            return Err(VisionError::InvalidInput.into());
+        }
+        if !authority_info.is_signer || *authority_info.key != swap_state.authority {
+            return Err(VisionError::InvalidAuthority.into());
+        }
+        // The buffer of the generation after the wrap around was closed long before
+        let generation = swap_state.observations_generation.wrapping_add(1);
+        if *system_program_info.key != system_program::ID{
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+    // EXECUTION
+        // Copy the samples, oldest first
+        let mut buffer = Self::create_observations(program_id, payer_info, pda_info.key, new_observations_info, system_program_info, generation, capacity)?;
+        for sample in observations.samples(&observations_info.data.borrow())? {
+            buffer.push(&mut new_observations_info.data.borrow_mut(), sample)?;
+        }
+        ObservationBuffer::pack(buffer, &mut new_observations_info.data.borrow_mut()[..ObservationBuffer::LEN])?;
+
+        // Close the current buffer, its rent goes to the pool authority
+        let lamports = observations_info.lamports();
+        **authority_info.lamports.borrow_mut() = authority_info.lamports().checked_add(lamports).ok_or(VisionError::Overflow)?;
+        **observations_info.lamports.borrow_mut() = 0;
+        observations_info.data.borrow_mut().iter_mut().for_each(|byte| *byte = 0);
+
+        swap_state.observations_generation = generation;
+        PageTokenSwap::pack_versioned(swap_state, &mut pda_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Rejects trades executed after their deadline, the deadline itself is still valid
+    fn check_deadline(deadline: Deadline) -> ProgramResult {
+        let clock = Clock::get()?;
+        let expired = match deadline {
+            Deadline::Slot(slot) => clock.slot > slot,
+            Deadline::UnixTimestamp(unix_timestamp) => clock.unix_timestamp > unix_timestamp,
+        };
+        if expired {
+            return Err(VisionError::DeadlineExceeded.into());
+        }
+        Ok(())
+    }
+
+    fn check_payer(payer_info: &AccountInfo) -> Result<(), ProgramError> {
+        if !payer_info.is_signer {
+            return Err(VisionError::SignatureRequired.into());
+        }
+        if *payer_info.owner != system_program::ID {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        Ok(())
+    }
+
+    /// Metaplex data of a page token, without royalties, creators, collection or uses
+    fn metadata_data(token_metadata: TokenMetadata) -> Result<metadata::DataV2, ProgramError> {
+        let TokenMetadata { name, symbol, uri } = token_metadata;
+        if name.len() > metadata::MAX_NAME_LENGTH || symbol.len() > metadata::MAX_SYMBOL_LENGTH || uri.len() > metadata::MAX_URI_LENGTH {
+            return Err(VisionError::InvalidInput.into());
+        }
+        Ok(metadata::DataV2 { name, symbol, uri, seller_fee_basis_points: 0, creators: None, collection: None, uses: None })
+    }
+
+    fn check_metadata_accounts(mint: &Pubkey, metadata_info: &AccountInfo, metadata_program_info: &AccountInfo) -> ProgramResult {
+        if *metadata_program_info.key != metadata::id() {
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+        if *metadata_info.key != metadata::find_metadata_address(mint).0 {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        Ok(())
+    }
+
+    /// Unpacks the pool of a mint after checking its owner and address
+    fn unpack_pool(program_id: &Pubkey, pda_info: &AccountInfo, mint_info: &AccountInfo) -> Result<PageTokenSwap, ProgramError> {
+        if *pda_info.owner != *program_id{
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        let swap_state = PageTokenSwap::unpack_versioned(&pda_info.data.borrow())?;
+        if *pda_info.key != (Pubkey::create_program_address(&[&mint_info.key.to_bytes(), &[swap_state.bump_seed]], program_id)?) {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        Ok(swap_state)
+    }
+
+    /// Checks the account holding the reserve of a pool and returns the reserve balance: the lamports
+    /// of pda_sol above rent, or the amount of the pool's reserve token account
+    fn unpack_reserve(program_id: &Pubkey, pda_info: &AccountInfo, swap_state: &PageTokenSwap, reserve_info: &AccountInfo) -> Result<u64, ProgramError> {
+        if !swap_state.has_token_reserve() {
+            if *reserve_info.key != (Pubkey::create_program_address(&[&pda_info.key.to_bytes(), &[swap_state.bump_seed_sol]], program_id)?) {
+                return Err(VisionError::InvalidAccountAddress.into());
+            }
+            if *reserve_info.owner != system_program::ID{
+                return Err(VisionError::InvalidAccountOnwerProgram.into());
+            }
+            return Ok(reserve_info.lamports().checked_sub((Rent::get()?).minimum_balance(0)).ok_or(VisionError::Overflow)?);
+        }
+        if !token::is_token_program(reserve_info.owner) {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        if *reserve_info.key != token::get_associated_token_address(pda_info.key, &swap_state.reserve_mint, reserve_info.owner) {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        Ok(token::unpack_account(&reserve_info.data.borrow())?.amount)
+    }
+
+    /// Checks the reserve mint of a pool with a reserve mint and its token program, returns its decimals
+    fn unpack_reserve_mint(swap_state: &PageTokenSwap, reserve_mint_info: &AccountInfo, reserve_token_program_info: &AccountInfo) -> Result<u8, ProgramError> {
+        if *reserve_mint_info.key != swap_state.reserve_mint {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        if !token::is_token_program(reserve_token_program_info.key) {
+            return Err(VisionError::InvalidProgramAddress.into());
+        }
+        if *reserve_mint_info.owner != *reserve_token_program_info.key {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        Ok(token::unpack_mint(&reserve_mint_info.data.borrow())?.decimals)
+    }
+
+    /// Checks that `info` is the associated token account of `wallet` for the pool's reserve mint
+    fn check_reserve_account(swap_state: &PageTokenSwap, info: &AccountInfo, wallet: &Pubkey, token_program: &Pubkey) -> ProgramResult {
+        if *info.owner != *token_program {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        if *info.key != token::get_associated_token_address(wallet, &swap_state.reserve_mint, token_program) {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        Ok(())
+    }
+
+    /// Creates the ObservationBuffer PDA of a generation, returns its empty header
+    fn create_observations<'a>(
+        program_id: &Pubkey,
+        payer_info: &AccountInfo<'a>,
+        pda: &Pubkey,
+        observations_info: &AccountInfo<'a>,
+        system_program_info: &AccountInfo<'a>,
+        generation: u8,
+        capacity: u32
+    ) -> Result<ObservationBuffer, ProgramError> {
+        let (observations_pda, bump_seed) = Pubkey::find_program_address(&[ObservationBuffer::SEED, &pda.to_bytes(), &[generation]], program_id);
+        if observations_info.lamports() > 0 {
+            return Err(VisionError::AlreadyInUse.into());
+        }
+        if *observations_info.key != observations_pda {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+
+        let space = ObservationBuffer::space(capacity);
+        invoke_signed(
+            &system_instruction::create_account(
+                payer_info.key,
+                observations_info.key,
+                (Rent::get()?).minimum_balance(space),
+                space as u64,
+                program_id,
+            ),
+            &[
+                payer_info.clone(),
+                observations_info.clone(),
+                system_program_info.clone()
+            ],
+            &[&[
+                ObservationBuffer::SEED,
+                &pda.to_bytes(),
+                &[generation],
+                &[bump_seed]
+            ]]
+        )?;
+
+        Ok(ObservationBuffer {
+            is_initialized: true,
+            version: ObservationBuffer::VERSION,
+            bump_seed,
+            generation,
+            pool: *pda,
+            capacity,
+            len: 0,
+            head: 0
+        })
+    }
+
+    /// Unpacks the current ObservationBuffer of a pool after checking its owner and address
+    fn unpack_observations(
+        program_id: &Pubkey,
+        pda: &Pubkey,
+        swap_state: &PageTokenSwap,
+        observations_info: &AccountInfo
+    ) -> Result<ObservationBuffer, ProgramError> {
+        if *observations_info.owner != *program_id {
+            return Err(VisionError::InvalidAccountOnwerProgram.into());
+        }
+        let observations = {
+            let data = observations_info.data.borrow();
+            ObservationBuffer::unpack(data.get(..ObservationBuffer::LEN).ok_or(ProgramError::InvalidAccountData)?)?
+        };
+        if observations.pool != *pda || observations.generation != swap_state.observations_generation {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        let seeds: &[&[u8]] = &[ObservationBuffer::SEED, &pda.to_bytes(), &[observations.generation], &[observations.bump_seed]];
+        if *observations_info.key != Pubkey::create_program_address(seeds, program_id)? {
+            return Err(VisionError::InvalidAccountAddress.into());
+        }
+        Ok(observations)
+    }
+
+    fn write_observation(observations_info: &AccountInfo, mut observations: ObservationBuffer, sample: PriceSample) -> ProgramResult {
+        let mut data = observations_info.data.borrow_mut();
+        observations.push(&mut data, sample)?;
+        ObservationBuffer::pack(observations, &mut data[..ObservationBuffer::LEN])
+    }
+
+    pub fn process(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8]
+    ) -> ProgramResult {
+        let instruction = VisionInstruction::unpack(instruction_data)?;
+
+        match instruction {
+            VisionInstruction::Initialize(Curve { reserve_ratio, curve_type, curve_parameters }, options) => {
+                Self::initialize_page_token(program_id, accounts, reserve_ratio, curve_type, curve_parameters, options)
+            }
+            VisionInstruction::Buy(Amount { amount_in, minimum_amount_out }) => {
+                Self::buy(program_id, accounts, amount_in, minimum_amount_out)
+            }
+            VisionInstruction::BuyExactOut(ExactTokenOut { token_amount_out, maximum_amount_in }) => {
+                Self::buy_exact_out(program_id, accounts, token_amount_out, maximum_amount_in)
+            }
+            VisionInstruction::Sell(Amount { amount_in, minimum_amount_out }) => {
+                Self::sell(program_id, accounts, amount_in, minimum_amount_out)
+            }
+            VisionInstruction::SellExactOut(ExactLamportsOut { lamports_out, maximum_tokens_in }) => {
+                Self::sell_exact_out(program_id, accounts, lamports_out, maximum_tokens_in)
+            }
+            VisionInstruction::Quote(QuoteRequest { side, amount, exact_in }) => {
+                Self::quote(program_id, accounts, side, amount, exact_in)
+            }
             VisionInstruction::ChangeFee(Fee { fee }) => {
                 Self::change_page_fee(program_id, accounts, fee)
             }
+            VisionInstruction::InitializeConfig(Config { provider_fee_bps, min_page_fee, max_page_fee, pause_state }) => {
+                Self::initialize_config(program_id, accounts, provider_fee_bps, min_page_fee, max_page_fee, pause_state)
+            }
+            VisionInstruction::UpdateConfig(Config { provider_fee_bps, min_page_fee, max_page_fee, pause_state }) => {
+                Self::update_config(program_id, accounts, provider_fee_bps, min_page_fee, max_page_fee, pause_state)
+            }
+            VisionInstruction::View(ViewRequest { side, amount }) => {
+                Self::view(program_id, accounts, side, amount)
+            }
+            VisionInstruction::BuyWithDeadline(Amount { amount_in, minimum_amount_out }, deadline) => {
+                Self::check_deadline(deadline)?;
+                Self::buy(program_id, accounts, amount_in, minimum_amount_out)
+            }
+            VisionInstruction::SellWithDeadline(Amount { amount_in, minimum_amount_out }, deadline) => {
+                Self::check_deadline(deadline)?;
+                Self::sell(program_id, accounts, amount_in, minimum_amount_out)
+            }
+            VisionInstruction::BuyExactOutWithDeadline(ExactTokenOut { token_amount_out, maximum_amount_in }, deadline) => {
+                Self::check_deadline(deadline)?;
+                Self::buy_exact_out(program_id, accounts, token_amount_out, maximum_amount_in)
+            }
+            VisionInstruction::SellExactOutWithDeadline(ExactLamportsOut { lamports_out, maximum_tokens_in }, deadline) => {
+                Self::check_deadline(deadline)?;
+                Self::sell_exact_out(program_id, accounts, lamports_out, maximum_tokens_in)
+            }
+            VisionInstruction::InitializeObservations(ObservationCapacity { capacity }) => {
+                Self::initialize_observations(program_id, accounts, capacity)
+            }
+            VisionInstruction::GrowObservations(ObservationCapacity { capacity }) => {
+                Self::grow_observations(program_id, accounts, capacity)
+            }
+            VisionInstruction::SetPauseState(pause_state) => {
+                Self::set_pause_state(program_id, accounts, pause_state)
+            }
+            VisionInstruction::SetAuthority(step) => {
+                Self::set_authority(program_id, accounts, step)
+            }
+            VisionInstruction::UpdateMetadata(token_metadata) => {
+                Self::update_metadata(program_id, accounts, token_metadata)
+            }
+            VisionInstruction::WithdrawWithheldFees => {
+                Self::withdraw_withheld_fees(program_id, accounts)
+            }
+            VisionInstruction::MigratePool => {
+                Self::migrate_pool(program_id, accounts)
+            }
         }
     }
 }
@@ -659,7 +2114,20 @@ impl PrintProgramError for VisionError {
             },
             VisionError::InvalidInput => msg!("Error: Invalid User Input"),
             VisionError::ReserveError => msg!("Error: Reserve error"),
-            VisionError::BalanceTooSmall => msg!("Error: Balance too small")
+            VisionError::BalanceTooSmall => msg!("Error: Balance too small"),
+            VisionError::InvalidReserveRatio => msg!("Error: Invalid reserve ratio"),
+            VisionError::InvalidCurve => msg!("Error: Invalid curve type or parameters"),
+            VisionError::InvalidInstruction => msg!("Error: Invalid instruction"),
+            VisionError::Overflow => msg!("Error: Overflow"),
+            VisionError::InvalidFee => msg!("Error: Invalid fee"),
+            VisionError::InvalidFeeAccount => msg!("Error: Invalid fee account"),
+            VisionError::InvalidConfig => msg!("Error: Invalid config"),
+            VisionError::Paused => msg!("Error: Trading paused"),
+            VisionError::InvalidAdmin => msg!("Error: Invalid admin"),
+            VisionError::DeadlineExceeded => msg!("Error: Trade submitted after its deadline"),
+            VisionError::InvalidAuthority => msg!("Error: Invalid authority"),
+            VisionError::FeeStepExceeded => msg!("Error: Fee step exceeded"),
+            VisionError::OutdatedPoolLayout => msg!("Error: Outdated pool layout")
         }
     }
 }
\ No newline at end of file
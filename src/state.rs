@@ -1,13 +1,21 @@
 use solana_program::{
-    msg,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
-    pubkey::{Pubkey, PUBKEY_BYTES},
+    pubkey::Pubkey,
 };
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSerialize};
+use crate::{
+    curve::{self, calculator::{CurveCalculator, PRICE_SCALE, TOKEN_PRECISION}, math::{self, U256}},
+    error::VisionError,
+};
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct PageTokenSwap {
     pub is_initialized: bool,
 
+    /// Layout version of the account data, see `PageTokenSwap::VERSION`
+    pub version: u8,
+
     /// PDA with Bump Seed -> Save Bump seed in Account and use it in subsequent public Key validation
     /// with "create_program_address([-Mint PubKey-, bump_seed], programId)"; 
     /// bump_seed -> client side "findProgramAddress([-Mint PublicKey-], programId)"
@@ -20,14 +28,519 @@ pub struct PageTokenSwap {
     pub fee: u16,
 
     /// Page Creator/Fee collector that will receive fee
-    pub fee_collector_pubkey: Pubkey
+    pub fee_collector_pubkey: Pubkey,
+
+    /// Bancor connector weight in ppm (1000000 = 100%), see `curve::bancor::MAX_RESERVE_RATIO`
+    pub reserve_ratio: u32,
+
+    /// Curve used to price trades, see `curve::CurveType`
+    pub curve_type: u8,
+
+    /// Parameters of non Bancor curves, meaning depends on `curve_type`
+    pub curve_parameters: [u64; 2],
+
+    // Lifetime statistics, see `PageTokenSwap::stats`
+
+    /// Lamports paid by buyers, fees included
+    pub sol_volume_in: u128,
+
+    /// Lamports paid to sellers, fees excluded
+    pub sol_volume_out: u128,
+
+    /// Lamports paid to the page fee collector
+    pub page_fees: u128,
+
+    /// Lamports paid to the provider fee collector
+    pub provider_fees: u128,
+
+    /// Buys and sells executed
+    pub trade_count: u64,
+
+    /// Slot of the last buy or sell, 0 before the first trade
+    pub last_trade_slot: u64,
+
+    // Price oracle, see `PageTokenSwap::observe`
+
+    /// Sum of the spot price times the slots it was in effect, wraps around on overflow
+    pub price_cumulative: u128,
+
+    /// Slot price_cumulative was last updated at
+    pub price_cumulative_slot: u64,
+
+    /// Spot price since price_cumulative_slot
+    pub last_price: u128,
+
+    /// Generation of the pool's ObservationBuffer, bumped by GrowObservations. Wraps around, the
+    /// buffer of a generation is closed when the next one is created.
+    pub observations_generation: u8,
+
+    /// Set by the authority, trades also need `ProgramConfig::pause_state` to allow them
+    pub pause_state: PauseState,
+
+    /// Changes the fee, the fee collector and the pause state. Starts as the fee collector
+    pub authority: Pubkey,
+
+    /// Proposed by the authority with SetAuthority, becomes the authority once it accepts.
+    /// Pubkey::default() if none
+    pub pending_authority: Pubkey,
+
+    /// Fee queued by ChangeFee, replaces `fee` from pending_fee_slot on, see `PageTokenSwap::queue_fee`
+    pub pending_fee: u16,
+
+    /// Slot pending_fee applies from, 0 if no change is queued
+    pub pending_fee_slot: u64,
+
+    /// Mint of the reserve, Pubkey::default() for native SOL held by pda_sol. Otherwise the reserve is
+    /// the pool's associated token account and the lamport amounts of the pool are in reserve tokens.
+    pub reserve_mint: Pubkey,
+
+    /// Set by InitializeObservations, Buy and Sell then have to sample into the ObservationBuffer
+    pub has_observations: bool,
+}
+
+/// Trading state of a pool, or of every pool in `ProgramConfig`.
+/// Saved as a u8: Active and Paused have the values of the former `paused: bool`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum PauseState {
+    #[default]
+    Active,
+    /// Buy and sell are disabled
+    Paused,
+    /// Only sells are enabled, holders can still withdraw their lamports
+    WithdrawOnly,
+}
+
+impl PauseState {
+    pub fn from_u8(value: u8) -> Result<Self, ProgramError> {
+        match value {
+            0 => Ok(PauseState::Active),
+            1 => Ok(PauseState::Paused),
+            2 => Ok(PauseState::WithdrawOnly),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    pub fn allows_buy(self) -> bool {
+        self == PauseState::Active
+    }
+
+    pub fn allows_sell(self) -> bool {
+        self != PauseState::Paused
+    }
+}
+
+/// Lifetime statistics of a pool, see the fields of `PageTokenSwap`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolStats {
+    pub sol_volume_in: u128,
+    pub sol_volume_out: u128,
+    pub page_fees: u128,
+    pub provider_fees: u128,
+    pub trade_count: u64,
+    pub last_trade_slot: u64
+}
+
+/// Cumulative price of a pool at a slot, see `PageTokenSwap::observe`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Observation {
+    pub slot: u64,
+    pub price_cumulative: u128
+}
+
+impl Observation {
+    /// Time weighted average spot price between this and a later observation, scaled like
+    /// `CurveCalculator::spot_price`. None if `later` is not at a later slot.
+    pub fn twap(&self, later: &Observation) -> Option<u128> {
+        if later.slot <= self.slot {
+            return None;
+        }
+        // price_cumulative wraps around, the difference is correct as long as it fits into a u128
+        Some(later.price_cumulative.wrapping_sub(self.price_cumulative) / (later.slot - self.slot) as u128)
+    }
+}
+
+impl PageTokenSwap {
+    /// Layout version of new pools. New fields take the reserved bytes at the end of the account,
+    /// their default has to be all zeros, so adding a field doesn't bump the version.
+    pub const VERSION: u8 = 1;
+
+    /// Account size of pools created before the layout had a version, "version 0":
+    /// [is_initialized, bump_seed, bump_seed_sol, fee, fee_collector_pubkey]. MigratePool grows them
+    /// to `PageTokenSwap::LEN`.
+    pub const BASELINE_LEN: usize = 37;
+
+    /// Unpacks a pool of the current or the baseline layout. Baseline pools are Bancor pools with
+    /// `DEFAULT_RESERVE_RATIO` and the fee collector as authority, their other fields get their default.
+    pub fn unpack_versioned(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != Self::BASELINE_LEN {
+            return Self::unpack(input);
+        }
+        let input = array_ref![input, 0, PageTokenSwap::BASELINE_LEN];
+        let(
+            is_initialized,
+            bump_seed,
+            bump_seed_sol,
+            fee,
+            fee_collector_pubkey
+        ) = array_refs![input, 1, 1, 1, 2, 32];
+        let fee_collector_pubkey = Pubkey::new_from_array(*fee_collector_pubkey);
+        Ok(PageTokenSwap {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            version: 0,
+            bump_seed: bump_seed[0],
+            bump_seed_sol: bump_seed_sol[0],
+            fee: u16::from_le_bytes(*fee),
+            fee_collector_pubkey,
+            reserve_ratio: curve::bancor::DEFAULT_RESERVE_RATIO,
+            authority: fee_collector_pubkey,
+            ..PageTokenSwap::default()
+        })
+    }
+
+    /// Packs a pool into an account of the current or the baseline layout. Baseline pools drop the
+    /// statistics and the price accumulator, any other field that differs from what `unpack_versioned`
+    /// reads back fails with `OutdatedPoolLayout`.
+    pub fn pack_versioned(swap_state: PageTokenSwap, output: &mut [u8]) -> Result<(), ProgramError> {
+        if output.len() != Self::BASELINE_LEN {
+            return Self::pack(swap_state, output);
+        }
+        let mut data = [0; PageTokenSwap::BASELINE_LEN];
+        let(
+            is_initialized_dst,
+            bump_seed_dst,
+            bump_seed_sol_dst,
+            fee_dst,
+            fee_collector_pubkey_dst
+        ) = mut_array_refs![&mut data, 1, 1, 1, 2, 32];
+        is_initialized_dst[0] = swap_state.is_initialized as u8;
+        bump_seed_dst[0] = swap_state.bump_seed;
+        bump_seed_sol_dst[0] = swap_state.bump_seed_sol;
+        *fee_dst = swap_state.fee.to_le_bytes();
+        fee_collector_pubkey_dst.copy_from_slice(swap_state.fee_collector_pubkey.as_ref());
+
+        let stored = PageTokenSwap {
+            version: 0,
+            sol_volume_in: 0,
+            sol_volume_out: 0,
+            page_fees: 0,
+            provider_fees: 0,
+            trade_count: 0,
+            last_trade_slot: 0,
+            price_cumulative: 0,
+            price_cumulative_slot: 0,
+            last_price: 0,
+            ..swap_state
+        };
+        if Self::unpack_versioned(&data)? != stored {
+            return Err(VisionError::OutdatedPoolLayout.into());
+        }
+        output.copy_from_slice(&data);
+        Ok(())
+    }
+
+    /// Token supply the curve starts from. Never minted, trades are priced at mint supply + VIRTUAL_SUPPLY.
+    pub const VIRTUAL_SUPPLY: u64 = 1_000_000_000;
+
+    /// Slots between ChangeFee and the new fee applying to trades, about a day
+    pub const FEE_CHANGE_DELAY_SLOTS: u64 = 216_000;
+
+    /// Largest move of the page fee per ChangeFee (2.5%), on the (x/100000) scale of `fee`
+    pub const MAX_FEE_STEP: u16 = 2500;
+
+    /// Calculator of the curve saved in the pool
+    pub fn curve_calculator(&self) -> Result<Box<dyn CurveCalculator>, ProgramError> {
+        Ok(curve::curve_calculator(self.curve_type, self.reserve_ratio, &self.curve_parameters)?)
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            sol_volume_in: self.sol_volume_in,
+            sol_volume_out: self.sol_volume_out,
+            page_fees: self.page_fees,
+            provider_fees: self.provider_fees,
+            trade_count: self.trade_count,
+            last_trade_slot: self.last_trade_slot
+        }
+    }
+
+    /// Adds a buy to the statistics, `amount_in` includes both fees
+    pub fn record_buy(&mut self, amount_in: u64, page_fee: u64, provider_fee: u64, slot: u64) -> Result<(), VisionError> {
+        self.sol_volume_in = self.sol_volume_in.checked_add(amount_in as u128).ok_or(VisionError::Overflow)?;
+        self.page_fees = self.page_fees.checked_add(page_fee as u128).ok_or(VisionError::Overflow)?;
+        self.record_trade(provider_fee, slot)
+    }
+
+    /// Adds a sell to the statistics, `amount_out` excludes the provider fee
+    pub fn record_sell(&mut self, amount_out: u64, provider_fee: u64, slot: u64) -> Result<(), VisionError> {
+        self.sol_volume_out = self.sol_volume_out.checked_add(amount_out as u128).ok_or(VisionError::Overflow)?;
+        self.record_trade(provider_fee, slot)
+    }
+
+    /// Accumulates the last price up to `slot` and sets `price` as the spot price from there on
+    pub fn record_price(&mut self, slot: u64, price: u128) {
+        let elapsed = slot.saturating_sub(self.price_cumulative_slot);
+        self.price_cumulative = self.price_cumulative.wrapping_add(self.last_price.wrapping_mul(elapsed as u128));
+        self.price_cumulative_slot = slot;
+        self.last_price = price;
+    }
+
+    /// Cumulative price at `slot`, without waiting for the next trade to update it
+    pub fn observe(&self, slot: u64) -> Observation {
+        let elapsed = slot.saturating_sub(self.price_cumulative_slot);
+        Observation {
+            slot: slot.max(self.price_cumulative_slot),
+            price_cumulative: self.price_cumulative.wrapping_add(self.last_price.wrapping_mul(elapsed as u128))
+        }
+    }
+
+    /// Whether the reserve is held in tokens of reserve_mint instead of lamports
+    pub fn has_token_reserve(&self) -> bool {
+        self.reserve_mint != Pubkey::default()
+    }
+
+    /// Page fee trades pay at `slot`, pending_fee once it applies
+    pub fn fee_at(&self, slot: u64) -> u16 {
+        if self.pending_fee_slot != 0 && slot >= self.pending_fee_slot {
+            self.pending_fee
+        } else {
+            self.fee
+        }
+    }
+
+    /// Moves pending_fee to fee once it applies at `slot`
+    pub fn activate_pending_fee(&mut self, slot: u64) {
+        self.fee = self.fee_at(slot);
+        if self.pending_fee_slot != 0 && slot >= self.pending_fee_slot {
+            self.pending_fee = 0;
+            self.pending_fee_slot = 0;
+        }
+    }
+
+    /// Queues `fee` to apply FEE_CHANGE_DELAY_SLOTS after `slot`, at most MAX_FEE_STEP away from
+    /// the current fee. The current fee cancels a queued change and the queued fee keeps its slot.
+    pub fn queue_fee(&mut self, fee: u16, slot: u64) -> Result<(), VisionError> {
+        self.activate_pending_fee(slot);
+        if fee == self.fee {
+            self.pending_fee = 0;
+            self.pending_fee_slot = 0;
+            return Ok(());
+        }
+        if self.pending_fee_slot != 0 && fee == self.pending_fee {
+            return Ok(());
+        }
+        if fee.max(self.fee) - fee.min(self.fee) > Self::MAX_FEE_STEP {
+            return Err(VisionError::FeeStepExceeded);
+        }
+        self.pending_fee = fee;
+        self.pending_fee_slot = slot.checked_add(Self::FEE_CHANGE_DELAY_SLOTS).ok_or(VisionError::Overflow)?;
+        Ok(())
+    }
+
+    fn record_trade(&mut self, provider_fee: u64, slot: u64) -> Result<(), VisionError> {
+        self.provider_fees = self.provider_fees.checked_add(provider_fee as u128).ok_or(VisionError::Overflow)?;
+        self.trade_count = self.trade_count.checked_add(1).ok_or(VisionError::Overflow)?;
+        self.last_trade_slot = slot;
+        Ok(())
+    }
+
+    // Views, usable on- and off-chain. `mint_supply` is the supply of the page token mint and
+    // `reserve_balance` the lamports of the pool's sol account minus its rent exemption.
+
+    /// Price of one whole token in lamports, scaled by `curve::calculator::PRICE_SCALE`
+    pub fn spot_price(&self, mint_supply: u64, reserve_balance: u64) -> Result<u128, ProgramError> {
+        let supply = Self::curve_supply(mint_supply)?;
+        Ok(self.curve_calculator()?.spot_price(supply, reserve_balance).ok_or(VisionError::Overflow)?)
+    }
+
+    /// Lamports the minted tokens are worth at the spot price
+    pub fn market_cap(&self, mint_supply: u64, reserve_balance: u64) -> Result<u128, ProgramError> {
+        Self::value(self.spot_price(mint_supply, reserve_balance)?, mint_supply)
+    }
+
+    /// Lamports the minted and the virtual supply are worth at the spot price
+    pub fn fully_diluted_value(&self, mint_supply: u64, reserve_balance: u64) -> Result<u128, ProgramError> {
+        Self::value(self.spot_price(mint_supply, reserve_balance)?, Self::curve_supply(mint_supply)?)
+    }
+
+    /// Spot price after depositing `amount_in` lamports into the reserve, fees excluded
+    pub fn price_after_buy(&self, mint_supply: u64, reserve_balance: u64, amount_in: u64) -> Result<u128, ProgramError> {
+        let calculator = self.curve_calculator()?;
+        let supply = Self::curve_supply(mint_supply)?;
+        let amount_out = calculator.buy_out_for_in(supply, reserve_balance, amount_in).ok_or(VisionError::Overflow)?;
+        let supply_after = supply.checked_add(amount_out).ok_or(VisionError::Overflow)?;
+        let reserve_balance_after = reserve_balance.checked_add(amount_in).ok_or(VisionError::Overflow)?;
+        Ok(calculator.spot_price(supply_after, reserve_balance_after).ok_or(VisionError::Overflow)?)
+    }
+
+    /// Spot price after burning `amount_in` tokens, fees excluded
+    pub fn price_after_sell(&self, mint_supply: u64, reserve_balance: u64, amount_in: u64) -> Result<u128, ProgramError> {
+        if amount_in > mint_supply {
+            return Err(VisionError::InvalidInput.into());
+        }
+        let calculator = self.curve_calculator()?;
+        let supply = Self::curve_supply(mint_supply)?;
+        let amount_out = calculator.sell_out_for_in(supply, reserve_balance, amount_in).ok_or(VisionError::Overflow)?;
+        let reserve_balance_after = reserve_balance.checked_sub(amount_out).ok_or(VisionError::ReserveError)?;
+        Ok(calculator.spot_price(supply - amount_in, reserve_balance_after).ok_or(VisionError::Overflow)?)
+    }
+
+    fn curve_supply(mint_supply: u64) -> Result<u64, VisionError> {
+        mint_supply.checked_add(Self::VIRTUAL_SUPPLY).ok_or(VisionError::Overflow)
+    }
+
+    /// Lamports `amount` tokens are worth at `spot_price`, rounded down
+    fn value(spot_price: u128, amount: u64) -> Result<u128, ProgramError> {
+        let value = U256::from(spot_price) * U256::from(amount) / (U256::from(PRICE_SCALE) * U256::from(TOKEN_PRECISION));
+        Ok(math::to_u128(value).ok_or(VisionError::Overflow)?)
+    }
+}
+
+/// Singleton program configuration, PDA with seed `ProgramConfig::SEED`
+pub struct ProgramConfig {
+    pub is_initialized: bool,
+
+    /// Layout version of the account data, see `ProgramConfig::VERSION`
+    pub version: u8,
+
+    /// Pda bump seed of the config account
+    pub bump_seed: u8,
+
+    /// Only key allowed to update the config
+    pub admin: Pubkey,
+
+    /// Receives the provider fee of every buy and sell
+    pub provider_fee_collector: Pubkey,
+
+    /// Provider fee in basis points (100 = 1%)
+    pub provider_fee_bps: u16,
+
+    /// Bounds of the page fee (x/100000) a pool can be set to
+    pub min_page_fee: u16,
+    pub max_page_fee: u16,
+
+    /// Trading state of every pool, applies on top of `PageTokenSwap::pause_state`
+    pub pause_state: PauseState
+}
+
+impl ProgramConfig {
+    /// Current layout version. Bumped whenever fields are added to the account.
+    pub const VERSION: u8 = 1;
+
+    /// Seed of the config PDA: find_program_address([SEED], programId)
+    pub const SEED: &'static [u8] = b"config";
+}
+
+impl Sealed for ProgramConfig {}
+impl IsInitialized for ProgramConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for ProgramConfig {
+    const LEN: usize = 74;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, ProgramConfig::LEN];
+        let(
+            is_initialized_dst,
+            version_dst,
+            bump_seed_dst,
+            admin_dst,
+            provider_fee_collector_dst,
+            provider_fee_bps_dst,
+            min_page_fee_dst,
+            max_page_fee_dst,
+            pause_state_dst
+        ) = mut_array_refs![output, 1, 1, 1, 32, 32, 2, 2, 2, 1];
+
+        let ProgramConfig {
+            is_initialized,
+            version,
+            bump_seed,
+            admin,
+            provider_fee_collector,
+            provider_fee_bps,
+            min_page_fee,
+            max_page_fee,
+            pause_state
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        version_dst[0] = *version;
+        bump_seed_dst[0] = *bump_seed;
+        admin_dst.copy_from_slice(admin.as_ref());
+        provider_fee_collector_dst.copy_from_slice(provider_fee_collector.as_ref());
+        *provider_fee_bps_dst = provider_fee_bps.to_le_bytes();
+        *min_page_fee_dst = min_page_fee.to_le_bytes();
+        *max_page_fee_dst = max_page_fee.to_le_bytes();
+        pause_state_dst[0] = *pause_state as u8;
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, ProgramConfig::LEN];
+        let(
+            is_initialized,
+            version,
+            bump_seed,
+            admin,
+            provider_fee_collector,
+            provider_fee_bps,
+            min_page_fee,
+            max_page_fee,
+            pause_state
+        ) = array_refs![input, 1, 1, 1, 32, 32, 2, 2, 2, 1];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        // Uninitialized accounts are all zeros
+        let version = match (is_initialized, version[0]) {
+            (false, 0) => 0,
+            (true, ProgramConfig::VERSION) => ProgramConfig::VERSION,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(ProgramConfig{
+            is_initialized,
+            version,
+            bump_seed: bump_seed[0],
+            admin: Pubkey::new_from_array(*admin),
+            provider_fee_collector: Pubkey::new_from_array(*provider_fee_collector),
+            provider_fee_bps: u16::from_le_bytes(*provider_fee_bps),
+            min_page_fee: u16::from_le_bytes(*min_page_fee),
+            max_page_fee: u16::from_le_bytes(*max_page_fee),
+            pause_state: PauseState::from_u8(pause_state[0])?
+        })
+    }
 }
 
+/// Result of a buy calculation, all amounts in lamports except token_amt
 pub struct BuyAmt {
-    pub adjusted_amount_in: u128,
-    pub token_amt: u128,
-    pub fee_page: u128,
-    pub fee_provider: u128
+    /// Lamports spent by the buyer, fees included
+    pub amount_in: u64,
+    /// Lamports deposited into the reserve
+    pub adjusted_amount_in: u64,
+    /// Tokens minted to the buyer
+    pub token_amt: u64,
+    pub fee_page: u64,
+    pub fee_provider: u64
+}
+
+/// Result of a sell calculation, all amounts in lamports except token_amt
+pub struct SellAmt {
+    /// Tokens burned by the seller
+    pub token_amt: u64,
+    /// Lamports released from the reserve, fees included
+    pub sol_amt: u64,
+    /// Lamports paid to the seller
+    pub adjusted_sol_amt: u64,
+    pub fee_provider: u64
 }
 
 impl Sealed for PageTokenSwap {}
@@ -38,54 +551,589 @@ impl IsInitialized for PageTokenSwap {
 }
 
 impl Pack for PageTokenSwap {
-    const LEN: usize = 37;
+    /// Layout of `PageTokenSwap::VERSION`, the last 63 bytes are reserved for new fields
+    const LEN: usize = 351;
 
     fn pack_into_slice(&self, output: &mut [u8]) {
         let output = array_mut_ref![output, 0, PageTokenSwap::LEN];
         let(
             is_initialized_dst,
+            version_dst,
             bump_seed_dst,
             bump_seed_sol_dst,
             fee_dst,
-            fee_collector_pubkey_dst
-        ) = mut_array_refs![output, 1, 1, 1, 2, 32];
+            fee_collector_pubkey_dst,
+            reserve_ratio_dst,
+            curve_type_dst,
+            curve_parameter_a_dst,
+            curve_parameter_b_dst,
+            sol_volume_in_dst,
+            sol_volume_out_dst,
+            page_fees_dst,
+            provider_fees_dst,
+            trade_count_dst,
+            last_trade_slot_dst,
+            price_cumulative_dst,
+            price_cumulative_slot_dst,
+            last_price_dst,
+            observations_generation_dst,
+            pause_state_dst,
+            authority_dst,
+            pending_authority_dst,
+            pending_fee_dst,
+            pending_fee_slot_dst,
+            reserve_mint_dst,
+            has_observations_dst,
+            reserved_dst
+        ) = mut_array_refs![output, 1, 1, 1, 1, 2, 32, 4, 1, 8, 8, 16, 16, 16, 16, 8, 8, 16, 8, 16, 1, 1, 32, 32, 2, 8, 32, 1, 63];
 
         let PageTokenSwap {
             is_initialized,
+            version,
             bump_seed,
             bump_seed_sol,
             fee,
-            fee_collector_pubkey
+            fee_collector_pubkey,
+            reserve_ratio,
+            curve_type,
+            curve_parameters,
+            sol_volume_in,
+            sol_volume_out,
+            page_fees,
+            provider_fees,
+            trade_count,
+            last_trade_slot,
+            price_cumulative,
+            price_cumulative_slot,
+            last_price,
+            observations_generation,
+            pause_state,
+            authority,
+            pending_authority,
+            pending_fee,
+            pending_fee_slot,
+            reserve_mint,
+            has_observations
         } = self;
 
         is_initialized_dst[0] = *is_initialized as u8;
+        version_dst[0] = *version;
         bump_seed_dst[0] = *bump_seed;
         bump_seed_sol_dst[0] = *bump_seed_sol;
         *fee_dst = fee.to_le_bytes();
         fee_collector_pubkey_dst.copy_from_slice(fee_collector_pubkey.as_ref());
+        *reserve_ratio_dst = reserve_ratio.to_le_bytes();
+        curve_type_dst[0] = *curve_type;
+        *curve_parameter_a_dst = curve_parameters[0].to_le_bytes();
+        *curve_parameter_b_dst = curve_parameters[1].to_le_bytes();
+        *sol_volume_in_dst = sol_volume_in.to_le_bytes();
+        *sol_volume_out_dst = sol_volume_out.to_le_bytes();
+        *page_fees_dst = page_fees.to_le_bytes();
+        *provider_fees_dst = provider_fees.to_le_bytes();
+        *trade_count_dst = trade_count.to_le_bytes();
+        *last_trade_slot_dst = last_trade_slot.to_le_bytes();
+        *price_cumulative_dst = price_cumulative.to_le_bytes();
+        *price_cumulative_slot_dst = price_cumulative_slot.to_le_bytes();
+        *last_price_dst = last_price.to_le_bytes();
+        observations_generation_dst[0] = *observations_generation;
+        pause_state_dst[0] = *pause_state as u8;
+        authority_dst.copy_from_slice(authority.as_ref());
+        pending_authority_dst.copy_from_slice(pending_authority.as_ref());
+        *pending_fee_dst = pending_fee.to_le_bytes();
+        *pending_fee_slot_dst = pending_fee_slot.to_le_bytes();
+        reserve_mint_dst.copy_from_slice(reserve_mint.as_ref());
+        has_observations_dst[0] = *has_observations as u8;
+        *reserved_dst = [0; 63];
     }
 
     fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
         let input = array_ref![input, 0, PageTokenSwap::LEN];
         let(
             is_initialized,
+            version,
             bump_seed,
             bump_seed_sol,
             fee,
-            fee_collector_pubkey
-        ) = array_refs![input, 1, 1, 1, 2, 32];
+            fee_collector_pubkey,
+            reserve_ratio,
+            curve_type,
+            curve_parameter_a,
+            curve_parameter_b,
+            sol_volume_in,
+            sol_volume_out,
+            page_fees,
+            provider_fees,
+            trade_count,
+            last_trade_slot,
+            price_cumulative,
+            price_cumulative_slot,
+            last_price,
+            observations_generation,
+            pause_state,
+            authority,
+            pending_authority,
+            pending_fee,
+            pending_fee_slot,
+            reserve_mint,
+            has_observations,
+            _reserved
+        ) = array_refs![input, 1, 1, 1, 1, 2, 32, 4, 1, 8, 8, 16, 16, 16, 16, 8, 8, 16, 8, 16, 1, 1, 32, 32, 2, 8, 32, 1, 63];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
             _ => return Err(ProgramError::InvalidAccountData),
         };
+        // Uninitialized accounts are all zeros
+        let version = match (is_initialized, version[0]) {
+            (false, 0) => 0,
+            (true, PageTokenSwap::VERSION) => PageTokenSwap::VERSION,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
 
         Ok(PageTokenSwap{
             is_initialized,
+            version,
             bump_seed: bump_seed[0],
             bump_seed_sol: bump_seed_sol[0],
             fee: u16::from_le_bytes(*fee),
-            fee_collector_pubkey: Pubkey::new_from_array(*fee_collector_pubkey)
+            fee_collector_pubkey: Pubkey::new_from_array(*fee_collector_pubkey),
+            reserve_ratio: u32::from_le_bytes(*reserve_ratio),
+            curve_type: curve_type[0],
+            curve_parameters: [
+                u64::from_le_bytes(*curve_parameter_a),
+                u64::from_le_bytes(*curve_parameter_b)
+            ],
+            sol_volume_in: u128::from_le_bytes(*sol_volume_in),
+            sol_volume_out: u128::from_le_bytes(*sol_volume_out),
+            page_fees: u128::from_le_bytes(*page_fees),
+            provider_fees: u128::from_le_bytes(*provider_fees),
+            trade_count: u64::from_le_bytes(*trade_count),
+            last_trade_slot: u64::from_le_bytes(*last_trade_slot),
+            price_cumulative: u128::from_le_bytes(*price_cumulative),
+            price_cumulative_slot: u64::from_le_bytes(*price_cumulative_slot),
+            last_price: u128::from_le_bytes(*last_price),
+            observations_generation: observations_generation[0],
+            pause_state: PauseState::from_u8(pause_state[0])?,
+            authority: Pubkey::new_from_array(*authority),
+            pending_authority: Pubkey::new_from_array(*pending_authority),
+            pending_fee: u16::from_le_bytes(*pending_fee),
+            pending_fee_slot: u64::from_le_bytes(*pending_fee_slot),
+            reserve_mint: Pubkey::new_from_array(*reserve_mint),
+            has_observations: match has_observations {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            }
+        })
+    }
+}
+
+/// Result of the Quote instruction, returned via return data
+pub struct Quote {
+    /// Lamports spent (buy) or tokens burned (sell), fees included
+    pub amount_in: u64,
+    /// Tokens minted (buy) or lamports paid to the seller (sell)
+    pub amount_out: u64,
+    pub page_fee: u64,
+    pub provider_fee: u64,
+    /// Spot price after the trade, lamports per token scaled by `curve::calculator::PRICE_SCALE`
+    pub price_after: u128,
+    /// Change of the spot price caused by the trade, in ppm
+    pub price_impact: u64
+}
+
+impl Sealed for Quote {}
+
+impl Pack for Quote {
+    const LEN: usize = 56;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, Quote::LEN];
+        let(
+            amount_in_dst,
+            amount_out_dst,
+            page_fee_dst,
+            provider_fee_dst,
+            price_after_dst,
+            price_impact_dst
+        ) = mut_array_refs![output, 8, 8, 8, 8, 16, 8];
+
+        *amount_in_dst = self.amount_in.to_le_bytes();
+        *amount_out_dst = self.amount_out.to_le_bytes();
+        *page_fee_dst = self.page_fee.to_le_bytes();
+        *provider_fee_dst = self.provider_fee.to_le_bytes();
+        *price_after_dst = self.price_after.to_le_bytes();
+        *price_impact_dst = self.price_impact.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, Quote::LEN];
+        let(
+            amount_in,
+            amount_out,
+            page_fee,
+            provider_fee,
+            price_after,
+            price_impact
+        ) = array_refs![input, 8, 8, 8, 8, 16, 8];
+
+        Ok(Quote {
+            amount_in: u64::from_le_bytes(*amount_in),
+            amount_out: u64::from_le_bytes(*amount_out),
+            page_fee: u64::from_le_bytes(*page_fee),
+            provider_fee: u64::from_le_bytes(*provider_fee),
+            price_after: u128::from_le_bytes(*price_after),
+            price_impact: u64::from_le_bytes(*price_impact)
+        })
+    }
+}
+
+/// Result of the View instruction, returned via return data.
+/// Prices are lamports per token scaled by `curve::calculator::PRICE_SCALE`, values are lamports.
+pub struct PoolView {
+    /// Supply of the page token mint, without the virtual supply
+    pub supply: u64,
+    /// Lamports backing the supply, rent excluded
+    pub reserve_balance: u64,
+    pub spot_price: u128,
+    /// Minted supply at the spot price
+    pub market_cap: u128,
+    /// Minted and virtual supply at the spot price
+    pub fully_diluted_value: u128,
+    /// Spot price after the requested trade, fees excluded
+    pub price_after: u128
+}
+
+impl Sealed for PoolView {}
+
+impl Pack for PoolView {
+    const LEN: usize = 80;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, PoolView::LEN];
+        let(
+            supply_dst,
+            reserve_balance_dst,
+            spot_price_dst,
+            market_cap_dst,
+            fully_diluted_value_dst,
+            price_after_dst
+        ) = mut_array_refs![output, 8, 8, 16, 16, 16, 16];
+
+        *supply_dst = self.supply.to_le_bytes();
+        *reserve_balance_dst = self.reserve_balance.to_le_bytes();
+        *spot_price_dst = self.spot_price.to_le_bytes();
+        *market_cap_dst = self.market_cap.to_le_bytes();
+        *fully_diluted_value_dst = self.fully_diluted_value.to_le_bytes();
+        *price_after_dst = self.price_after.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, PoolView::LEN];
+        let(
+            supply,
+            reserve_balance,
+            spot_price,
+            market_cap,
+            fully_diluted_value,
+            price_after
+        ) = array_refs![input, 8, 8, 16, 16, 16, 16];
+
+        Ok(PoolView {
+            supply: u64::from_le_bytes(*supply),
+            reserve_balance: u64::from_le_bytes(*reserve_balance),
+            spot_price: u128::from_le_bytes(*spot_price),
+            market_cap: u128::from_le_bytes(*market_cap),
+            fully_diluted_value: u128::from_le_bytes(*fully_diluted_value),
+            price_after: u128::from_le_bytes(*price_after)
         })
     }
-}
\ No newline at end of file
+}
+
+/// Ring buffer of price samples of a pool, PDA with seeds
+/// `[ObservationBuffer::SEED, pool, [generation]]`. The header is followed by `capacity` PriceSamples.
+/// Accounts can't be resized, GrowObservations moves the samples to the PDA of the next generation.
+pub struct ObservationBuffer {
+    pub is_initialized: bool,
+
+    /// Layout version of the account data, see `ObservationBuffer::VERSION`
+    pub version: u8,
+
+    /// Pda bump seed of the buffer
+    pub bump_seed: u8,
+
+    /// Generation the PDA is derived with, see `PageTokenSwap::observations_generation`
+    pub generation: u8,
+
+    /// Pool the samples are taken from
+    pub pool: Pubkey,
+
+    /// Samples the account has room for
+    pub capacity: u32,
+
+    /// Samples written, up to capacity
+    pub len: u32,
+
+    /// Index of the latest sample
+    pub head: u32
+}
+
+impl ObservationBuffer {
+    /// Current layout version. Bumped whenever fields are added to the account.
+    pub const VERSION: u8 = 1;
+
+    /// First seed of the buffer PDA
+    pub const SEED: &'static [u8] = b"observations";
+
+    /// Largest capacity, bounds the samples copied by GrowObservations
+    pub const MAX_CAPACITY: u32 = 1024;
+
+    /// Account size of a buffer holding `capacity` samples
+    pub fn space(capacity: u32) -> usize {
+        ObservationBuffer::LEN + capacity as usize * PriceSample::LEN
+    }
+
+    /// Sample at `index` of the ring, `data` is the whole account data
+    pub fn sample(&self, data: &[u8], index: u32) -> Result<PriceSample, ProgramError> {
+        if index >= self.capacity {
+            return Err(VisionError::InvalidInput.into());
+        }
+        let start = ObservationBuffer::LEN + index as usize * PriceSample::LEN;
+        PriceSample::unpack_from_slice(data.get(start..start + PriceSample::LEN).ok_or(ProgramError::AccountDataTooSmall)?)
+    }
+
+    fn set_sample(&self, data: &mut [u8], index: u32, sample: &PriceSample) -> Result<(), ProgramError> {
+        let start = ObservationBuffer::LEN + index as usize * PriceSample::LEN;
+        sample.pack_into_slice(data.get_mut(start..start + PriceSample::LEN).ok_or(ProgramError::AccountDataTooSmall)?);
+        Ok(())
+    }
+
+    /// Samples written, oldest first
+    pub fn samples(&self, data: &[u8]) -> Result<Vec<PriceSample>, ProgramError> {
+        let oldest = if self.len < self.capacity { 0 } else { (self.head + 1) % self.capacity };
+        (0..self.len).map(|i| self.sample(data, (oldest + i) % self.capacity)).collect()
+    }
+
+    /// Writes `sample` as the latest sample, overwriting the oldest one once the buffer is full.
+    /// A sample of the same slot as the latest one replaces it.
+    pub fn push(&mut self, data: &mut [u8], sample: PriceSample) -> Result<(), ProgramError> {
+        if self.len > 0 && self.sample(data, self.head)?.slot == sample.slot {
+            return self.set_sample(data, self.head, &sample);
+        }
+        self.head = if self.len == 0 { 0 } else { (self.head + 1) % self.capacity };
+        self.len = (self.len + 1).min(self.capacity);
+        self.set_sample(data, self.head, &sample)
+    }
+}
+
+impl Sealed for ObservationBuffer {}
+impl IsInitialized for ObservationBuffer {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for ObservationBuffer {
+    const LEN: usize = 48;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, ObservationBuffer::LEN];
+        let(
+            is_initialized_dst,
+            version_dst,
+            bump_seed_dst,
+            generation_dst,
+            pool_dst,
+            capacity_dst,
+            len_dst,
+            head_dst
+        ) = mut_array_refs![output, 1, 1, 1, 1, 32, 4, 4, 4];
+
+        is_initialized_dst[0] = self.is_initialized as u8;
+        version_dst[0] = self.version;
+        bump_seed_dst[0] = self.bump_seed;
+        generation_dst[0] = self.generation;
+        pool_dst.copy_from_slice(self.pool.as_ref());
+        *capacity_dst = self.capacity.to_le_bytes();
+        *len_dst = self.len.to_le_bytes();
+        *head_dst = self.head.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, ObservationBuffer::LEN];
+        let(
+            is_initialized,
+            version,
+            bump_seed,
+            generation,
+            pool,
+            capacity,
+            len,
+            head
+        ) = array_refs![input, 1, 1, 1, 1, 32, 4, 4, 4];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        // Uninitialized accounts are all zeros
+        let version = match (is_initialized, version[0]) {
+            (false, 0) => 0,
+            (true, ObservationBuffer::VERSION) => ObservationBuffer::VERSION,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(ObservationBuffer {
+            is_initialized,
+            version,
+            bump_seed: bump_seed[0],
+            generation: generation[0],
+            pool: Pubkey::new_from_array(*pool),
+            capacity: u32::from_le_bytes(*capacity),
+            len: u32::from_le_bytes(*len),
+            head: u32::from_le_bytes(*head)
+        })
+    }
+}
+
+/// Pool state after the trades of a slot, see `ObservationBuffer`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PriceSample {
+    pub slot: u64,
+    /// `PageTokenSwap::price_cumulative` at slot
+    pub price_cumulative: u128,
+    /// Mint supply, without the virtual supply
+    pub supply: u64,
+    /// Lamports backing the supply, rent excluded
+    pub reserve_balance: u64
+}
+
+impl Sealed for PriceSample {}
+
+impl Pack for PriceSample {
+    const LEN: usize = 40;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, PriceSample::LEN];
+        let(
+            slot_dst,
+            price_cumulative_dst,
+            supply_dst,
+            reserve_balance_dst
+        ) = mut_array_refs![output, 8, 16, 8, 8];
+
+        *slot_dst = self.slot.to_le_bytes();
+        *price_cumulative_dst = self.price_cumulative.to_le_bytes();
+        *supply_dst = self.supply.to_le_bytes();
+        *reserve_balance_dst = self.reserve_balance.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, PriceSample::LEN];
+        let(
+            slot,
+            price_cumulative,
+            supply,
+            reserve_balance
+        ) = array_refs![input, 8, 16, 8, 8];
+
+        Ok(PriceSample {
+            slot: u64::from_le_bytes(*slot),
+            price_cumulative: u128::from_le_bytes(*price_cumulative),
+            supply: u64::from_le_bytes(*supply),
+            reserve_balance: u64::from_le_bytes(*reserve_balance)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn twap_survives_accumulator_wrap_around() {
+        let earlier = Observation { slot: 100, price_cumulative: u128::MAX - 5 };
+        let later = Observation { slot: 110, price_cumulative: 14 };
+        assert_eq!(earlier.twap(&later), Some(2));
+        assert_eq!(later.twap(&earlier), None);
+        assert_eq!(earlier.twap(&earlier), None);
+    }
+
+    #[test]
+    fn baseline_layout_is_read_and_written() {
+        let fee_collector = Pubkey::new_unique();
+        let mut data = vec![1, 254, 253];
+        data.extend_from_slice(&2500u16.to_le_bytes());
+        data.extend_from_slice(fee_collector.as_ref());
+        assert_eq!(data.len(), PageTokenSwap::BASELINE_LEN);
+
+        let mut old_state = PageTokenSwap::unpack_versioned(&data).unwrap();
+        assert_eq!(old_state, PageTokenSwap {
+            is_initialized: true,
+            version: 0,
+            bump_seed: 254,
+            bump_seed_sol: 253,
+            fee: 2500,
+            fee_collector_pubkey: fee_collector,
+            reserve_ratio: curve::bancor::DEFAULT_RESERVE_RATIO,
+            authority: fee_collector,
+            ..PageTokenSwap::default()
+        });
+
+        // Trades drop the statistics and the price accumulator
+        old_state.trade_count = 1;
+        old_state.price_cumulative = 1;
+        old_state.fee_collector_pubkey = Pubkey::new_unique();
+        old_state.authority = old_state.fee_collector_pubkey;
+        PageTokenSwap::pack_versioned(old_state, &mut data).unwrap();
+        let old_state = PageTokenSwap::unpack_versioned(&data).unwrap();
+        assert_eq!((old_state.trade_count, old_state.price_cumulative), (0, 0));
+        assert_eq!(&data[5..], old_state.fee_collector_pubkey.as_ref());
+
+        // Later fields can't be stored
+        let mut changed = old_state;
+        changed.pending_fee_slot = 10;
+        assert_eq!(PageTokenSwap::pack_versioned(changed, &mut data), Err(VisionError::OutdatedPoolLayout.into()));
+        let mut changed = old_state;
+        changed.pause_state = PauseState::Paused;
+        assert_eq!(PageTokenSwap::pack_versioned(changed, &mut data), Err(VisionError::OutdatedPoolLayout.into()));
+        let mut changed = old_state;
+        changed.authority = Pubkey::new_unique();
+        assert_eq!(PageTokenSwap::pack_versioned(changed, &mut data), Err(VisionError::OutdatedPoolLayout.into()));
+
+        // Migrated pools keep their fields in the current layout
+        let migrated = PageTokenSwap { version: PageTokenSwap::VERSION, ..old_state };
+        let mut data = vec![0; PageTokenSwap::LEN];
+        PageTokenSwap::pack_versioned(migrated, &mut data).unwrap();
+        assert_eq!(PageTokenSwap::unpack_versioned(&data).unwrap(), migrated);
+
+        // Any other size or version is rejected
+        assert_eq!(PageTokenSwap::unpack_versioned(&data[..42]).err(), Some(ProgramError::InvalidAccountData));
+        data[1] = PageTokenSwap::VERSION + 1;
+        assert_eq!(PageTokenSwap::unpack_versioned(&data).err(), Some(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn fee_changes_are_queued() {
+        let mut swap_state = PageTokenSwap { fee: 2500, ..PageTokenSwap::default() };
+        let delay = PageTokenSwap::FEE_CHANGE_DELAY_SLOTS;
+
+        assert_eq!(swap_state.queue_fee(5001, 10), Err(VisionError::FeeStepExceeded));
+        swap_state.queue_fee(5000, 10).unwrap();
+        assert_eq!((swap_state.pending_fee, swap_state.pending_fee_slot), (5000, 10 + delay));
+        assert_eq!(swap_state.fee_at(9 + delay), 2500);
+        assert_eq!(swap_state.fee_at(10 + delay), 5000);
+
+        // Queuing the same fee again keeps its slot, the current fee cancels it
+        swap_state.queue_fee(5000, 20).unwrap();
+        assert_eq!(swap_state.pending_fee_slot, 10 + delay);
+        swap_state.queue_fee(2500, 20).unwrap();
+        assert_eq!(swap_state.fee_at(u64::MAX), 2500);
+
+        // The step is measured from the fee in effect
+        swap_state.queue_fee(0, 30).unwrap();
+        swap_state.activate_pending_fee(30 + delay);
+        assert_eq!((swap_state.fee, swap_state.pending_fee_slot), (0, 0));
+        assert_eq!(swap_state.queue_fee(5000, 30 + delay), Err(VisionError::FeeStepExceeded));
+    }
+}
@@ -0,0 +1,126 @@
+//! Page token mints are owned by spl-token or Token-2022. spl-token-2022's builders and state
+//! accept both programs, the base instructions and account layouts are shared.
+
+use solana_program::{
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use spl_token_2022::{
+    extension::{
+        metadata_pointer, transfer_fee, BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+    state::{Account, Mint},
+};
+
+pub mod token_2022 {
+    pub use spl_token_2022::{check_id, id, ID};
+}
+
+pub fn is_token_program(key: &Pubkey) -> bool {
+    *key == spl_token::id() || *key == token_2022::id()
+}
+
+/// Associated token account of `wallet` for a mint of `token_program`
+pub fn get_associated_token_address(wallet: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    spl_associated_token_account::get_associated_token_address_with_program_id(wallet, mint, token_program)
+}
+
+/// Base state of a mint of either program, extensions are skipped
+pub fn unpack_mint(data: &[u8]) -> Result<Mint, ProgramError> {
+    Ok(StateWithExtensions::<Mint>::unpack(data)?.base)
+}
+
+/// Base state of a token account of either program, extensions are skipped
+pub fn unpack_account(data: &[u8]) -> Result<Account, ProgramError> {
+    Ok(StateWithExtensions::<Account>::unpack(data)?.base)
+}
+
+/// Whether a mint has an extension of `extension_type`, always false for spl-token mints
+pub fn has_extension(mint_data: &[u8], extension_type: ExtensionType) -> Result<bool, ProgramError> {
+    Ok(StateWithExtensions::<Mint>::unpack(mint_data)?.get_extension_types()?.contains(&extension_type))
+}
+
+/// Space the associated token account program allocates for a token account of `mint_data`.
+/// Token-2022 accounts are immutably owned and carry the account extensions their mint requires.
+pub fn associated_account_space(token_program: &Pubkey, mint_data: &[u8]) -> Result<usize, ProgramError> {
+    if *token_program != token_2022::id() {
+        return Ok(Account::LEN);
+    }
+    let mint = StateWithExtensions::<Mint>::unpack(mint_data)?;
+    let mut extension_types = ExtensionType::get_required_init_account_extensions(&mint.get_extension_types()?);
+    extension_types.push(ExtensionType::ImmutableOwner);
+    ExtensionType::try_calculate_account_len::<Account>(&extension_types)
+}
+
+/// Space of a mint, Token-2022 only with extensions
+pub fn mint_space(transfer_fee: bool, metadata_pointer: bool) -> Result<usize, ProgramError> {
+    let mut extension_types = Vec::new();
+    if transfer_fee {
+        extension_types.push(ExtensionType::TransferFeeConfig);
+    }
+    if metadata_pointer {
+        extension_types.push(ExtensionType::MetadataPointer);
+    }
+    ExtensionType::try_calculate_account_len::<Mint>(&extension_types)
+}
+
+pub fn initialize_mint(token_program: &Pubkey, mint: &Pubkey, mint_authority: &Pubkey, decimals: u8) -> Result<Instruction, ProgramError> {
+    spl_token_2022::instruction::initialize_mint(token_program, mint, mint_authority, None, decimals)
+}
+
+pub fn mint_to(token_program: &Pubkey, mint: &Pubkey, account: &Pubkey, authority: &Pubkey, amount: u64) -> Result<Instruction, ProgramError> {
+    spl_token_2022::instruction::mint_to(token_program, mint, account, authority, &[], amount)
+}
+
+pub fn burn(token_program: &Pubkey, account: &Pubkey, mint: &Pubkey, authority: &Pubkey, amount: u64) -> Result<Instruction, ProgramError> {
+    spl_token_2022::instruction::burn(token_program, account, mint, authority, &[], amount)
+}
+
+pub fn transfer_checked(token_program: &Pubkey, source: &Pubkey, mint: &Pubkey, destination: &Pubkey, authority: &Pubkey, amount: u64, decimals: u8) -> Result<Instruction, ProgramError> {
+    spl_token_2022::instruction::transfer_checked(token_program, source, mint, destination, authority, &[], amount, decimals)
+}
+
+pub fn create_associated_token_account(payer: &Pubkey, wallet: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Instruction {
+    spl_associated_token_account::instruction::create_associated_token_account(payer, wallet, mint, token_program)
+}
+
+/// Token-2022 transfer fee on the mint, precedes InitializeMint. The fee can't be changed.
+pub fn initialize_transfer_fee_config(mint: &Pubkey, withdraw_withheld_authority: &Pubkey, basis_points: u16, maximum_fee: u64) -> Result<Instruction, ProgramError> {
+    transfer_fee::instruction::initialize_transfer_fee_config(&token_2022::id(), mint, None, Some(withdraw_withheld_authority), basis_points, maximum_fee)
+}
+
+/// Moves the Token-2022 transfer fees withheld in the mint to `destination`
+pub fn withdraw_withheld_tokens_from_mint(mint: &Pubkey, destination: &Pubkey, authority: &Pubkey) -> Result<Instruction, ProgramError> {
+    transfer_fee::instruction::withdraw_withheld_tokens_from_mint(&token_2022::id(), mint, destination, authority, &[])
+}
+
+/// Moves the Token-2022 transfer fees withheld in the `sources` token accounts to `destination`
+pub fn withdraw_withheld_tokens_from_accounts(mint: &Pubkey, destination: &Pubkey, authority: &Pubkey, sources: &[&Pubkey]) -> Result<Instruction, ProgramError> {
+    transfer_fee::instruction::withdraw_withheld_tokens_from_accounts(&token_2022::id(), mint, destination, authority, &[], sources)
+}
+
+/// Token-2022 pointer from the mint to its metadata, precedes InitializeMint. The pointer can't be changed.
+pub fn initialize_metadata_pointer(mint: &Pubkey, metadata_address: &Pubkey) -> Result<Instruction, ProgramError> {
+    metadata_pointer::instruction::initialize(&token_2022::id(), mint, None, Some(*metadata_address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mint_and_account_space() {
+        assert_eq!(mint_space(false, false), Ok(82));
+        assert_eq!(mint_space(true, false), Ok(278));
+        assert_eq!(mint_space(false, true), Ok(234));
+        assert_eq!(mint_space(true, true), Ok(346));
+
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack(Mint { is_initialized: true, decimals: 9, ..Mint::default() }, &mut data).unwrap();
+        assert_eq!(associated_account_space(&spl_token::id(), &data), Ok(165));
+        assert_eq!(associated_account_space(&token_2022::id(), &data), Ok(170));
+        assert_eq!(has_extension(&data, ExtensionType::TransferFeeConfig), Ok(false));
+    }
+}
@@ -0,0 +1,488 @@
+//! Synchronous wrapper around `ProgramTest` for the integration tests.
+//!
+//! The program runs natively, spl-token, Token-2022 and the associated token account program run
+//! their BPF builds that come with solana-program-test. Metaplex has no build for this solana
+//! version, a native stand-in decodes CreateMetadataAccountV3 and UpdateMetadataAccountV2, checks
+//! their accounts and keeps the metadata in the metadata account like Metaplex does.
+//! Every signer of the tests is a keypair of the bank, created with `new_signer`.
+
+#![allow(dead_code)]
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use bpf_program_template::{instruction, metadata, processor::Processor, token};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    compute_budget::ComputeBudgetInstruction,
+    message::Message,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_token_2022::{
+    extension::{
+        transfer_fee::{TransferFee, TransferFeeConfig},
+        ExtensionType, StateWithExtensionsMut,
+    },
+    state::{Account as TokenAccount, Mint},
+};
+use std::{collections::HashMap, convert::TryFrom, sync::Once};
+use tokio::runtime::Runtime;
+
+/// Metaplex metadata account without the fields the pool never sets. Metaplex pads the strings.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct MetadataAccount {
+    pub key: u8,
+    pub update_authority: Pubkey,
+    pub mint: Pubkey,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub creators: Option<Vec<metadata::Creator>>,
+    pub primary_sale_happened: bool,
+    pub is_mutable: bool,
+    pub edition_nonce: Option<u8>,
+    pub token_standard: Option<u8>,
+    pub collection: Option<metadata::Collection>,
+    pub uses: Option<metadata::Uses>,
+}
+
+impl MetadataAccount {
+    /// Key of Metaplex metadata accounts
+    pub const KEY: u8 = 4;
+    /// Space Metaplex allocates for metadata accounts
+    pub const LEN: usize = 679;
+
+    fn set_data(&mut self, data: metadata::DataV2) -> ProgramResult {
+        if data.name.len() > metadata::MAX_NAME_LENGTH || data.symbol.len() > metadata::MAX_SYMBOL_LENGTH || data.uri.len() > metadata::MAX_URI_LENGTH {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.name = data.name;
+        self.symbol = data.symbol;
+        self.uri = data.uri;
+        self.seller_fee_basis_points = data.seller_fee_basis_points;
+        self.creators = data.creators;
+        self.collection = data.collection;
+        self.uses = data.uses;
+        Ok(())
+    }
+}
+
+/// Native stand-in for the Metaplex token metadata program, checks the accounts and data of
+/// CreateMetadataAccountV3 and UpdateMetadataAccountV2 like Metaplex does
+fn process_metadata_instruction(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+    let instruction = metadata::MetadataInstruction::unpack(input).ok_or(ProgramError::InvalidInstructionData)?;
+    let account_info_iter = &mut accounts.iter();
+    let metadata_info = next_account_info(account_info_iter)?;
+    match instruction {
+        metadata::MetadataInstruction::CreateMetadataAccountV3(args) => {
+            let mint_info = next_account_info(account_info_iter)?;
+            let mint_authority_info = next_account_info(account_info_iter)?;
+            let payer_info = next_account_info(account_info_iter)?;
+            let update_authority_info = next_account_info(account_info_iter)?;
+            let system_program_info = next_account_info(account_info_iter)?;
+
+            let (metadata_address, bump) = metadata::find_metadata_address(mint_info.key);
+            if *metadata_info.key != metadata_address || !metadata_info.is_writable {
+                return Err(ProgramError::InvalidSeeds);
+            }
+            if !token::is_token_program(mint_info.owner) || *system_program_info.key != system_program::id() {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            let mint = token::unpack_mint(&mint_info.data.borrow())?;
+            if mint.mint_authority != COption::Some(*mint_authority_info.key) {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            if !mint_authority_info.is_signer || !payer_info.is_signer || !update_authority_info.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if args.collection_details.is_some() {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let mut account = MetadataAccount {
+                key: MetadataAccount::KEY,
+                update_authority: *update_authority_info.key,
+                mint: *mint_info.key,
+                name: String::new(),
+                symbol: String::new(),
+                uri: String::new(),
+                seller_fee_basis_points: 0,
+                creators: None,
+                primary_sale_happened: false,
+                is_mutable: args.is_mutable,
+                edition_nonce: None,
+                token_standard: None,
+                collection: None,
+                uses: None,
+            };
+            account.set_data(args.data)?;
+
+            invoke_signed(
+                &system_instruction::create_account(
+                    payer_info.key,
+                    metadata_info.key,
+                    Rent::get()?.minimum_balance(MetadataAccount::LEN),
+                    MetadataAccount::LEN as u64,
+                    program_id,
+                ),
+                &[payer_info.clone(), metadata_info.clone(), system_program_info.clone()],
+                &[&[metadata::PREFIX, &program_id.to_bytes(), &mint_info.key.to_bytes(), &[bump]]],
+            )?;
+            account.serialize(&mut &mut metadata_info.data.borrow_mut()[..]).map_err(|_| ProgramError::AccountDataTooSmall)
+        }
+        metadata::MetadataInstruction::UpdateMetadataAccountV2(args) => {
+            let update_authority_info = next_account_info(account_info_iter)?;
+            if metadata_info.owner != program_id || !metadata_info.is_writable {
+                return Err(ProgramError::IllegalOwner);
+            }
+            let mut account = MetadataAccount::deserialize(&mut &metadata_info.data.borrow()[..])?;
+            if account.update_authority != *update_authority_info.key || !update_authority_info.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if !account.is_mutable {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            if let Some(data) = args.data {
+                account.set_data(data)?;
+            }
+            if let Some(update_authority) = args.update_authority {
+                account.update_authority = update_authority;
+            }
+            if let Some(primary_sale_happened) = args.primary_sale_happened {
+                account.primary_sale_happened = primary_sale_happened;
+            }
+            if let Some(is_mutable) = args.is_mutable {
+                account.is_mutable = is_mutable;
+            }
+            account.serialize(&mut &mut metadata_info.data.borrow_mut()[..]).map_err(|_| ProgramError::AccountDataTooSmall)
+        }
+    }
+}
+
+/// solana-program-test's syscall stubs print sol_log_data of native programs to stdout.
+/// Logs them as program logs of the transaction instead, see `Bank::process`.
+struct LogDataStubs(Box<dyn SyscallStubs>);
+
+impl SyscallStubs for LogDataStubs {
+    fn sol_log(&self, message: &str) {
+        self.0.sol_log(message)
+    }
+
+    fn sol_log_compute_units(&self) {
+        self.0.sol_log_compute_units()
+    }
+
+    fn sol_remaining_compute_units(&self) -> u64 {
+        self.0.sol_remaining_compute_units()
+    }
+
+    fn sol_invoke_signed(&self, instruction: &Instruction, account_infos: &[AccountInfo], signers_seeds: &[&[&[u8]]]) -> ProgramResult {
+        self.0.sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_clock_sysvar(var_addr)
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_schedule_sysvar(var_addr)
+    }
+
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_fees_sysvar(var_addr)
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_rent_sysvar(var_addr)
+    }
+
+    fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_rewards_sysvar(var_addr)
+    }
+
+    fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_last_restart_slot(var_addr)
+    }
+
+    unsafe fn sol_memcpy(&self, dst: *mut u8, src: *const u8, n: usize) {
+        self.0.sol_memcpy(dst, src, n)
+    }
+
+    unsafe fn sol_memmove(&self, dst: *mut u8, src: *const u8, n: usize) {
+        self.0.sol_memmove(dst, src, n)
+    }
+
+    unsafe fn sol_memcmp(&self, s1: *const u8, s2: *const u8, n: usize, result: *mut i32) {
+        self.0.sol_memcmp(s1, s2, n, result)
+    }
+
+    unsafe fn sol_memset(&self, s: *mut u8, c: u8, n: usize) {
+        self.0.sol_memset(s, c, n)
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.0.sol_get_return_data()
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.0.sol_set_return_data(data)
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        let data = fields.iter().map(base64::encode).collect::<Vec<_>>().join(" ");
+        self.0.sol_log(&format!("Program data: {}", data))
+    }
+
+    fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+        self.0.sol_get_processed_sibling_instruction(index)
+    }
+
+    fn sol_get_stack_height(&self) -> u64 {
+        self.0.sol_get_stack_height()
+    }
+}
+
+/// No placeholder stub is called: every bank wraps the stubs before its first transaction
+struct NoStubs;
+
+impl SyscallStubs for NoStubs {}
+
+static WRAP_STUBS: Once = Once::new();
+
+pub struct Bank {
+    pub program_id: Pubkey,
+    runtime: Runtime,
+    context: ProgramTestContext,
+    /// Keypairs of the signers created by `new_signer`
+    keypairs: HashMap<Pubkey, Keypair>,
+    /// Makes otherwise identical transactions distinct
+    nonce: u64,
+    /// sol_log_data lines of the last successful instruction, formatted like the runtime logs them
+    pub logs: Vec<String>,
+    /// Slot and unix timestamp of the Clock sysvar
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+impl Bank {
+    pub fn new(program_id: Pubkey) -> Self {
+        let mut program_test = ProgramTest::new("bpf_program_template", program_id, processor!(Processor::process));
+        program_test.add_program("mpl_token_metadata", metadata::id(), processor!(process_metadata_instruction));
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        // Installs solana-program-test's stubs
+        let context = runtime.block_on(program_test.start_with_context());
+        WRAP_STUBS.call_once(|| {
+            let program_test_stubs = set_syscall_stubs(Box::new(NoStubs));
+            set_syscall_stubs(Box::new(LogDataStubs(program_test_stubs)));
+        });
+        Bank {
+            program_id,
+            runtime,
+            context,
+            keypairs: HashMap::new(),
+            nonce: 0,
+            logs: Vec::new(),
+            slot: 1,
+            unix_timestamp: 1_600_000_000,
+        }
+    }
+
+    /// Address of a new keypair that can sign in `process`
+    pub fn new_signer(&mut self) -> Pubkey {
+        let keypair = Keypair::new();
+        let address = keypair.pubkey();
+        self.keypairs.insert(address, keypair);
+        address
+    }
+
+    /// ProgramData account of the program, as deployed by the upgradeable loader
+    pub fn set_upgrade_authority(&mut self, authority: Option<Pubkey>) {
+        let (program_data, _) = instruction::find_program_data_address(&self.program_id);
+        let data = bincode::serialize(&UpgradeableLoaderState::ProgramData { slot: 0, upgrade_authority_address: authority }).unwrap();
+        self.set_account(&program_data, Account {
+            lamports: self.rent_exempt(data.len()),
+            data,
+            owner: bpf_loader_upgradeable::id(),
+            executable: false,
+            rent_epoch: 0,
+        });
+    }
+
+    pub fn rent_exempt(&self, len: usize) -> u64 {
+        Rent::default().minimum_balance(len)
+    }
+
+    pub fn get_account(&self, key: &Pubkey) -> Option<Account> {
+        self.runtime.block_on(self.context.banks_client.clone().get_account(*key)).unwrap()
+    }
+
+    pub fn account(&self, key: &Pubkey) -> Account {
+        self.get_account(key).expect("account does not exist")
+    }
+
+    /// Overwrites the account, bypassing the programs
+    pub fn set_account(&mut self, key: &Pubkey, account: Account) {
+        self.context.set_account(key, &account.into());
+    }
+
+    /// Creates or funds a system account
+    pub fn airdrop(&mut self, key: &Pubkey, lamports: u64) {
+        let mut account = self.get_account(key).unwrap_or_else(|| Account::new(0, 0, &system_program::id()));
+        account.lamports += lamports;
+        self.set_account(key, account);
+    }
+
+    pub fn lamports(&self, key: &Pubkey) -> u64 {
+        self.get_account(key).map_or(0, |account| account.lamports)
+    }
+
+    pub fn unpack<T: Pack>(&self, key: &Pubkey) -> T {
+        T::unpack_unchecked(&self.account(key).data).unwrap()
+    }
+
+    pub fn pack<T: Pack>(&mut self, key: &Pubkey, state: T) {
+        let mut account = self.account(key);
+        T::pack(state, &mut account.data).unwrap();
+        self.set_account(key, account);
+    }
+
+    /// Metaplex metadata of `mint`
+    pub fn metadata(&self, mint: &Pubkey) -> MetadataAccount {
+        let (address, _) = metadata::find_metadata_address(mint);
+        let account = self.account(&address);
+        assert_eq!(account.owner, metadata::id());
+        MetadataAccount::deserialize(&mut &account.data[..]).unwrap()
+    }
+
+    /// Initialized spl-token mint without a mint authority
+    pub fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        let address = Pubkey::new_unique();
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(
+            spl_token::state::Mint { decimals, is_initialized: true, ..spl_token::state::Mint::default() },
+            &mut data,
+        )
+        .unwrap();
+        self.set_account(&address, Account {
+            lamports: self.rent_exempt(data.len()),
+            data,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        });
+        address
+    }
+
+    /// Token-2022 mint, with a 1% transfer fee if `transfer_fee`
+    pub fn create_mint_2022(&mut self, decimals: u8, transfer_fee: bool) -> Pubkey {
+        let address = Pubkey::new_unique();
+        let extensions: &[ExtensionType] = if transfer_fee { &[ExtensionType::TransferFeeConfig] } else { &[] };
+        let mut data = vec![0u8; ExtensionType::try_calculate_account_len::<Mint>(extensions).unwrap()];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        if transfer_fee {
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            let fee = TransferFee { transfer_fee_basis_points: 100.into(), maximum_fee: u64::MAX.into(), ..TransferFee::default() };
+            config.older_transfer_fee = fee;
+            config.newer_transfer_fee = fee;
+        }
+        state.base = Mint { decimals, is_initialized: true, ..Mint::default() };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        self.set_account(&address, Account {
+            lamports: self.rent_exempt(data.len()),
+            data,
+            owner: token::token_2022::id(),
+            executable: false,
+            rent_epoch: 0,
+        });
+        address
+    }
+
+    /// Sets the balance of a token account of either token program
+    pub fn set_token_balance(&mut self, key: &Pubkey, amount: u64) {
+        let mut account = self.account(key);
+        let mut state = StateWithExtensionsMut::<TokenAccount>::unpack(&mut account.data).unwrap();
+        state.base.amount = amount;
+        state.pack_base();
+        self.set_account(key, account);
+    }
+
+    /// Associated token account of owner, created by the associated token account program
+    /// for the token program of the mint
+    pub fn create_token_account(&mut self, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        let token_program = self.account(mint).owner;
+        let payer = self.context.payer.pubkey();
+        let ix = token::create_associated_token_account(&payer, owner, mint, &token_program);
+        // Keeps the events of the instruction before
+        let logs = std::mem::take(&mut self.logs);
+        self.process(&ix, &[]).unwrap();
+        self.logs = logs;
+        token::get_associated_token_address(owner, mint, &token_program)
+    }
+
+    pub fn token_balance(&self, key: &Pubkey) -> u64 {
+        token::unpack_account(&self.account(key).data).unwrap().amount
+    }
+
+    pub fn mint_supply(&self, mint: &Pubkey) -> u64 {
+        token::unpack_mint(&self.account(mint).data).unwrap().supply
+    }
+
+    /// Runs the instruction in its own transaction, `signers` are the keys that sign it.
+    /// Accounts the instruction expects to sign but that are not in `signers` are passed unsigned.
+    /// Returns the return data set by the program.
+    pub fn process(&mut self, instruction: &Instruction, signers: &[&Pubkey]) -> Result<Option<Vec<u8>>, ProgramError> {
+        let mut instruction = instruction.clone();
+        for meta in instruction.accounts.iter_mut() {
+            meta.is_signer &= signers.contains(&&meta.pubkey);
+        }
+        self.context.set_sysvar(&Clock { slot: self.slot, unix_timestamp: self.unix_timestamp, ..Clock::default() });
+        self.nonce += 1;
+
+        let payer = self.context.payer.pubkey();
+        let message = Message::new(&[ComputeBudgetInstruction::set_compute_unit_price(self.nonce), instruction], Some(&payer));
+        let keypairs: Vec<&Keypair> = message
+            .signer_keys()
+            .into_iter()
+            .map(|key| if *key == payer { &self.context.payer } else { self.keypairs.get(key).expect("signer without keypair") })
+            .collect();
+        let transaction = Transaction::new(&keypairs, message, self.context.last_blockhash);
+
+        let result = self.runtime.block_on(self.context.banks_client.process_transaction_with_metadata(transaction)).unwrap();
+        match result.result {
+            Ok(()) => {}
+            Err(TransactionError::InstructionError(_, error)) => {
+                return Err(ProgramError::try_from(error.clone()).unwrap_or_else(|_| panic!("runtime error {:?}", error)));
+            }
+            Err(error) => panic!("transaction failed: {}", error),
+        }
+        let metadata = result.metadata.expect("processed transactions have metadata");
+        self.logs = metadata
+            .log_messages
+            .iter()
+            .filter_map(|log| log.strip_prefix("Program log: "))
+            .filter(|log| log.starts_with("Program data: "))
+            .map(String::from)
+            .collect();
+        Ok(metadata
+            .return_data
+            .filter(|return_data| return_data.program_id == self.program_id && !return_data.data.is_empty())
+            .map(|return_data| return_data.data))
+    }
+}
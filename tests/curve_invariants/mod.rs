@@ -0,0 +1,182 @@
+//! Invariants of the bonding curves, shared by the property tests and the fuzz target.
+//! Every check panics with the violated invariant.
+
+#![allow(dead_code)]
+
+use bpf_program_template::{
+    curve::{self, calculator::CurveCalculator},
+    processor::{BuyLimit, Processor, SellLimit},
+    state::{BuyAmt, PageTokenSwap, SellAmt},
+};
+
+/// Token supply a pool starts from, never held by anyone
+pub const VIRTUAL_SUPPLY: u64 = 1_000_000_000;
+
+/// Lamports deposited by Initialize that always stay in the reserve
+pub const COLLATERAL: u64 = 36;
+
+/// Page fee (x/100000) and provider fee (x/10000) precision
+const FEE_DENOMINATOR: u128 = 100_000;
+const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Curve fields of a pool, as saved in `PageTokenSwap`
+#[derive(Clone, Debug)]
+pub struct CurveParams {
+    pub curve_type: u8,
+    pub reserve_ratio: u32,
+    pub curve_parameters: [u64; 2],
+}
+
+impl CurveParams {
+    /// None if Initialize would reject the curve
+    pub fn calculator(&self) -> Option<Box<dyn CurveCalculator>> {
+        let calculator = curve::curve_calculator(self.curve_type, self.reserve_ratio, &self.curve_parameters).ok()?;
+        calculator.validate().ok()?;
+        Some(calculator)
+    }
+
+    pub fn swap_state(&self, fee: u16) -> PageTokenSwap {
+        PageTokenSwap {
+            is_initialized: true,
+            version: PageTokenSwap::VERSION,
+            fee,
+            reserve_ratio: self.reserve_ratio,
+            curve_type: self.curve_type,
+            curve_parameters: self.curve_parameters,
+            ..PageTokenSwap::default()
+        }
+    }
+}
+
+/// Supply and reserve balance after buying with `buys` from a fresh pool.
+/// Buys the curve can't price are skipped, like the processor rejects them.
+pub fn pool_after_buys(calculator: &dyn CurveCalculator, buys: &[u64]) -> (u64, u64) {
+    let (mut supply, mut reserve) = (VIRTUAL_SUPPLY, COLLATERAL);
+    for amount in buys.iter() {
+        let next = calculator
+            .buy_out_for_in(supply, reserve, *amount)
+            .and_then(|out| Some((supply.checked_add(out)?, reserve.checked_add(*amount)?)));
+        if let Some((next_supply, next_reserve)) = next {
+            supply = next_supply;
+            reserve = next_reserve;
+        }
+    }
+    (supply, reserve)
+}
+
+/// Spending more never mints fewer tokens
+pub fn check_buy_monotonic(calculator: &dyn CurveCalculator, supply: u64, reserve: u64, a: u64, b: u64) {
+    let (low, high) = (a.min(b), a.max(b));
+    if let (Some(low_out), Some(high_out)) = (calculator.buy_out_for_in(supply, reserve, low), calculator.buy_out_for_in(supply, reserve, high)) {
+        assert!(low_out <= high_out, "buy not monotonic: {} -> {}, {} -> {}", low, low_out, high, high_out);
+    }
+}
+
+/// Burning more never releases fewer lamports
+pub fn check_sell_monotonic(calculator: &dyn CurveCalculator, supply: u64, reserve: u64, a: u64, b: u64) {
+    let (low, high) = (a.min(b), a.max(b));
+    if let (Some(low_out), Some(high_out)) = (calculator.sell_out_for_in(supply, reserve, low), calculator.sell_out_for_in(supply, reserve, high)) {
+        assert!(low_out <= high_out, "sell not monotonic: {} -> {}, {} -> {}", low, low_out, high, high_out);
+    }
+}
+
+/// Selling the tokens of a buy right away never returns more than was deposited
+pub fn check_buy_sell_round_trip(calculator: &dyn CurveCalculator, supply: u64, reserve: u64, amount_in: u64) {
+    let tokens = match calculator.buy_out_for_in(supply, reserve, amount_in) {
+        Some(tokens) => tokens,
+        None => return,
+    };
+    let (supply_after, reserve_after) = match (supply.checked_add(tokens), reserve.checked_add(amount_in)) {
+        (Some(supply_after), Some(reserve_after)) => (supply_after, reserve_after),
+        _ => return,
+    };
+    if let Some(amount_out) = calculator.sell_out_for_in(supply_after, reserve_after, tokens) {
+        assert!(amount_out <= amount_in, "round trip profit: deposited {}, withdrew {}", amount_in, amount_out);
+    }
+}
+
+/// Buying exactly `amount_out` tokens and selling them right away never returns more than was paid
+pub fn check_exact_out_round_trip(calculator: &dyn CurveCalculator, supply: u64, reserve: u64, amount_out: u64) {
+    let amount_in = match calculator.in_for_exact_out(supply, reserve, amount_out) {
+        Some(amount_in) => amount_in,
+        None => return,
+    };
+    let (supply_after, reserve_after) = match (supply.checked_add(amount_out), reserve.checked_add(amount_in)) {
+        (Some(supply_after), Some(reserve_after)) => (supply_after, reserve_after),
+        _ => return,
+    };
+    if let Some(lamports_out) = calculator.sell_out_for_in(supply_after, reserve_after, amount_out) {
+        assert!(lamports_out <= amount_in, "exact out round trip profit: paid {}, withdrew {}", amount_in, lamports_out);
+    }
+}
+
+/// Burning `tokens_in` may pay out `lamports_out`: the tokens are worth at least that much.
+/// No value means they are worth more than the reserve holds, then only lamports of the reserve can be paid.
+fn assert_covered(calculator: &dyn CurveCalculator, supply: u64, reserve: u64, tokens_in: u64, lamports_out: u64) {
+    match calculator.sell_out_for_in(supply, reserve, tokens_in) {
+        Some(value) => assert!(value >= lamports_out, "burning {} tokens pays {}, worth {}", tokens_in, lamports_out, value),
+        None => assert!(tokens_in > 0 && lamports_out <= reserve, "burning {} tokens pays {} from a reserve of {}", tokens_in, lamports_out, reserve),
+    }
+}
+
+/// The tokens burned for exactly `lamports_out` are worth at least `lamports_out`
+pub fn check_sell_exact_out(calculator: &dyn CurveCalculator, supply: u64, reserve: u64, lamports_out: u64) {
+    if let Some(tokens_in) = calculator.sell_in_for_exact_out(supply, reserve, lamports_out) {
+        assert_covered(calculator, supply, reserve, tokens_in, lamports_out);
+    }
+}
+
+/// The reserve covers the sale of every circulating token without touching the collateral.
+/// The virtual supply is never held by anyone, so it is never sold.
+pub fn check_solvency(calculator: &dyn CurveCalculator, supply: u64, reserve: u64) {
+    let circulating = supply - VIRTUAL_SUPPLY;
+    if let Some(amount_out) = calculator.sell_out_for_in(supply, reserve, circulating) {
+        assert!(amount_out <= reserve - COLLATERAL, "selling {} circulating tokens pays {} from a reserve of {}", circulating, amount_out, reserve);
+    }
+}
+
+/// Lamports spent are split into the reserve deposit and both fees, each fee rounded down
+pub fn check_buy_fees(params: &CurveParams, fee: u16, provider_fee_bps: u16, supply: u64, reserve: u64, amount: u64, exact_in: bool) {
+    let limit = if exact_in {
+        BuyLimit::ExactIn { amount_in: amount, minimum_amount_out: 0 }
+    } else {
+        BuyLimit::ExactOut { token_amount_out: amount, maximum_amount_in: u64::MAX }
+    };
+    let BuyAmt { amount_in, adjusted_amount_in, token_amt, fee_page, fee_provider } = match Processor::buy_amounts(&params.swap_state(fee), provider_fee_bps, supply, reserve, &limit) {
+        Ok(amounts) => amounts,
+        Err(_) => return,
+    };
+    assert_eq!(amount_in as u128, adjusted_amount_in as u128 + fee_page as u128 + fee_provider as u128);
+    assert_eq!(fee_page as u128, amount_in as u128 * fee as u128 / FEE_DENOMINATOR);
+    assert_eq!(fee_provider as u128, amount_in as u128 * provider_fee_bps as u128 / BPS_DENOMINATOR);
+    if !exact_in {
+        assert_eq!(token_amt, amount);
+        // The deposit after fees pays for the tokens
+        let calculator = params.calculator().unwrap();
+        let cost = calculator.in_for_exact_out(supply, reserve, amount).unwrap();
+        assert!(adjusted_amount_in >= cost, "deposit {} below cost {}", adjusted_amount_in, cost);
+    }
+}
+
+/// Lamports released are split into the payout and the provider fee, the fee rounded down
+pub fn check_sell_fees(params: &CurveParams, provider_fee_bps: u16, supply: u64, reserve: u64, amount: u64, exact_in: bool) {
+    let limit = if exact_in {
+        SellLimit::ExactIn { amount_in: amount, minimum_amount_out: 0 }
+    } else {
+        SellLimit::ExactOut { lamports_out: amount, maximum_tokens_in: u64::MAX }
+    };
+    let SellAmt { token_amt, sol_amt, adjusted_sol_amt, fee_provider } = match Processor::sell_amounts(&params.swap_state(0), provider_fee_bps, supply, reserve, &limit) {
+        Ok(amounts) => amounts,
+        Err(_) => return,
+    };
+    assert_eq!(sol_amt as u128, adjusted_sol_amt as u128 + fee_provider as u128);
+    let calculator = params.calculator().unwrap();
+    if exact_in {
+        assert_eq!(token_amt, amount);
+        assert_eq!(fee_provider as u128, sol_amt as u128 * provider_fee_bps as u128 / BPS_DENOMINATOR);
+    } else {
+        assert_eq!(adjusted_sol_amt, amount);
+        // The burned tokens are worth the payout and the fee
+        assert_covered(&*calculator, supply, reserve, token_amt, sol_amt);
+    }
+}
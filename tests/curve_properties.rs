@@ -0,0 +1,128 @@
+mod curve_invariants;
+
+use bpf_program_template::curve::bancor::{MAX_RESERVE_RATIO, MIN_RESERVE_RATIO};
+use curve_invariants::*;
+use proptest::prelude::*;
+
+/// Every curve type over the whole parameter range, invalid curves are filtered out
+fn curve_params() -> impl Strategy<Value = CurveParams> {
+    prop_oneof![
+        (MIN_RESERVE_RATIO..=MAX_RESERVE_RATIO).prop_map(|reserve_ratio| CurveParams { curve_type: 0, reserve_ratio, curve_parameters: [0, 0] }),
+        (amount(), amount()).prop_map(|(initial_price, slope)| CurveParams { curve_type: 1, reserve_ratio: 0, curve_parameters: [initial_price, slope] }),
+        amount().prop_map(|doubling_supply| CurveParams { curve_type: 2, reserve_ratio: 0, curve_parameters: [doubling_supply, 0] }),
+        (amount(), amount()).prop_map(|(sol, token)| CurveParams { curve_type: 3, reserve_ratio: 0, curve_parameters: [sol, token] }),
+    ]
+    .prop_filter("invalid curve", |params| params.calculator().is_some())
+}
+
+/// Amounts of every magnitude, uniform u64 values alone are almost always > 10^18
+fn amount() -> impl Strategy<Value = u64> {
+    prop_oneof![
+        0..1_000u64,
+        0..1_000_000_000u64,
+        0..1_000_000_000_000_000u64,
+        any::<u64>(),
+    ]
+}
+
+/// Pool reached through a few buys from a fresh pool
+fn pool() -> impl Strategy<Value = (CurveParams, u64, u64)> {
+    (curve_params(), prop::collection::vec(amount(), 0..4)).prop_map(|(params, buys)| {
+        let (supply, reserve) = pool_after_buys(&*params.calculator().unwrap(), &buys);
+        (params, supply, reserve)
+    })
+}
+
+proptest! {
+    #[test]
+    fn buy_is_monotonic((params, supply, reserve) in pool(), a in amount(), b in amount()) {
+        check_buy_monotonic(&*params.calculator().unwrap(), supply, reserve, a, b);
+    }
+
+    #[test]
+    fn sell_is_monotonic((params, supply, reserve) in pool(), a in amount(), b in amount()) {
+        let circulating = supply - VIRTUAL_SUPPLY;
+        check_sell_monotonic(&*params.calculator().unwrap(), supply, reserve, a % (circulating + 1), b % (circulating + 1));
+    }
+
+    #[test]
+    fn buy_sell_round_trip_never_profits((params, supply, reserve) in pool(), amount_in in amount()) {
+        check_buy_sell_round_trip(&*params.calculator().unwrap(), supply, reserve, amount_in);
+    }
+
+    #[test]
+    fn exact_out_round_trip_never_profits((params, supply, reserve) in pool(), amount_out in amount()) {
+        check_exact_out_round_trip(&*params.calculator().unwrap(), supply, reserve, amount_out);
+    }
+
+    #[test]
+    fn sell_exact_out_is_covered((params, supply, reserve) in pool(), lamports_out in amount()) {
+        check_sell_exact_out(&*params.calculator().unwrap(), supply, reserve, lamports_out);
+    }
+
+    #[test]
+    fn reserve_covers_supply((params, supply, reserve) in pool()) {
+        check_solvency(&*params.calculator().unwrap(), supply, reserve);
+    }
+
+    #[test]
+    fn buy_fees_add_up((params, supply, reserve) in pool(), fee in 0..=50_000u16, provider_fee_bps in 0..=1_000u16, amount in amount(), exact_in: bool) {
+        check_buy_fees(&params, fee, provider_fee_bps, supply, reserve, amount, exact_in);
+    }
+
+    #[test]
+    fn sell_fees_add_up((params, supply, reserve) in pool(), provider_fee_bps in 0..=1_000u16, amount in amount(), exact_in: bool) {
+        check_sell_fees(&params, provider_fee_bps, supply, reserve, amount, exact_in);
+    }
+}
+
+// Counterexamples found by the properties above
+
+/// Constant product pool whose token side is empty: supply == virtual_token_reserve
+fn exhausted_constant_product(virtual_sol_reserve: u64) -> CurveParams {
+    CurveParams { curve_type: 3, reserve_ratio: 0, curve_parameters: [virtual_sol_reserve, VIRTUAL_SUPPLY] }
+}
+
+#[test]
+fn sell_exact_out_on_empty_token_side_burns_tokens() {
+    // sell_in_for_exact_out asked for 0 tokens to release lamports
+    let params = exhausted_constant_product(22_451_193_985_095_599);
+    let calculator = params.calculator().unwrap();
+    assert_eq!(calculator.sell_in_for_exact_out(VIRTUAL_SUPPLY, 296, 1), None);
+    check_sell_exact_out(&*calculator, VIRTUAL_SUPPLY, 296, 0);
+    check_sell_exact_out(&*calculator, VIRTUAL_SUPPLY, 296, 1);
+
+    let params = exhausted_constant_product(271_836_550_794_814_865);
+    check_sell_fees(&params, 0, VIRTUAL_SUPPLY, 655, 1, false);
+}
+
+#[test]
+fn sell_nothing_on_empty_token_side() {
+    // sell_out_for_in returned None instead of selling nothing
+    let params = exhausted_constant_product(22_451_193_985_095_599);
+    assert_eq!(params.calculator().unwrap().sell_out_for_in(VIRTUAL_SUPPLY, 296, 0), Some(0));
+    check_sell_fees(&params, 0, VIRTUAL_SUPPLY, 655, 0, false);
+}
+
+#[test]
+fn sell_nothing_beyond_token_side() {
+    // Supply above virtual_token_reserve can't be priced at all, not even a zero amount
+    let params = CurveParams { curve_type: 3, reserve_ratio: 0, curve_parameters: [1, 1] };
+    let calculator = params.calculator().unwrap();
+    assert_eq!(calculator.sell_in_for_exact_out(VIRTUAL_SUPPLY, 36, 0), None);
+    check_sell_exact_out(&*calculator, VIRTUAL_SUPPLY, 36, 0);
+}
+
+#[test]
+fn fresh_linear_pool_with_zero_initial_price() {
+    // The first tokens of the curve are free, the reserve holds only the collateral
+    let params = CurveParams { curve_type: 1, reserve_ratio: 0, curve_parameters: [0, 75] };
+    let calculator = params.calculator().unwrap();
+    check_solvency(&*calculator, VIRTUAL_SUPPLY, COLLATERAL);
+    check_buy_monotonic(&*calculator, VIRTUAL_SUPPLY, COLLATERAL, 0, 1);
+    check_buy_sell_round_trip(&*calculator, VIRTUAL_SUPPLY, COLLATERAL, 1);
+    check_exact_out_round_trip(&*calculator, VIRTUAL_SUPPLY, COLLATERAL, 1);
+    check_sell_exact_out(&*calculator, VIRTUAL_SUPPLY, COLLATERAL, 0);
+    check_buy_fees(&params, 0, 0, VIRTUAL_SUPPLY, COLLATERAL, 1, false);
+    check_sell_fees(&params, 0, VIRTUAL_SUPPLY, COLLATERAL, 0, true);
+}
@@ -0,0 +1,1460 @@
+mod common;
+
+use bpf_program_template::{
+    curve::bancor::DEFAULT_RESERVE_RATIO,
+    error::VisionError,
+    event::{AuthorityEvent, ChangeFeeEvent, PauseStateEvent, TradeEvent, VisionEvent},
+    instruction::{self, Config, Curve, Deadline, MintExtensions, ReserveAccounts, TokenMetadata, TradeOptions, TradeSide, TransferFee},
+    metadata,
+    oracle,
+    processor::{BuyLimit, Processor, SellLimit},
+    state::{Observation, ObservationBuffer, PageTokenSwap, PauseState, PoolStats, PoolView, PriceSample, ProgramConfig, Quote},
+    token,
+};
+use common::{Bank, MetadataAccount};
+use solana_program::{
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+};
+use spl_token_2022::extension::{
+    metadata_pointer::MetadataPointer,
+    transfer_fee::{self, TransferFeeAmount, TransferFeeConfig},
+    BaseStateWithExtensions, StateWithExtensions,
+};
+
+const SOL: u64 = 1_000_000_000;
+
+/// Token supply the curve starts from, see Processor::process_buy
+const VIRTUAL_SUPPLY: u64 = 1_000_000_000;
+
+struct Pool {
+    bank: Bank,
+    program_id: Pubkey,
+    admin: Pubkey,
+    payer: Pubkey,
+    mint: Pubkey,
+    pool: Pubkey,
+    pool_sol: Pubkey,
+    payer_token: Pubkey,
+    page_fee_collector: Pubkey,
+    provider_fee_collector: Pubkey,
+    token_program: Pubkey,
+}
+
+fn config(provider_fee_bps: u16, pause_state: PauseState) -> Config {
+    Config { provider_fee_bps, min_page_fee: 0, max_page_fee: 50000, pause_state }
+}
+
+fn custom_error(error: VisionError) -> Result<Option<Vec<u8>>, ProgramError> {
+    Err(error.into())
+}
+
+/// Config with a 1% provider fee and a fresh Bancor pool, the payer holds 100 SOL and an empty token account
+fn setup() -> Pool {
+    setup_with_token_program(&spl_token::id())
+}
+
+fn setup_with_token_program(token_program: &Pubkey) -> Pool {
+    let program_id = bpf_program_template::id();
+    let mut bank = Bank::new(program_id);
+    let admin = bank.new_signer();
+    let payer = bank.new_signer();
+    let mint = bank.new_signer();
+    let page_fee_collector = bank.new_signer();
+    let provider_fee_collector = Pubkey::new_unique();
+    bank.airdrop(&admin, 10 * SOL);
+    bank.airdrop(&payer, 100 * SOL);
+    bank.airdrop(&page_fee_collector, bank.rent_exempt(0));
+    bank.airdrop(&provider_fee_collector, bank.rent_exempt(0));
+    bank.set_upgrade_authority(Some(admin));
+
+    bank.process(
+        &instruction::initialize_config(&program_id, &admin, &provider_fee_collector, config(100, PauseState::Active)),
+        &[&admin],
+    )
+    .unwrap();
+    let curve = Curve { reserve_ratio: DEFAULT_RESERVE_RATIO, curve_type: 0, curve_parameters: [0, 0] };
+    bank.process(
+        &instruction::initialize_with_options(&program_id, &payer, &mint, &page_fee_collector, curve, token_program, None, None, None).unwrap(),
+        &[&payer, &mint],
+    )
+    .unwrap();
+    let payer_token = bank.create_token_account(&payer, &mint);
+
+    let (pool, _) = instruction::find_pool_address(&program_id, &mint);
+    let (pool_sol, _) = instruction::find_pool_sol_address(&program_id, &pool);
+    Pool { bank, program_id, admin, payer, mint, pool, pool_sol, payer_token, page_fee_collector, provider_fee_collector, token_program: *token_program }
+}
+
+impl Pool {
+    /// `Initialize` of a Bancor pool of `mint`, created by `payer`, with any combination of options
+    fn initialize_ix(
+        &self,
+        payer: &Pubkey,
+        mint: &Pubkey,
+        token_program: &Pubkey,
+        metadata: Option<TokenMetadata>,
+        extensions: Option<MintExtensions>,
+        reserve: Option<ReserveAccounts>,
+    ) -> Instruction {
+        let curve = Curve { reserve_ratio: DEFAULT_RESERVE_RATIO, curve_type: 0, curve_parameters: [0, 0] };
+        instruction::initialize_with_options(&self.program_id, payer, mint, &self.page_fee_collector, curve, token_program, metadata, extensions, reserve).unwrap()
+    }
+
+    /// Trade options of the pool's token program
+    fn trade_options(&self) -> TradeOptions {
+        TradeOptions { token_program: self.token_program, ..TradeOptions::default() }
+    }
+
+    fn buy_ix(&self, amount_in: u64, minimum_amount_out: u64) -> Instruction {
+        self.buy_ix_with(amount_in, minimum_amount_out, &self.trade_options())
+    }
+
+    fn buy_ix_with(&self, amount_in: u64, minimum_amount_out: u64, options: &TradeOptions) -> Instruction {
+        instruction::buy_with_options(&self.program_id, &self.payer, &self.mint, &self.page_fee_collector, &self.provider_fee_collector, amount_in, minimum_amount_out, options)
+            .unwrap()
+    }
+
+    fn sell_ix(&self, amount_in: u64, minimum_amount_out: u64) -> Instruction {
+        self.sell_ix_with(amount_in, minimum_amount_out, &self.trade_options())
+    }
+
+    fn sell_ix_with(&self, amount_in: u64, minimum_amount_out: u64, options: &TradeOptions) -> Instruction {
+        instruction::sell_with_options(&self.program_id, &self.payer, &self.mint, &self.provider_fee_collector, amount_in, minimum_amount_out, options).unwrap()
+    }
+
+    fn buy(&mut self, amount_in: u64) {
+        let ix = self.buy_ix(amount_in, 0);
+        self.bank.process(&ix, &[&self.payer]).unwrap();
+    }
+
+    fn sell(&mut self, amount_in: u64) {
+        let ix = self.sell_ix(amount_in, 0);
+        self.bank.process(&ix, &[&self.payer]).unwrap();
+    }
+
+    /// Events logged by the last instruction
+    fn events(&self) -> Vec<VisionEvent> {
+        self.bank.logs.iter().map(|log| VisionEvent::from_log(log).expect("log is an event")).collect()
+    }
+
+    fn swap_state(&self) -> PageTokenSwap {
+        PageTokenSwap::unpack_versioned(&self.bank.account(&self.pool).data).unwrap()
+    }
+
+    /// Supply and reserve balance the processor prices trades with
+    fn curve_state(&self) -> (u64, u64) {
+        let supply = self.bank.mint_supply(&self.mint) + VIRTUAL_SUPPLY;
+        let reserve = self.bank.lamports(&self.pool_sol) - Rent::default().minimum_balance(0);
+        (supply, reserve)
+    }
+}
+
+#[test]
+fn initialize_creates_pool() {
+    let pool = setup();
+    let swap_state = pool.swap_state();
+    assert!(swap_state.is_initialized);
+    assert_eq!(swap_state.version, PageTokenSwap::VERSION);
+    assert_eq!(swap_state.fee, 2500);
+    assert_eq!(swap_state.fee_collector_pubkey, pool.page_fee_collector);
+    assert_eq!(swap_state.reserve_ratio, DEFAULT_RESERVE_RATIO);
+    assert_eq!(pool.bank.account(&pool.pool).owner, pool.program_id);
+    assert_eq!(pool.bank.account(&pool.mint).owner, spl_token::id());
+    assert_eq!(pool.bank.mint_supply(&pool.mint), 0);
+    let mint_state: spl_token::state::Mint = pool.bank.unpack(&pool.mint);
+    assert_eq!(mint_state.mint_authority, COption::Some(pool.pool));
+    assert_eq!(pool.bank.lamports(&pool.pool_sol), Rent::default().minimum_balance(0) + 36);
+
+    let config: ProgramConfig = pool.bank.unpack(&instruction::find_config_address(&pool.program_id).0);
+    assert_eq!(config.admin, pool.admin);
+    assert_eq!(config.provider_fee_collector, pool.provider_fee_collector);
+    assert_eq!(config.provider_fee_bps, 100);
+}
+
+#[test]
+fn buy_moves_lamports_and_mints_tokens() {
+    let mut pool = setup();
+    let (supply, reserve) = pool.curve_state();
+    let expected = Processor::buy_amounts(&pool.swap_state(), 100, supply, reserve, &BuyLimit::ExactIn { amount_in: SOL, minimum_amount_out: 0 }).unwrap();
+    assert_eq!(expected.fee_page, SOL / 40);
+    assert_eq!(expected.fee_provider, SOL / 100);
+
+    let payer_before = pool.bank.lamports(&pool.payer);
+    let pool_sol_before = pool.bank.lamports(&pool.pool_sol);
+    let page_fee_before = pool.bank.lamports(&pool.page_fee_collector);
+    let provider_fee_before = pool.bank.lamports(&pool.provider_fee_collector);
+    pool.buy(SOL);
+
+    assert_eq!(pool.bank.lamports(&pool.payer), payer_before - SOL);
+    assert_eq!(pool.bank.lamports(&pool.pool_sol), pool_sol_before + expected.adjusted_amount_in);
+    assert_eq!(pool.bank.lamports(&pool.page_fee_collector), page_fee_before + expected.fee_page);
+    assert_eq!(pool.bank.lamports(&pool.provider_fee_collector), provider_fee_before + expected.fee_provider);
+    assert_eq!(pool.bank.token_balance(&pool.payer_token), expected.token_amt);
+    assert_eq!(pool.bank.mint_supply(&pool.mint), expected.token_amt);
+}
+
+fn token_metadata(name: &str) -> TokenMetadata {
+    TokenMetadata { name: name.to_string(), symbol: "PAGE".to_string(), uri: "https://example.com/page.json".to_string() }
+}
+
+#[test]
+fn initialize_and_update_metadata() {
+    let mut pool = setup();
+    let mint = pool.bank.new_signer();
+    let (pool_address, _) = instruction::find_pool_address(&pool.program_id, &mint);
+    let (metadata_address, _) = metadata::find_metadata_address(&mint);
+    let ix = pool.initialize_ix(&pool.payer, &mint, &spl_token::id(), Some(token_metadata(&"x".repeat(metadata::MAX_NAME_LENGTH + 1))), None, None);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &mint]), custom_error(VisionError::InvalidInput));
+    let mut ix = pool.initialize_ix(&pool.payer, &mint, &spl_token::id(), Some(token_metadata("Page")), None, None);
+    ix.accounts[9].pubkey = metadata::find_metadata_address(&pool.mint).0;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &mint]), custom_error(VisionError::InvalidAccountAddress));
+
+    // Signed by the pool as mint and update authority
+    let ix = pool.initialize_ix(&pool.payer, &mint, &spl_token::id(), Some(token_metadata("Page")), None, None);
+    pool.bank.process(&ix, &[&pool.payer, &mint]).unwrap();
+    assert_eq!(pool.bank.metadata(&mint), MetadataAccount {
+        key: MetadataAccount::KEY,
+        update_authority: pool_address,
+        mint,
+        name: "Page".to_string(),
+        symbol: "PAGE".to_string(),
+        uri: "https://example.com/page.json".to_string(),
+        seller_fee_basis_points: 0,
+        creators: None,
+        primary_sale_happened: false,
+        is_mutable: true,
+        edition_nonce: None,
+        token_standard: None,
+        collection: None,
+        uses: None,
+    });
+    assert_eq!(pool.bank.account(&metadata_address).owner, metadata::id());
+    assert_eq!(pool.bank.account(&mint).owner, spl_token::id());
+
+    // Only the pool authority updates
+    let other = pool.bank.new_signer();
+    let ix = instruction::update_metadata(&pool.program_id, &other, &mint, token_metadata("Renamed"));
+    assert_eq!(pool.bank.process(&ix, &[&other]), custom_error(VisionError::InvalidAuthority));
+    let mut ix = instruction::update_metadata(&pool.program_id, &pool.page_fee_collector, &mint, token_metadata("Renamed"));
+    ix.accounts[4].pubkey = spl_token::id();
+    assert_eq!(pool.bank.process(&ix, &[&pool.page_fee_collector]), custom_error(VisionError::InvalidProgramAddress));
+
+    let ix = instruction::update_metadata(&pool.program_id, &pool.page_fee_collector, &mint, token_metadata("Renamed"));
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    let metadata = pool.bank.metadata(&mint);
+    assert_eq!((metadata.name.as_str(), metadata.update_authority, metadata.is_mutable), ("Renamed", pool_address, true));
+}
+
+#[test]
+fn buy_creates_token_account() {
+    let mut pool = setup();
+    let buyer = pool.bank.new_signer();
+    pool.bank.airdrop(&buyer, 2 * SOL);
+    let buyer_token = spl_associated_token_account::get_associated_token_address(&buyer, &pool.mint);
+    let token_account_rent = pool.bank.rent_exempt(spl_token::state::Account::LEN);
+
+    // Not enough lamports left for the rent of the token account
+    let ix = instruction::buy(&pool.program_id, &buyer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, 2 * SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&buyer]), custom_error(VisionError::BalanceTooSmall));
+
+    let ix = instruction::buy(&pool.program_id, &buyer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, SOL, 0);
+    pool.bank.process(&ix, &[&buyer]).unwrap();
+    assert_eq!(pool.bank.account(&buyer_token).owner, spl_token::id());
+    assert_eq!(pool.bank.lamports(&buyer_token), token_account_rent);
+    assert_eq!(pool.bank.lamports(&buyer), SOL - token_account_rent);
+    let tokens = pool.bank.token_balance(&buyer_token);
+    assert!(tokens > 0);
+    let token_account: spl_token::state::Account = pool.bank.unpack(&buyer_token);
+    assert_eq!((token_account.owner, token_account.mint), (buyer, pool.mint));
+
+    // The existing account is reused
+    let ix = instruction::buy(&pool.program_id, &buyer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, SOL / 2, 0);
+    pool.bank.process(&ix, &[&buyer]).unwrap();
+    assert!(pool.bank.token_balance(&buyer_token) > tokens);
+    assert_eq!(pool.bank.lamports(&buyer_token), token_account_rent);
+}
+
+#[test]
+fn token_2022_pool_trades() {
+    let mut pool = setup_with_token_program(&token::token_2022::id());
+    assert_eq!(pool.bank.account(&pool.mint).owner, token::token_2022::id());
+    assert_eq!(pool.payer_token, token::get_associated_token_address(&pool.payer, &pool.mint, &token::token_2022::id()));
+
+    pool.buy(SOL);
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    assert!(tokens > 0);
+    assert_eq!(pool.bank.mint_supply(&pool.mint), tokens);
+    let lamports = pool.bank.lamports(&pool.payer);
+    pool.sell(tokens / 2);
+    assert_eq!(pool.bank.token_balance(&pool.payer_token), tokens - tokens / 2);
+    assert!(pool.bank.lamports(&pool.payer) > lamports);
+
+    // spl-token passed for a Token-2022 mint, with either associated token account
+    let mut ix = instruction::buy(&pool.program_id, &pool.payer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountOnwerProgram));
+    ix.accounts[1].pubkey = pool.payer_token;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountOnwerProgram));
+}
+
+#[test]
+fn mint_extensions_need_token_2022() {
+    let mut pool = setup();
+    let mint = pool.bank.new_signer();
+    let extensions = MintExtensions {
+        metadata_pointer: Some(metadata::find_metadata_address(&mint).0),
+        transfer_fee: Some(TransferFee { basis_points: 50, maximum_fee: SOL }),
+    };
+    let curve = Curve { reserve_ratio: DEFAULT_RESERVE_RATIO, curve_type: 0, curve_parameters: [0, 0] };
+    assert_eq!(
+        instruction::initialize_with_options(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, curve, &spl_token::id(), None, Some(extensions.clone()), None),
+        Err(VisionError::InvalidProgramAddress.into())
+    );
+    let mut ix = pool.initialize_ix(&pool.payer, &mint, &token::token_2022::id(), None, Some(extensions), None);
+    ix.accounts[6].pubkey = spl_token::id();
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &mint]), custom_error(VisionError::InvalidProgramAddress));
+
+    // Without extensions the mint is a plain Token-2022 mint
+    let ix = pool.initialize_ix(&pool.payer, &mint, &token::token_2022::id(), None, Some(MintExtensions::default()), None);
+    pool.bank.process(&ix, &[&pool.payer, &mint]).unwrap();
+    assert_eq!(pool.bank.account(&mint).owner, token::token_2022::id());
+    assert_eq!(pool.bank.account(&mint).data.len(), spl_token::state::Mint::LEN);
+}
+
+#[test]
+fn token_2022_mint_extensions() {
+    let mut pool = setup();
+    let mint = pool.bank.new_signer();
+    let (pool_address, _) = instruction::find_pool_address(&pool.program_id, &mint);
+    let (metadata_address, _) = metadata::find_metadata_address(&mint);
+    let extensions = MintExtensions {
+        metadata_pointer: Some(metadata_address),
+        transfer_fee: Some(TransferFee { basis_points: 50, maximum_fee: SOL }),
+    };
+    let ix = pool.initialize_ix(&pool.payer, &mint, &token::token_2022::id(), None, Some(extensions), None);
+    pool.bank.process(&ix, &[&pool.payer, &mint]).unwrap();
+
+    let account = pool.bank.account(&mint);
+    assert_eq!(account.data.len(), token::mint_space(true, true).unwrap());
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&account.data).unwrap();
+    assert_eq!(mint_state.base.mint_authority, COption::Some(pool_address));
+    let transfer_fee_config = mint_state.get_extension::<TransferFeeConfig>().unwrap();
+    assert_eq!(Option::<Pubkey>::from(transfer_fee_config.transfer_fee_config_authority), None);
+    assert_eq!(Option::<Pubkey>::from(transfer_fee_config.withdraw_withheld_authority), Some(pool_address));
+    let transfer_fee = transfer_fee_config.get_epoch_fee(0);
+    assert_eq!((u16::from(transfer_fee.transfer_fee_basis_points), u64::from(transfer_fee.maximum_fee)), (50, SOL));
+    let metadata_pointer = mint_state.get_extension::<MetadataPointer>().unwrap();
+    assert_eq!(Option::<Pubkey>::from(metadata_pointer.metadata_address), Some(metadata_address));
+
+    // Minted and burned by the pool, no transfer fee is withheld
+    let payer_token = pool.bank.create_token_account(&pool.payer, &mint);
+    let options = TradeOptions { token_program: token::token_2022::id(), ..TradeOptions::default() };
+    let buy = instruction::buy_with_options(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, &pool.provider_fee_collector, SOL, 0, &options).unwrap();
+    pool.bank.process(&buy, &[&pool.payer]).unwrap();
+    let tokens = pool.bank.token_balance(&payer_token);
+    assert_eq!(pool.bank.mint_supply(&mint), tokens);
+    let sell = instruction::sell_with_options(&pool.program_id, &pool.payer, &mint, &pool.provider_fee_collector, tokens, 0, &options).unwrap();
+    pool.bank.process(&sell, &[&pool.payer]).unwrap();
+    assert_eq!(pool.bank.mint_supply(&mint), 0);
+    let account = pool.bank.account(&payer_token);
+    let token_state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data).unwrap();
+    assert_eq!(u64::from(token_state.get_extension::<TransferFeeAmount>().unwrap().withheld_amount), 0);
+}
+
+#[test]
+fn withdraw_withheld_fees_to_current_fee_collector() {
+    let mut pool = setup();
+    let mint = pool.bank.new_signer();
+    let extensions = MintExtensions { metadata_pointer: None, transfer_fee: Some(TransferFee { basis_points: 100, maximum_fee: SOL }) };
+    let ix = pool.initialize_ix(&pool.payer, &mint, &token::token_2022::id(), None, Some(extensions), None);
+    pool.bank.process(&ix, &[&pool.payer, &mint]).unwrap();
+    let payer_token = pool.bank.create_token_account(&pool.payer, &mint);
+    let options = TradeOptions { token_program: token::token_2022::id(), ..TradeOptions::default() };
+    let buy = instruction::buy_with_options(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, &pool.provider_fee_collector, SOL, 0, &options).unwrap();
+    pool.bank.process(&buy, &[&pool.payer]).unwrap();
+    let tokens = pool.bank.token_balance(&payer_token);
+
+    // Fees withheld in the mint and in the receiving account
+    let holder = pool.bank.new_signer();
+    let holder_token = pool.bank.create_token_account(&holder, &mint);
+    let withheld = |bank: &Bank| {
+        let account = bank.account(&holder_token);
+        let token_state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data).unwrap();
+        u64::from(token_state.get_extension::<TransferFeeAmount>().unwrap().withheld_amount)
+    };
+    let transfer = token::transfer_checked(&token::token_2022::id(), &payer_token, &mint, &holder_token, &pool.payer, tokens / 2, 9).unwrap();
+    pool.bank.process(&transfer, &[&pool.payer]).unwrap();
+    let harvested = withheld(&pool.bank);
+    assert!(harvested > 0);
+    let harvest = transfer_fee::instruction::harvest_withheld_tokens_to_mint(&token::token_2022::id(), &mint, &[&holder_token]).unwrap();
+    pool.bank.process(&harvest, &[]).unwrap();
+    let transfer = token::transfer_checked(&token::token_2022::id(), &payer_token, &mint, &holder_token, &pool.payer, tokens / 4, 9).unwrap();
+    pool.bank.process(&transfer, &[&pool.payer]).unwrap();
+    let in_account = withheld(&pool.bank);
+
+    // The fees follow a change of the fee collector
+    let new_fee_collector = pool.bank.new_signer();
+    pool.bank.airdrop(&new_fee_collector, pool.bank.rent_exempt(0));
+    let (pool_address, _) = instruction::find_pool_address(&pool.program_id, &mint);
+    let fee = pool.bank.unpack::<PageTokenSwap>(&pool_address).fee;
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &new_fee_collector, &mint, fee);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    pool.bank.create_token_account(&pool.page_fee_collector, &mint);
+    let new_fee_collector_token = pool.bank.create_token_account(&new_fee_collector, &mint);
+    let ix = instruction::withdraw_withheld_fees(&pool.program_id, &mint, &pool.page_fee_collector, &[holder_token]);
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::InvalidAccountAddress));
+
+    // Anyone withdraws, from the mint and the accounts passed
+    let ix = instruction::withdraw_withheld_fees(&pool.program_id, &mint, &new_fee_collector, &[holder_token]);
+    pool.bank.process(&ix, &[]).unwrap();
+    assert_eq!(pool.bank.token_balance(&new_fee_collector_token), harvested + in_account);
+    assert_eq!(withheld(&pool.bank), 0);
+
+    // Pools without a transfer fee have nothing to withdraw
+    let ix = instruction::withdraw_withheld_fees(&pool.program_id, &pool.mint, &pool.page_fee_collector, &[]);
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::InvalidAccountOnwerProgram));
+}
+
+/// Pool of a new spl-token mint with a reserve of tokens of a new `reserve_token_program` mint next to
+/// the SOL pool of `pool`. The payer holds 100 SOL worth of reserve tokens, the fee collectors hold
+/// empty token accounts.
+fn setup_token_reserve(pool: &mut Pool, reserve_token_program: &Pubkey) -> (Pubkey, Pubkey, Pubkey) {
+    let reserve_mint = if *reserve_token_program == spl_token::id() {
+        pool.bank.create_mint(6)
+    } else {
+        pool.bank.create_mint_2022(6, false)
+    };
+    let payer_reserve = pool.bank.create_token_account(&pool.payer, &reserve_mint);
+    pool.bank.set_token_balance(&payer_reserve, 100 * SOL);
+    pool.bank.create_token_account(&pool.page_fee_collector, &reserve_mint);
+    pool.bank.create_token_account(&pool.provider_fee_collector, &reserve_mint);
+
+    let mint = pool.bank.new_signer();
+    let reserve = ReserveAccounts { reserve_mint, reserve_token_program: *reserve_token_program, trader_reserve: payer_reserve };
+    let ix = pool.initialize_ix(&pool.payer, &mint, &spl_token::id(), None, None, Some(reserve));
+    pool.bank.process(&ix, &[&pool.payer, &mint]).unwrap();
+    (mint, reserve_mint, payer_reserve)
+}
+
+#[test]
+fn token_reserve_trades_like_sol_reserve() {
+    assert_token_reserve_trades_like_sol_reserve(&spl_token::id());
+}
+
+#[test]
+fn token_2022_reserve_trades_like_sol_reserve() {
+    assert_token_reserve_trades_like_sol_reserve(&token::token_2022::id());
+}
+
+fn assert_token_reserve_trades_like_sol_reserve(reserve_token_program: &Pubkey) {
+    let mut pool = setup();
+    let (mint, reserve_mint, payer_reserve) = setup_token_reserve(&mut pool, reserve_token_program);
+    let reserve = instruction::find_reserve_address(&pool.program_id, &mint, &reserve_mint, reserve_token_program);
+    let (token_pool, _) = instruction::find_pool_address(&pool.program_id, &mint);
+    let token_pool_sol = instruction::find_pool_sol_address(&pool.program_id, &token_pool).0;
+    let swap_state: PageTokenSwap = pool.bank.unpack(&token_pool);
+    assert_eq!(swap_state.reserve_mint, reserve_mint);
+    assert_eq!(pool.bank.token_balance(&reserve), 36);
+    assert_eq!(pool.bank.lamports(&token_pool_sol), 0);
+    assert_eq!(pool.bank.token_balance(&payer_reserve), 100 * SOL - 36);
+    match VisionEvent::from_log(&pool.bank.logs[0]) {
+        Some(VisionEvent::Initialize(event)) => assert_eq!(event.reserve_mint, reserve_mint),
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    // Same curve and fees as the SOL pool, amounts are in reserve tokens
+    let reserve_accounts = ReserveAccounts { reserve_mint, reserve_token_program: *reserve_token_program, trader_reserve: payer_reserve };
+    let options = TradeOptions { reserve: Some(reserve_accounts), ..TradeOptions::default() };
+    let buy = instruction::buy_with_options(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, &pool.provider_fee_collector, SOL, 0, &options).unwrap();
+    pool.bank.process(&buy, &[&pool.payer]).unwrap();
+    pool.buy(SOL);
+    let payer_token = spl_associated_token_account::get_associated_token_address(&pool.payer, &mint);
+    let tokens = pool.bank.token_balance(&payer_token);
+    assert_eq!(tokens, pool.bank.token_balance(&pool.payer_token));
+    assert_eq!(pool.bank.token_balance(&payer_reserve), 99 * SOL - 36);
+    assert_eq!(pool.bank.token_balance(&reserve), 36 + SOL - SOL / 40 - SOL / 100);
+    let page_fee_reserve = token::get_associated_token_address(&pool.page_fee_collector, &reserve_mint, reserve_token_program);
+    let provider_fee_reserve = token::get_associated_token_address(&pool.provider_fee_collector, &reserve_mint, reserve_token_program);
+    assert_eq!(pool.bank.token_balance(&page_fee_reserve), SOL / 40);
+    assert_eq!(pool.bank.token_balance(&provider_fee_reserve), SOL / 100);
+
+    let token_view = instruction::view_with_options(&pool.program_id, &mint, TradeSide::Sell, tokens / 2, &options).unwrap();
+    let view = instruction::view(&pool.program_id, &pool.mint, TradeSide::Sell, tokens / 2);
+    assert_eq!(pool.bank.process(&token_view, &[]).unwrap(), pool.bank.process(&view, &[]).unwrap());
+
+    let sell = instruction::sell_with_options(&pool.program_id, &pool.payer, &mint, &pool.provider_fee_collector, tokens / 2, 0, &options).unwrap();
+    pool.bank.process(&sell, &[&pool.payer]).unwrap();
+    let lamports = pool.bank.lamports(&pool.payer);
+    pool.sell(tokens / 2);
+    let amount_out = pool.bank.lamports(&pool.payer) - lamports;
+    assert_eq!(pool.bank.token_balance(&payer_reserve), 99 * SOL - 36 + amount_out);
+    assert_eq!(pool.bank.token_balance(&payer_token), tokens - tokens / 2);
+}
+
+#[test]
+fn token_reserve_accounts() {
+    let mut pool = setup();
+    let (mint, reserve_mint, payer_reserve) = setup_token_reserve(&mut pool, &spl_token::id());
+    let buy = instruction::buy(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, &pool.provider_fee_collector, SOL, 0);
+
+    // Reserve accounts missing
+    assert_eq!(pool.bank.process(&buy, &[&pool.payer]), Err(ProgramError::NotEnoughAccountKeys));
+
+    // Page fee paid to the payer
+    let reserve_accounts = ReserveAccounts { reserve_mint, reserve_token_program: spl_token::id(), trader_reserve: payer_reserve };
+    let options = TradeOptions { reserve: Some(reserve_accounts), ..TradeOptions::default() };
+    let buy = instruction::buy_with_options(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, &pool.provider_fee_collector, SOL, 0, &options).unwrap();
+    let mut ix = buy.clone();
+    ix.accounts[13].pubkey = payer_reserve;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountAddress));
+
+    // pda_sol instead of the reserve token account
+    let mut ix = buy.clone();
+    ix.accounts[3].pubkey = instruction::find_pool_sol_address(&pool.program_id, &ix.accounts[2].pubkey).0;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountOnwerProgram));
+
+    // Another reserve mint
+    let mut ix = buy.clone();
+    let reserve_mint_index = ix.accounts.len() - 2;
+    ix.accounts[reserve_mint_index].pubkey = pool.mint;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountAddress));
+
+    // Token program that doesn't own the reserve mint
+    let mut ix = buy;
+    ix.accounts[reserve_mint_index + 1].pubkey = token::token_2022::id();
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountOnwerProgram));
+
+    // More than the payer's reserve tokens
+    let ix = instruction::buy_with_options(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, &pool.provider_fee_collector, 100 * SOL, 0, &options).unwrap();
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::BalanceTooSmall));
+
+    // Collateral not covered by the payer's reserve tokens
+    let other_payer_reserve = pool.bank.create_token_account(&pool.admin, &reserve_mint);
+    let mint = pool.bank.new_signer();
+    let reserve = ReserveAccounts { reserve_mint, reserve_token_program: spl_token::id(), trader_reserve: other_payer_reserve };
+    let ix = pool.initialize_ix(&pool.admin, &mint, &spl_token::id(), None, None, Some(reserve));
+    assert_eq!(pool.bank.process(&ix, &[&pool.admin, &mint]), custom_error(VisionError::BalanceTooSmall));
+
+    // Reserve mint account that isn't the one of the instruction data
+    let mut ix = pool.initialize_ix(&pool.payer, &mint, &spl_token::id(), None, None, Some(ReserveAccounts { trader_reserve: payer_reserve, ..reserve }));
+    ix.accounts[9].pubkey = pool.mint;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &mint]), custom_error(VisionError::InvalidAccountAddress));
+
+    // Transfer fees would be taken from the reserve
+    let fee_mint = pool.bank.create_mint_2022(6, true);
+    let fee_payer_reserve = pool.bank.create_token_account(&pool.admin, &fee_mint);
+    pool.bank.set_token_balance(&fee_payer_reserve, SOL);
+    let reserve = ReserveAccounts { reserve_mint: fee_mint, reserve_token_program: token::token_2022::id(), trader_reserve: fee_payer_reserve };
+    let ix = pool.initialize_ix(&pool.admin, &mint, &spl_token::id(), None, None, Some(reserve));
+    assert_eq!(pool.bank.process(&ix, &[&pool.admin, &mint]), custom_error(VisionError::InvalidMint));
+}
+
+#[test]
+fn initialize_combines_options() {
+    let mut pool = setup();
+    let reserve_mint = pool.bank.create_mint_2022(6, false);
+    let payer_reserve = pool.bank.create_token_account(&pool.payer, &reserve_mint);
+    pool.bank.set_token_balance(&payer_reserve, SOL);
+    let reserve = ReserveAccounts { reserve_mint, reserve_token_program: token::token_2022::id(), trader_reserve: payer_reserve };
+
+    for options in 0..8u8 {
+        let (with_metadata, with_extensions, with_reserve) = (options & 1 != 0, options & 2 != 0, options & 4 != 0);
+        let mint = pool.bank.new_signer();
+        let (pool_address, _) = instruction::find_pool_address(&pool.program_id, &mint);
+        let (metadata_address, _) = metadata::find_metadata_address(&mint);
+        let extensions = MintExtensions {
+            metadata_pointer: Some(metadata_address),
+            transfer_fee: Some(TransferFee { basis_points: 50, maximum_fee: SOL }),
+        };
+        let token_program = if with_extensions { token::token_2022::id() } else { spl_token::id() };
+        let ix = pool.initialize_ix(
+            &pool.payer,
+            &mint,
+            &token_program,
+            if with_metadata { Some(token_metadata("Page")) } else { None },
+            if with_extensions { Some(extensions) } else { None },
+            if with_reserve { Some(reserve) } else { None },
+        );
+        pool.bank.process(&ix, &[&pool.payer, &mint]).unwrap();
+
+        let account = pool.bank.account(&mint);
+        assert_eq!(account.owner, token_program);
+        assert_eq!(account.data.len(), token::mint_space(with_extensions, with_extensions).unwrap());
+        assert_eq!(pool.bank.get_account(&metadata_address).is_some(), with_metadata);
+        if with_metadata {
+            assert_eq!(pool.bank.metadata(&mint).update_authority, pool_address);
+        }
+        let swap_state: PageTokenSwap = pool.bank.unpack(&pool_address);
+        let reserve_address = instruction::find_reserve_address(&pool.program_id, &mint, &reserve_mint, &token::token_2022::id());
+        if with_reserve {
+            assert_eq!(swap_state.reserve_mint, reserve_mint);
+            assert_eq!(pool.bank.token_balance(&reserve_address), 36);
+        } else {
+            assert_eq!(swap_state.reserve_mint, Pubkey::default());
+            assert!(pool.bank.get_account(&reserve_address).is_none());
+        }
+    }
+}
+
+#[test]
+fn buy_exact_out_mints_exact_amount() {
+    let mut pool = setup();
+    pool.buy(SOL);
+    let balance_before = pool.bank.token_balance(&pool.payer_token);
+    let payer_before = pool.bank.lamports(&pool.payer);
+
+    let ix = instruction::buy_exact_out(&pool.program_id, &pool.payer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, 1_000 * SOL, 10 * SOL);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+
+    assert_eq!(pool.bank.token_balance(&pool.payer_token), balance_before + 1_000 * SOL);
+    let spent = payer_before - pool.bank.lamports(&pool.payer);
+    assert!(spent > 0 && spent <= 10 * SOL);
+}
+
+#[test]
+fn sell_returns_lamports_minus_provider_fee() {
+    let mut pool = setup();
+    pool.buy(10 * SOL);
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    let (supply, reserve) = pool.curve_state();
+    let expected = Processor::sell_amounts(&pool.swap_state(), 100, supply, reserve, &SellLimit::ExactIn { amount_in: tokens / 2, minimum_amount_out: 0 }).unwrap();
+
+    let payer_before = pool.bank.lamports(&pool.payer);
+    let pool_sol_before = pool.bank.lamports(&pool.pool_sol);
+    let provider_fee_before = pool.bank.lamports(&pool.provider_fee_collector);
+    let ix = pool.sell_ix(tokens / 2, expected.adjusted_sol_amt);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+
+    assert_eq!(pool.bank.lamports(&pool.payer), payer_before + expected.adjusted_sol_amt);
+    assert_eq!(pool.bank.lamports(&pool.pool_sol), pool_sol_before - expected.sol_amt);
+    assert_eq!(pool.bank.lamports(&pool.provider_fee_collector), provider_fee_before + expected.fee_provider);
+    assert_eq!(pool.bank.token_balance(&pool.payer_token), tokens - tokens / 2);
+    assert_eq!(pool.bank.mint_supply(&pool.mint), tokens - tokens / 2);
+}
+
+#[test]
+fn sell_exact_out_pays_exact_amount() {
+    let mut pool = setup();
+    pool.buy(10 * SOL);
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    let payer_before = pool.bank.lamports(&pool.payer);
+
+    let ix = instruction::sell_exact_out(&pool.program_id, &pool.payer, &pool.mint, &pool.provider_fee_collector, SOL, tokens);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+
+    assert_eq!(pool.bank.lamports(&pool.payer), payer_before + SOL);
+    assert!(pool.bank.token_balance(&pool.payer_token) < tokens);
+}
+
+#[test]
+fn quote_matches_execution() {
+    let mut pool = setup();
+    pool.buy(SOL);
+
+    let ix = instruction::quote(&pool.program_id, &pool.payer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, TradeSide::Buy, SOL, true);
+    let data = pool.bank.process(&ix, &[&pool.payer]).unwrap().expect("quote sets return data");
+    let quote = Quote::unpack_unchecked(&data).unwrap();
+    // Nothing moved
+    assert_eq!(pool.bank.mint_supply(&pool.mint), pool.bank.token_balance(&pool.payer_token));
+
+    let balance_before = pool.bank.token_balance(&pool.payer_token);
+    pool.buy(SOL);
+    assert_eq!(quote.amount_in, SOL);
+    assert_eq!(quote.amount_out, pool.bank.token_balance(&pool.payer_token) - balance_before);
+    assert_eq!(quote.page_fee, SOL / 40);
+    assert_eq!(quote.provider_fee, SOL / 100);
+}
+
+#[test]
+fn change_fee_updates_fee_and_collector() {
+    let mut pool = setup();
+    let new_collector = Pubkey::new_unique();
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &new_collector, &pool.mint, 5000);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+
+    let swap_state = pool.swap_state();
+    assert_eq!((swap_state.fee, swap_state.pending_fee), (2500, 5000));
+    assert_eq!(swap_state.fee_collector_pubkey, new_collector);
+    // The new collector is funded to be rent exempt
+    assert_eq!(pool.bank.lamports(&new_collector), Rent::default().minimum_balance(0));
+
+    pool.page_fee_collector = new_collector;
+    pool.bank.slot = swap_state.pending_fee_slot;
+    pool.buy(SOL);
+    assert_eq!((pool.swap_state().fee, pool.swap_state().pending_fee_slot), (5000, 0));
+    assert_eq!(pool.bank.lamports(&new_collector), Rent::default().minimum_balance(0) + SOL / 20);
+}
+
+#[test]
+fn baseline_pools_trade_and_migrate() {
+    let mut pool = setup();
+    pool.buy(SOL);
+
+    // Pool created by the program before the layout had a version byte
+    let swap_state = pool.swap_state();
+    let mut account = pool.bank.account(&pool.pool);
+    account.data = vec![1, swap_state.bump_seed, swap_state.bump_seed_sol];
+    account.data.extend_from_slice(&swap_state.fee.to_le_bytes());
+    account.data.extend_from_slice(swap_state.fee_collector_pubkey.as_ref());
+    account.lamports = pool.bank.rent_exempt(PageTokenSwap::BASELINE_LEN);
+    pool.bank.set_account(&pool.pool, account);
+
+    // Trades work as before and drop the statistics
+    pool.buy(SOL);
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    pool.sell(tokens / 2);
+    let baseline_state = pool.swap_state();
+    assert_eq!((baseline_state.version, baseline_state.authority), (0, pool.page_fee_collector));
+    assert_eq!(baseline_state.stats(), PoolStats::default());
+
+    // A queued fee doesn't fit until the pool is migrated
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 5000);
+    assert_eq!(pool.bank.process(&ix, &[&pool.page_fee_collector]), custom_error(VisionError::OutdatedPoolLayout));
+
+    let payer_before = pool.bank.lamports(&pool.payer);
+    let ix = instruction::migrate_pool(&pool.program_id, &pool.payer, &pool.mint);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    let account = pool.bank.account(&pool.pool);
+    assert_eq!((account.data.len(), account.lamports), (PageTokenSwap::LEN, pool.bank.rent_exempt(PageTokenSwap::LEN)));
+    let rent = pool.bank.rent_exempt(PageTokenSwap::LEN) - pool.bank.rent_exempt(PageTokenSwap::BASELINE_LEN);
+    assert_eq!(pool.bank.lamports(&pool.payer), payer_before - rent);
+    assert_eq!(pool.swap_state(), PageTokenSwap { version: PageTokenSwap::VERSION, ..baseline_state });
+    let ix = instruction::migrate_pool(&pool.program_id, &pool.payer, &pool.mint);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::AlreadyInUse));
+
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 5000);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    assert_eq!(pool.swap_state().pending_fee, 5000);
+    pool.buy(SOL);
+    assert_eq!(pool.swap_state().trade_count, 1);
+}
+
+#[test]
+fn trades_expire_after_deadline() {
+    let mut pool = setup();
+    pool.bank.slot = 100;
+    pool.bank.unix_timestamp = 1_700_000_000;
+
+    // The deadline itself is still valid
+    let deadline = |deadline| TradeOptions { deadline: Some(deadline), ..TradeOptions::default() };
+    let ix = pool.buy_ix_with(SOL, 0, &deadline(Deadline::Slot(100)));
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    let ix = pool.buy_ix_with(SOL, 0, &deadline(Deadline::UnixTimestamp(1_700_000_000)));
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+
+    let ix = pool.buy_ix_with(SOL, 0, &deadline(Deadline::Slot(99)));
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::DeadlineExceeded));
+    let ix = pool.sell_ix_with(tokens, 0, &deadline(Deadline::UnixTimestamp(1_699_999_999)));
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::DeadlineExceeded));
+    let ix = instruction::buy_exact_out_with_options(&pool.program_id, &pool.payer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, 1, SOL, &deadline(Deadline::Slot(0)));
+    assert_eq!(pool.bank.process(&ix.unwrap(), &[&pool.payer]), custom_error(VisionError::DeadlineExceeded));
+    let ix = instruction::sell_exact_out_with_options(&pool.program_id, &pool.payer, &pool.mint, &pool.provider_fee_collector, 1, tokens, &deadline(Deadline::Slot(0)));
+    assert_eq!(pool.bank.process(&ix.unwrap(), &[&pool.payer]), custom_error(VisionError::DeadlineExceeded));
+
+    let ix = pool.sell_ix_with(tokens, 0, &deadline(Deadline::Slot(101)));
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    assert_eq!(pool.bank.token_balance(&pool.payer_token), 0);
+}
+
+#[test]
+fn update_config_changes_provider_fee() {
+    let mut pool = setup();
+    let new_provider = Pubkey::new_unique();
+    let ix = instruction::update_config(&pool.program_id, &pool.admin, &pool.admin, &new_provider, config(200, PauseState::Active));
+    pool.bank.process(&ix, &[&pool.admin]).unwrap();
+
+    pool.provider_fee_collector = new_provider;
+    pool.buy(SOL);
+    assert_eq!(pool.bank.lamports(&new_provider), SOL / 50);
+}
+
+#[test]
+fn trades_update_pool_stats() {
+    let mut pool = setup();
+    assert_eq!(pool.swap_state().stats(), PoolStats::default());
+
+    pool.bank.slot = 5;
+    pool.buy(SOL);
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    pool.bank.slot = 9;
+    let payer_before = pool.bank.lamports(&pool.payer);
+    let provider_fee_before = pool.bank.lamports(&pool.provider_fee_collector);
+    let ix = pool.sell_ix(tokens / 2, 0);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    let sell_out = pool.bank.lamports(&pool.payer) - payer_before;
+    let sell_provider_fee = pool.bank.lamports(&pool.provider_fee_collector) - provider_fee_before;
+
+    // Quotes and failed trades are not counted
+    let ix = instruction::quote(&pool.program_id, &pool.payer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, TradeSide::Buy, SOL, true);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    let ix = pool.buy_ix(SOL, u64::MAX);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::ExceededSlippage));
+
+    assert_eq!(pool.swap_state().stats(), PoolStats {
+        sol_volume_in: SOL as u128,
+        sol_volume_out: sell_out as u128,
+        page_fees: (SOL / 40) as u128,
+        provider_fees: (SOL / 100 + sell_provider_fee) as u128,
+        trade_count: 2,
+        last_trade_slot: 9,
+    });
+}
+
+#[test]
+fn price_oracle_weights_prices_by_slots() {
+    let mut pool = setup();
+    let initial_price = pool.swap_state().last_price;
+    let start = pool.swap_state().observe(pool.bank.slot);
+    assert_eq!(start, Observation { slot: 1, price_cumulative: 0 });
+
+    pool.bank.slot = 11;
+    pool.buy(SOL);
+    let (supply, reserve) = pool.curve_state();
+    let price_after_buy = pool.swap_state().curve_calculator().unwrap().spot_price(supply, reserve).unwrap();
+    let swap_state = pool.swap_state();
+    assert_eq!((swap_state.price_cumulative, swap_state.price_cumulative_slot, swap_state.last_price), (initial_price * 10, 11, price_after_buy));
+
+    // Observed between trades, the last price keeps accumulating
+    let end = pool.swap_state().observe(41);
+    assert_eq!(end, Observation { slot: 41, price_cumulative: initial_price * 10 + price_after_buy * 30 });
+    assert_eq!(start.twap(&end), Some((initial_price * 10 + price_after_buy * 30) / 40));
+    assert!(initial_price < start.twap(&end).unwrap() && start.twap(&end).unwrap() < price_after_buy);
+    assert_eq!(end.twap(&start), None);
+
+    // A trade within the same slot moves the price without adding to the accumulator
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    let ix = pool.sell_ix(tokens, 0);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    let swap_state = pool.swap_state();
+    assert_eq!(swap_state.price_cumulative, initial_price * 10);
+    assert!(swap_state.last_price < price_after_buy);
+}
+
+impl Pool {
+    fn observations(&self, generation: u8) -> (ObservationBuffer, Vec<PriceSample>) {
+        let (address, _) = instruction::find_observations_address(&self.program_id, &self.pool, generation);
+        let data = &self.bank.account(&address).data;
+        let buffer = ObservationBuffer::unpack(&data[..ObservationBuffer::LEN]).unwrap();
+        let samples = buffer.samples(data).unwrap();
+        (buffer, samples)
+    }
+}
+
+#[test]
+fn observations_sample_trades_and_grow() {
+    let mut pool = setup();
+    let authority = pool.swap_state().authority;
+
+    // Only the pool authority makes the buffer mandatory for trades
+    let other = pool.bank.new_signer();
+    pool.bank.airdrop(&other, SOL);
+    let ix = instruction::initialize_observations(&pool.program_id, &other, &pool.mint, &other, 0, 2);
+    assert_eq!(pool.bank.process(&ix, &[&other]), custom_error(VisionError::InvalidAuthority));
+    let mut ix = instruction::initialize_observations(&pool.program_id, &pool.payer, &pool.mint, &authority, 0, 2);
+    ix.accounts[5].is_signer = false;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAuthority));
+    assert!(!pool.swap_state().has_observations);
+
+    let ix = instruction::initialize_observations(&pool.program_id, &pool.payer, &pool.mint, &authority, 0, 2);
+    pool.bank.process(&ix, &[&pool.payer, &authority]).unwrap();
+    let (observations, _) = instruction::find_observations_address(&pool.program_id, &pool.pool, 0);
+    assert!(pool.swap_state().has_observations);
+    assert_eq!(pool.bank.lamports(&observations), pool.bank.rent_exempt(ObservationBuffer::space(2)));
+    assert_eq!(pool.observations(0).1, vec![]);
+
+    // One sample per slot, the oldest is overwritten once the buffer is full
+    for slot in [10, 20, 20, 30].iter() {
+        pool.bank.slot = *slot;
+        let ix = pool.buy_ix_with(SOL, 0, &TradeOptions { observations: Some(observations), ..pool.trade_options() });
+        pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    }
+    let (supply, reserve) = pool.curve_state();
+    let (buffer, samples) = pool.observations(0);
+    assert_eq!((buffer.capacity, buffer.len), (2, 2));
+    assert_eq!(samples.iter().map(|sample| sample.slot).collect::<Vec<_>>(), vec![20, 30]);
+    assert_eq!(samples[1], PriceSample {
+        slot: 30,
+        price_cumulative: pool.swap_state().price_cumulative,
+        supply: supply - VIRTUAL_SUPPLY,
+        reserve_balance: reserve,
+    });
+
+    // Trades can't skip the buffer once it exists
+    pool.bank.slot = 35;
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), Err(ProgramError::NotEnoughAccountKeys));
+    let ix = pool.sell_ix(1, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), Err(ProgramError::NotEnoughAccountKeys));
+
+    // Only the pool authority grows the buffer, the rent of the current buffer goes to it, not to the payer
+    let ix = instruction::grow_observations(&pool.program_id, &pool.payer, &pool.mint, &pool.payer, 0, 4);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAuthority));
+    let mut ix = instruction::grow_observations(&pool.program_id, &pool.payer, &pool.mint, &authority, 0, 4);
+    ix.accounts[6].is_signer = false;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAuthority));
+    let payer_before = pool.bank.lamports(&pool.payer);
+    let authority_before = pool.bank.lamports(&authority);
+    let ix = instruction::grow_observations(&pool.program_id, &pool.payer, &pool.mint, &authority, 0, 4);
+    pool.bank.process(&ix, &[&pool.payer, &authority]).unwrap();
+    let (new_observations, _) = instruction::find_observations_address(&pool.program_id, &pool.pool, 1);
+    assert_eq!(pool.swap_state().observations_generation, 1);
+    assert_eq!(pool.bank.lamports(&observations), 0);
+    assert_eq!(pool.bank.lamports(&pool.payer), payer_before - pool.bank.rent_exempt(ObservationBuffer::space(4)));
+    assert_eq!(pool.bank.lamports(&authority), authority_before + pool.bank.rent_exempt(ObservationBuffer::space(2)));
+    let (buffer, grown_samples) = pool.observations(1);
+    assert_eq!((buffer.capacity, buffer.generation), (4, 1));
+    assert_eq!(grown_samples, samples);
+
+    // The closed buffer can't be written anymore
+    let ix = pool.buy_ix_with(SOL, 0, &TradeOptions { observations: Some(observations), ..pool.trade_options() });
+    assert!(pool.bank.process(&ix, &[&pool.payer]).is_err());
+
+    pool.bank.slot = 40;
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    let ix = pool.sell_ix_with(tokens / 2, 0, &TradeOptions { observations: Some(new_observations), ..pool.trade_options() });
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    let (_, samples) = pool.observations(1);
+    assert_eq!(samples.iter().map(|sample| sample.slot).collect::<Vec<_>>(), vec![20, 30, 40]);
+
+    // The window matches the pool's accumulator
+    let swap_state = pool.swap_state();
+    let expected = Observation { slot: 20, price_cumulative: samples[0].price_cumulative }.twap(&swap_state.observe(50));
+    assert_eq!(oracle::twap(&samples, &swap_state, 20, 50), expected);
+    assert_eq!(oracle::twap(&samples, &swap_state, 10, 50), None);
+
+    let ix = instruction::grow_observations(&pool.program_id, &pool.payer, &pool.mint, &authority, 1, 4);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &authority]), custom_error(VisionError::InvalidInput));
+    let ix = instruction::grow_observations(&pool.program_id, &pool.payer, &pool.mint, &authority, 1, ObservationBuffer::MAX_CAPACITY + 1);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &authority]), custom_error(VisionError::InvalidInput));
+    // The pool's buffer is at generation 1
+    let ix = instruction::initialize_observations(&pool.program_id, &pool.payer, &pool.mint, &authority, 0, 2);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &authority]), custom_error(VisionError::InvalidAccountAddress));
+    let ix = instruction::initialize_observations(&pool.program_id, &pool.payer, &pool.mint, &authority, 1, 2);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &authority]), custom_error(VisionError::AlreadyInUse));
+}
+
+#[test]
+fn observations_generation_wraps_around() {
+    let mut pool = setup();
+    let mut swap_state = pool.swap_state();
+    swap_state.observations_generation = u8::MAX;
+    pool.bank.pack(&pool.pool, swap_state);
+
+    let ix = instruction::initialize_observations(&pool.program_id, &pool.payer, &pool.mint, &swap_state.authority, u8::MAX, 2);
+    pool.bank.process(&ix, &[&pool.payer, &swap_state.authority]).unwrap();
+    let ix = instruction::grow_observations(&pool.program_id, &pool.payer, &pool.mint, &swap_state.authority, u8::MAX, 3);
+    pool.bank.process(&ix, &[&pool.payer, &swap_state.authority]).unwrap();
+    assert_eq!(pool.swap_state().observations_generation, 0);
+    let (buffer, _) = pool.observations(0);
+    assert_eq!((buffer.capacity, buffer.generation), (3, 0));
+}
+
+#[test]
+fn view_matches_off_chain_math() {
+    let mut pool = setup();
+    pool.buy(10 * SOL);
+    let tokens = pool.bank.mint_supply(&pool.mint);
+    let (_, reserve) = pool.curve_state();
+    let swap_state = pool.swap_state();
+
+    let ix = instruction::view(&pool.program_id, &pool.mint, TradeSide::Buy, 0);
+    let data = pool.bank.process(&ix, &[]).unwrap().expect("view sets return data");
+    let view = PoolView::unpack_unchecked(&data).unwrap();
+    assert_eq!((view.supply, view.reserve_balance), (tokens, reserve));
+    assert_eq!(view.spot_price, swap_state.spot_price(tokens, reserve).unwrap());
+    assert_eq!(view.market_cap, swap_state.market_cap(tokens, reserve).unwrap());
+    assert_eq!(view.fully_diluted_value, swap_state.fully_diluted_value(tokens, reserve).unwrap());
+    assert_eq!(view.price_after, view.spot_price);
+    assert!(view.market_cap > 0 && view.market_cap < view.fully_diluted_value);
+
+    // Buying raises the price, selling lowers it
+    let ix = instruction::view(&pool.program_id, &pool.mint, TradeSide::Buy, SOL);
+    let buy_view = PoolView::unpack_unchecked(&pool.bank.process(&ix, &[]).unwrap().unwrap()).unwrap();
+    assert!(buy_view.price_after > view.spot_price);
+    let ix = instruction::view(&pool.program_id, &pool.mint, TradeSide::Sell, tokens);
+    let sell_view = PoolView::unpack_unchecked(&pool.bank.process(&ix, &[]).unwrap().unwrap()).unwrap();
+    assert!(sell_view.price_after < view.spot_price);
+
+    assert_eq!(buy_view.price_after, swap_state.price_after_buy(tokens, reserve, SOL).unwrap());
+    assert_eq!(sell_view.price_after, swap_state.price_after_sell(tokens, reserve, tokens).unwrap());
+
+    // More tokens than were minted can't be sold
+    let ix = instruction::view(&pool.program_id, &pool.mint, TradeSide::Sell, tokens + 1);
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::InvalidInput));
+}
+
+#[test]
+fn instructions_emit_events() {
+    let mut pool = setup();
+    let initialize = VisionEvent::from_log(&pool.bank.logs[0]).expect("initialize event");
+    match initialize {
+        VisionEvent::Initialize(event) => {
+            assert_eq!((event.mint, event.payer, event.fee_collector), (pool.mint, pool.payer, pool.page_fee_collector));
+            assert_eq!((event.fee, event.curve_type, event.reserve_ratio), (2500, 0, DEFAULT_RESERVE_RATIO));
+            assert_eq!(event.reserve_balance, 36);
+            assert_eq!(event.reserve_mint, Pubkey::default());
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    pool.buy(SOL);
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    let (supply, reserve) = pool.curve_state();
+    let spot_price = pool.swap_state().curve_calculator().unwrap().spot_price(supply, reserve).unwrap();
+    assert_eq!(pool.events(), vec![VisionEvent::Buy(TradeEvent {
+        mint: pool.mint,
+        trader: pool.payer,
+        amount_in: SOL,
+        amount_out: tokens,
+        page_fee: SOL / 40,
+        provider_fee: SOL / 100,
+        supply: tokens,
+        reserve_balance: reserve,
+        spot_price,
+    })]);
+
+    let payer_before = pool.bank.lamports(&pool.payer);
+    let ix = pool.sell_ix(tokens, 0);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    let (_, reserve) = pool.curve_state();
+    match &pool.events()[..] {
+        [VisionEvent::Sell(event)] => {
+            assert_eq!((event.amount_in, event.amount_out), (tokens, pool.bank.lamports(&pool.payer) - payer_before));
+            assert_eq!((event.supply, event.reserve_balance, event.page_fee), (0, reserve, 0));
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 5000);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    let pending_fee_slot = pool.bank.slot + PageTokenSwap::FEE_CHANGE_DELAY_SLOTS;
+    assert_eq!(pool.events(), vec![VisionEvent::ChangeFee(ChangeFeeEvent { mint: pool.mint, fee_collector: pool.page_fee_collector, fee: 2500, pending_fee: 5000, pending_fee_slot })]);
+
+    // Quotes change nothing and log nothing
+    let ix = instruction::quote(&pool.program_id, &pool.payer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, TradeSide::Buy, SOL, true);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+    assert!(pool.events().is_empty());
+}
+
+// Error paths
+
+#[test]
+fn missing_signature() {
+    let mut pool = setup();
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::SignatureRequired));
+
+    let mut pool = setup();
+    pool.buy(SOL);
+    let ix = pool.sell_ix(1, 0);
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::SignatureRequired));
+
+    let mint = Pubkey::new_unique();
+    let ix = instruction::initialize(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, DEFAULT_RESERVE_RATIO, 0, [0, 0]);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::SignatureRequired));
+}
+
+#[test]
+fn initialize_twice_fails() {
+    let mut pool = setup();
+    let ix = instruction::initialize(&pool.program_id, &pool.payer, &pool.mint, &pool.page_fee_collector, DEFAULT_RESERVE_RATIO, 0, [0, 0]);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &pool.mint]), custom_error(VisionError::AlreadyInUse));
+
+    let ix = instruction::initialize_config(&pool.program_id, &pool.admin, &pool.provider_fee_collector, config(100, PauseState::Active));
+    assert_eq!(pool.bank.process(&ix, &[&pool.admin]), custom_error(VisionError::AlreadyInUse));
+}
+
+#[test]
+fn initialize_config_needs_upgrade_authority() {
+    let program_id = bpf_program_template::id();
+    let mut bank = Bank::new(program_id);
+    let admin = bank.new_signer();
+    let attacker = bank.new_signer();
+    let provider_fee_collector = Pubkey::new_unique();
+    bank.airdrop(&admin, SOL);
+    bank.airdrop(&attacker, SOL);
+    bank.set_upgrade_authority(Some(admin));
+
+    let ix = instruction::initialize_config(&program_id, &attacker, &provider_fee_collector, config(100, PauseState::Active));
+    assert_eq!(bank.process(&ix, &[&attacker]), custom_error(VisionError::InvalidAdmin));
+
+    // ProgramData of another program
+    let mut ix = instruction::initialize_config(&program_id, &admin, &provider_fee_collector, config(100, PauseState::Active));
+    ix.accounts[4].pubkey = instruction::find_program_data_address(&spl_token::id()).0;
+    assert_eq!(bank.process(&ix, &[&admin]), custom_error(VisionError::InvalidAccountAddress));
+
+    // Immutable programs have no upgrade authority
+    bank.set_upgrade_authority(None);
+    let ix = instruction::initialize_config(&program_id, &admin, &provider_fee_collector, config(100, PauseState::Active));
+    assert_eq!(bank.process(&ix, &[&admin]), custom_error(VisionError::InvalidAdmin));
+
+    bank.set_upgrade_authority(Some(admin));
+    bank.process(&ix, &[&admin]).unwrap();
+    let (config_address, _) = instruction::find_config_address(&program_id);
+    assert_eq!(bank.unpack::<ProgramConfig>(&config_address).admin, admin);
+}
+
+#[test]
+fn wrong_accounts() {
+    let mut pool = setup();
+
+    // Page fee collector not saved in the pool
+    let mut ix = pool.buy_ix(SOL, 0);
+    ix.accounts[5].pubkey = pool.provider_fee_collector;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountAddress));
+
+    // Provider fee collector not saved in the config
+    let mut ix = pool.buy_ix(SOL, 0);
+    ix.accounts[6].pubkey = pool.page_fee_collector;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountAddress));
+
+    // Funded sol PDA of another pool
+    let other_pool_sol = instruction::find_pool_sol_address(&pool.program_id, &Pubkey::new_unique()).0;
+    pool.bank.airdrop(&other_pool_sol, SOL);
+    let mut ix = pool.buy_ix(SOL, 0);
+    ix.accounts[3].pubkey = other_pool_sol;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountAddress));
+
+    // Token account of someone else
+    let other = Pubkey::new_unique();
+    let other_token = pool.bank.create_token_account(&other, &pool.mint);
+    let mut ix = pool.buy_ix(SOL, 0);
+    ix.accounts[1].pubkey = other_token;
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountAddress));
+
+    // System program replaced
+    let mut ix = pool.buy_ix(SOL, 0);
+    ix.accounts[7].pubkey = spl_token::id();
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidProgramAddress));
+
+    // Associated token account program replaced
+    let mut ix = pool.buy_ix(SOL, 0);
+    ix.accounts[10].pubkey = Pubkey::new_unique();
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidProgramAddress));
+
+    // Token program replaced
+    let mut ix = pool.sell_ix(0, 0);
+    ix.accounts[7].pubkey = Pubkey::new_unique();
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidProgramAddress));
+}
+
+#[test]
+fn wrong_owner() {
+    let mut pool = setup();
+    let mut account = pool.bank.account(&pool.page_fee_collector);
+    account.owner = spl_token::id();
+    pool.bank.set_account(&pool.page_fee_collector, account);
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountOnwerProgram));
+
+    let mut pool = setup();
+    let mut account = pool.bank.account(&pool.payer_token);
+    account.owner = Pubkey::new_unique();
+    pool.bank.set_account(&pool.payer_token, account);
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountOnwerProgram));
+
+    // Config not owned by the program
+    let mut pool = setup();
+    let config_address = instruction::find_config_address(&pool.program_id).0;
+    let mut account = pool.bank.account(&config_address);
+    account.owner = Pubkey::new_unique();
+    pool.bank.set_account(&config_address, account);
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidAccountOnwerProgram));
+}
+
+#[test]
+fn mint_authority_not_pool() {
+    let mut pool = setup();
+    let mut mint_state: spl_token::state::Mint = pool.bank.unpack(&pool.mint);
+    mint_state.mint_authority = COption::Some(Pubkey::new_unique());
+    pool.bank.pack(&pool.mint, mint_state);
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidMint));
+}
+
+#[test]
+fn slippage() {
+    let mut pool = setup();
+    let ix = pool.buy_ix(SOL, u64::MAX);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::ExceededSlippage));
+
+    pool.buy(SOL);
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    let ix = pool.sell_ix(tokens, SOL);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::ExceededSlippage));
+
+    let ix = instruction::buy_exact_out(&pool.program_id, &pool.payer, &pool.mint, &pool.page_fee_collector, &pool.provider_fee_collector, tokens, 1);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::ExceededSlippage));
+
+    let ix = instruction::sell_exact_out(&pool.program_id, &pool.payer, &pool.mint, &pool.provider_fee_collector, SOL / 2, 1);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::ExceededSlippage));
+}
+
+#[test]
+fn balance_too_small() {
+    let mut pool = setup();
+    let ix = pool.buy_ix(1_000 * SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::BalanceTooSmall));
+
+    pool.buy(SOL);
+    let tokens = pool.bank.token_balance(&pool.payer_token);
+    let ix = pool.sell_ix(tokens + 1, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::BalanceTooSmall));
+}
+
+#[test]
+fn sell_below_collateral() {
+    let mut pool = setup();
+    pool.buy(SOL);
+    // Tokens that are not backed by the reserve
+    let tokens = 1_000 * pool.bank.token_balance(&pool.payer_token);
+    let mut token_state: spl_token::state::Account = pool.bank.unpack(&pool.payer_token);
+    token_state.amount = tokens;
+    pool.bank.pack(&pool.payer_token, token_state);
+    let mut mint_state: spl_token::state::Mint = pool.bank.unpack(&pool.mint);
+    mint_state.supply = tokens;
+    pool.bank.pack(&pool.mint, mint_state);
+
+    let ix = pool.sell_ix(tokens, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::ReserveError));
+}
+
+#[test]
+fn invalid_curve() {
+    let mut pool = setup();
+    let mint = pool.bank.new_signer();
+    let ix = instruction::initialize(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, 5, 0, [0, 0]);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &mint]), custom_error(VisionError::InvalidReserveRatio));
+
+    let ix = instruction::initialize(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, DEFAULT_RESERVE_RATIO, 99, [0, 0]);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &mint]), custom_error(VisionError::InvalidCurve));
+
+    // Linear curve without a price
+    let ix = instruction::initialize(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, DEFAULT_RESERVE_RATIO, 1, [0, 0]);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer, &mint]), custom_error(VisionError::InvalidCurve));
+}
+
+#[test]
+fn invalid_instruction() {
+    let mut pool = setup();
+    let mut ix = pool.buy_ix(SOL, 0);
+    ix.data = vec![99];
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidInstruction));
+    ix.data = vec![1, 0];
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::InvalidInstruction));
+}
+
+#[test]
+fn overflow() {
+    let mut pool = setup();
+    // Constant product pool, at most 1000 tokens can be minted
+    let mint = pool.bank.new_signer();
+    let ix = instruction::initialize(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, DEFAULT_RESERVE_RATIO, 3, [SOL, 1_000 * SOL]);
+    pool.bank.process(&ix, &[&pool.payer, &mint]).unwrap();
+    pool.bank.create_token_account(&pool.payer, &mint);
+
+    let ix = instruction::buy_exact_out(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, &pool.provider_fee_collector, 1_000 * SOL, u64::MAX);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::Overflow));
+    let ix = instruction::buy_exact_out(&pool.program_id, &pool.payer, &mint, &pool.page_fee_collector, &pool.provider_fee_collector, 10 * SOL, u64::MAX);
+    pool.bank.process(&ix, &[&pool.payer]).unwrap();
+}
+
+#[test]
+fn authority_transfer() {
+    let mut pool = setup();
+    assert_eq!(pool.swap_state().authority, pool.page_fee_collector);
+    let new_authority = pool.bank.new_signer();
+    pool.bank.airdrop(&new_authority, SOL);
+
+    // Only the authority proposes, only the pending authority accepts
+    let ix = instruction::propose_authority(&pool.program_id, &new_authority, &pool.mint, &new_authority);
+    assert_eq!(pool.bank.process(&ix, &[&new_authority]), custom_error(VisionError::InvalidAuthority));
+    let ix = instruction::accept_authority(&pool.program_id, &new_authority, &pool.mint);
+    assert_eq!(pool.bank.process(&ix, &[&new_authority]), custom_error(VisionError::InvalidAuthority));
+
+    let ix = instruction::propose_authority(&pool.program_id, &pool.page_fee_collector, &pool.mint, &new_authority);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    let swap_state = pool.swap_state();
+    assert_eq!((swap_state.authority, swap_state.pending_authority), (pool.page_fee_collector, new_authority));
+    assert_eq!(pool.events(), vec![VisionEvent::SetAuthority(AuthorityEvent { mint: pool.mint, authority: pool.page_fee_collector, pending_authority: new_authority })]);
+
+    let other = pool.bank.new_signer();
+    let ix = instruction::accept_authority(&pool.program_id, &other, &pool.mint);
+    assert_eq!(pool.bank.process(&ix, &[&other]), custom_error(VisionError::InvalidAuthority));
+    let mut ix = instruction::accept_authority(&pool.program_id, &new_authority, &pool.mint);
+    ix.accounts[0].is_signer = false;
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::InvalidAuthority));
+
+    let ix = instruction::accept_authority(&pool.program_id, &new_authority, &pool.mint);
+    pool.bank.process(&ix, &[&new_authority]).unwrap();
+    let swap_state = pool.swap_state();
+    assert_eq!((swap_state.authority, swap_state.pending_authority), (new_authority, Pubkey::default()));
+    assert_eq!(swap_state.fee_collector_pubkey, pool.page_fee_collector);
+    assert_eq!(pool.events(), vec![VisionEvent::SetAuthority(AuthorityEvent { mint: pool.mint, authority: new_authority, pending_authority: Pubkey::default() })]);
+
+    // The fee collector keeps receiving fees but can't change the fee anymore
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 5000);
+    assert_eq!(pool.bank.process(&ix, &[&pool.page_fee_collector]), custom_error(VisionError::InvalidAuthority));
+    let ix = instruction::change_fee(&pool.program_id, &new_authority, &pool.page_fee_collector, &pool.mint, 5000);
+    pool.bank.process(&ix, &[&new_authority]).unwrap();
+    assert_eq!(pool.swap_state().pending_fee, 5000);
+
+    // A proposal can be cancelled
+    let ix = instruction::propose_authority(&pool.program_id, &new_authority, &pool.mint, &other);
+    pool.bank.process(&ix, &[&new_authority]).unwrap();
+    let ix = instruction::propose_authority(&pool.program_id, &new_authority, &pool.mint, &Pubkey::default());
+    pool.bank.process(&ix, &[&new_authority]).unwrap();
+    let ix = instruction::accept_authority(&pool.program_id, &other, &pool.mint);
+    assert_eq!(pool.bank.process(&ix, &[&other]), custom_error(VisionError::InvalidAuthority));
+}
+
+#[test]
+fn fee_changes_apply_after_delay() {
+    let mut pool = setup();
+    let delay = PageTokenSwap::FEE_CHANGE_DELAY_SLOTS;
+    pool.bank.slot = 100;
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 5000);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+
+    // Buyers pay the old fee until the change applies
+    let fees = pool.bank.lamports(&pool.page_fee_collector);
+    pool.bank.slot = 99 + delay;
+    pool.buy(SOL);
+    assert_eq!(pool.bank.lamports(&pool.page_fee_collector) - fees, SOL / 40);
+
+    let fees = pool.bank.lamports(&pool.page_fee_collector);
+    pool.bank.slot = 100 + delay;
+    pool.buy(SOL);
+    assert_eq!(pool.bank.lamports(&pool.page_fee_collector) - fees, SOL / 20);
+    let swap_state = pool.swap_state();
+    assert_eq!((swap_state.fee, swap_state.pending_fee, swap_state.pending_fee_slot), (5000, 0, 0));
+
+    // At most MAX_FEE_STEP per change
+    let fee = 5000 + PageTokenSwap::MAX_FEE_STEP + 1;
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, fee);
+    assert_eq!(pool.bank.process(&ix, &[&pool.page_fee_collector]), custom_error(VisionError::FeeStepExceeded));
+
+    // The current fee cancels a queued change
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 7500);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 5000);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    pool.bank.slot += 2 * delay;
+    pool.buy(SOL);
+    assert_eq!(pool.swap_state().fee, 5000);
+}
+
+#[test]
+fn change_fee_errors() {
+    let mut pool = setup();
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 50001);
+    assert_eq!(pool.bank.process(&ix, &[&pool.page_fee_collector]), custom_error(VisionError::InvalidFee));
+
+    // Outside of the config bounds
+    let ix = instruction::update_config(&pool.program_id, &pool.admin, &pool.admin, &pool.provider_fee_collector, Config { provider_fee_bps: 100, min_page_fee: 1000, max_page_fee: 3000, pause_state: PauseState::Active });
+    pool.bank.process(&ix, &[&pool.admin]).unwrap();
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 500);
+    assert_eq!(pool.bank.process(&ix, &[&pool.page_fee_collector]), custom_error(VisionError::InvalidFee));
+
+    let ix = instruction::change_fee(&pool.program_id, &pool.page_fee_collector, &pool.page_fee_collector, &pool.mint, 2000);
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::InvalidAuthority));
+
+    let other = pool.bank.new_signer();
+    pool.bank.airdrop(&other, SOL);
+    let ix = instruction::change_fee(&pool.program_id, &other, &other, &pool.mint, 2000);
+    assert_eq!(pool.bank.process(&ix, &[&other]), custom_error(VisionError::InvalidAuthority));
+}
+
+#[test]
+fn config_errors() {
+    let mut pool = setup();
+    for invalid in [
+        Config { provider_fee_bps: 1001, min_page_fee: 0, max_page_fee: 50000, pause_state: PauseState::Active },
+        Config { provider_fee_bps: 100, min_page_fee: 3000, max_page_fee: 2000, pause_state: PauseState::Active },
+        Config { provider_fee_bps: 100, min_page_fee: 0, max_page_fee: 50001, pause_state: PauseState::Active },
+    ]
+    .iter()
+    {
+        let ix = instruction::update_config(&pool.program_id, &pool.admin, &pool.admin, &pool.provider_fee_collector, invalid.clone());
+        assert_eq!(pool.bank.process(&ix, &[&pool.admin]), custom_error(VisionError::InvalidConfig));
+    }
+
+    let other = pool.bank.new_signer();
+    let ix = instruction::update_config(&pool.program_id, &other, &other, &other, config(0, PauseState::Active));
+    assert_eq!(pool.bank.process(&ix, &[&other]), custom_error(VisionError::InvalidAdmin));
+    let ix = instruction::update_config(&pool.program_id, &pool.admin, &other, &other, config(0, PauseState::Active));
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::InvalidAdmin));
+}
+
+#[test]
+fn paused() {
+    let mut pool = setup();
+    pool.buy(SOL);
+    let ix = instruction::update_config(&pool.program_id, &pool.admin, &pool.admin, &pool.provider_fee_collector, config(100, PauseState::Paused));
+    pool.bank.process(&ix, &[&pool.admin]).unwrap();
+
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::Paused));
+    let ix = pool.sell_ix(1, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::Paused));
+
+    let ix = instruction::update_config(&pool.program_id, &pool.admin, &pool.admin, &pool.provider_fee_collector, config(100, PauseState::WithdrawOnly));
+    pool.bank.process(&ix, &[&pool.admin]).unwrap();
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::Paused));
+    pool.sell(SOL / 10);
+
+    let ix = instruction::update_config(&pool.program_id, &pool.admin, &pool.admin, &pool.provider_fee_collector, config(100, PauseState::Active));
+    pool.bank.process(&ix, &[&pool.admin]).unwrap();
+    pool.buy(SOL);
+}
+
+#[test]
+fn pool_paused_by_authority() {
+    let mut pool = setup();
+    pool.buy(SOL);
+    assert_eq!(pool.swap_state().pause_state, PauseState::Active);
+
+    // Only the pool authority, the fee collector until transferred
+    let other = pool.bank.new_signer();
+    let ix = instruction::set_pause_state(&pool.program_id, &other, &pool.mint, PauseState::Paused);
+    assert_eq!(pool.bank.process(&ix, &[&other]), custom_error(VisionError::InvalidAuthority));
+    let mut ix = instruction::set_pause_state(&pool.program_id, &pool.page_fee_collector, &pool.mint, PauseState::Paused);
+    ix.accounts[0].is_signer = false;
+    assert_eq!(pool.bank.process(&ix, &[]), custom_error(VisionError::InvalidAuthority));
+
+    let ix = instruction::set_pause_state(&pool.program_id, &pool.page_fee_collector, &pool.mint, PauseState::Paused);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    assert_eq!(pool.swap_state().pause_state, PauseState::Paused);
+    assert_eq!(pool.events(), vec![VisionEvent::SetPauseState(PauseStateEvent { mint: pool.mint, pause_state: PauseState::Paused })]);
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::Paused));
+    let ix = pool.sell_ix(1, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::Paused));
+
+    // Withdraw only
+    let ix = instruction::set_pause_state(&pool.program_id, &pool.page_fee_collector, &pool.mint, PauseState::WithdrawOnly);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    let ix = pool.buy_ix(SOL, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::Paused));
+    pool.sell(SOL / 10);
+
+    // A global withdraw only pause doesn't resume a paused pool
+    let ix = instruction::set_pause_state(&pool.program_id, &pool.page_fee_collector, &pool.mint, PauseState::Paused);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    let ix = instruction::update_config(&pool.program_id, &pool.admin, &pool.admin, &pool.provider_fee_collector, config(100, PauseState::WithdrawOnly));
+    pool.bank.process(&ix, &[&pool.admin]).unwrap();
+    let ix = pool.sell_ix(1, 0);
+    assert_eq!(pool.bank.process(&ix, &[&pool.payer]), custom_error(VisionError::Paused));
+
+    let ix = instruction::set_pause_state(&pool.program_id, &pool.page_fee_collector, &pool.mint, PauseState::Active);
+    pool.bank.process(&ix, &[&pool.page_fee_collector]).unwrap();
+    pool.sell(SOL / 10);
+}